@@ -22,7 +22,28 @@ CREATE TABLE IF NOT EXISTS api_entry
     url_params VARCHAR,
     headers    VARCHAR,
     body       VARCHAR,
-    updated    DATETIME DEFAULT CURRENT_TIMESTAMP
+    updated    DATETIME DEFAULT CURRENT_TIMESTAMP,
+    collection VARCHAR  DEFAULT '',
+    mock_enabled     INTEGER DEFAULT 0,
+    mock_status      INTEGER DEFAULT 200,
+    mock_headers     VARCHAR DEFAULT '',
+    mock_body        VARCHAR DEFAULT '',
+    mock_delay_ms    INTEGER DEFAULT 0,
+    mock_query_match VARCHAR DEFAULT '',
+    mock_body_match  VARCHAR DEFAULT ''
+);
+
+CREATE TABLE IF NOT EXISTS api_mock_hit
+(
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    api_entry_id INTEGER,
+    method       VARCHAR,
+    path         VARCHAR,
+    query        VARCHAR,
+    headers      VARCHAR,
+    body         VARCHAR,
+    status       INTEGER,
+    create_time  INTEGER
 );
 
 
@@ -46,7 +67,71 @@ CREATE TABLE IF NOT EXISTS email_inbox
     html_content  VARCHAR,
     full_body     VARCHAR,
     attachments   VARCHAR,
-    create_time   INTEGER
+    create_time   INTEGER,
+    is_read       INTEGER NOT NULL DEFAULT 0,
+    cc_mail       VARCHAR NOT NULL DEFAULT '',
+    envelope_from VARCHAR NOT NULL DEFAULT '',
+    envelope_to   VARCHAR NOT NULL DEFAULT '',
+    tags          VARCHAR NOT NULL DEFAULT '',
+    mailbox       VARCHAR NOT NULL DEFAULT '',
+    spf_result    VARCHAR NOT NULL DEFAULT '',
+    dkim_result   VARCHAR NOT NULL DEFAULT '',
+    dmarc_result  VARCHAR NOT NULL DEFAULT '',
+    auth_details  VARCHAR NOT NULL DEFAULT '',
+    size          INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS email_attachment
+(
+    id           INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    email_id     INTEGER,
+    filename     VARCHAR,
+    content_type VARCHAR,
+    size         INTEGER,
+    cid          VARCHAR,
+    file_path    VARCHAR,
+    create_time  INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS mail_outbox
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    from_mail       VARCHAR NOT NULL DEFAULT '',
+    to_mail         VARCHAR NOT NULL DEFAULT '',
+    subject         VARCHAR NOT NULL DEFAULT '',
+    mail_json       TEXT    NOT NULL,
+    status          VARCHAR NOT NULL DEFAULT 'queued',
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at INTEGER NOT NULL DEFAULT 0,
+    last_error      VARCHAR NOT NULL DEFAULT '',
+    create_time     INTEGER NOT NULL DEFAULT 0,
+    update_time     INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS mail_rule
+(
+    id                    INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name                  VARCHAR NOT NULL DEFAULT '',
+    enabled               INTEGER NOT NULL DEFAULT 1,
+    priority              INTEGER NOT NULL DEFAULT 0,
+    match_from            VARCHAR NOT NULL DEFAULT '',
+    match_to              VARCHAR NOT NULL DEFAULT '',
+    match_subject         VARCHAR NOT NULL DEFAULT '',
+    match_attachment_type VARCHAR NOT NULL DEFAULT '',
+    action                VARCHAR NOT NULL DEFAULT '',
+    action_arg            TEXT    NOT NULL DEFAULT '',
+    create_time           INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS mail_rule_hit
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    rule_id     INTEGER NOT NULL,
+    email_id    INTEGER NOT NULL DEFAULT 0,
+    from_mail   VARCHAR NOT NULL DEFAULT '',
+    subject     VARCHAR NOT NULL DEFAULT '',
+    result      VARCHAR NOT NULL DEFAULT '',
+    create_time INTEGER NOT NULL DEFAULT 0
 );
 
 insert into email_inbox(from_mail, to_mail, send_date, subject, plain_content, html_content, full_body, attachments, create_time)
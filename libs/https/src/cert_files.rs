@@ -0,0 +1,224 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use log::{info, warn};
+use rustls_acme::futures_rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs1KeyDer, PrivatePkcs8KeyDer, PrivateSec1KeyDer};
+use rustls_acme::futures_rustls::rustls::crypto::ring::sign::any_supported_type;
+use rustls_acme::futures_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use rustls_acme::futures_rustls::rustls::sign::CertifiedKey;
+
+use crate::status::StatusFile;
+
+/// how often the files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// the https and smtp servers ask for the self-signed pair at boot, only one may write it.
+static GENERATING: Mutex<()> = Mutex::new(());
+
+///
+/// a pem cert chain and key read from disk, swapped in when the files change.
+#[derive(Debug)]
+pub struct CertFiles {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    /// the pem contents the current key was built from.
+    current: RwLock<(Vec<u8>, Vec<u8>, Arc<CertifiedKey>)>,
+    status: Option<Arc<StatusFile>>,
+}
+
+impl CertFiles {
+    pub fn load(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> io::Result<Self> {
+        let (cert_path, key_path) = (cert_path.as_ref().to_path_buf(), key_path.as_ref().to_path_buf());
+        let (cert, key) = (fs::read(&cert_path)?, fs::read(&key_path)?);
+        let certified = certified_key(&cert, &key)?;
+        Ok(CertFiles { cert_path, key_path, current: RwLock::new((cert, key, certified)), status: None })
+    }
+
+    /// keeps the validity of the served certificate and failed reloads in the status file.
+    pub(crate) fn report_to(mut self, status: Arc<StatusFile>) -> Self {
+        status.served(&self.certified_key().cert[0], false);
+        self.status = Some(status);
+        self
+    }
+
+    /// reads the files again, `true` when they changed. unreadable files keep the old certificate.
+    pub fn reload(&self) -> io::Result<bool> {
+        let (cert, key) = (fs::read(&self.cert_path)?, fs::read(&self.key_path)?);
+        {
+            let current = self.current.read().unwrap();
+            if current.0 == cert && current.1 == key {
+                return Ok(false);
+            }
+        }
+        let certified = certified_key(&cert, &key)?;
+        if let Some(status) = &self.status {
+            status.served(&certified.cert[0], false);
+        }
+        *self.current.write().unwrap() = (cert, key, certified);
+        Ok(true)
+    }
+
+    pub fn certified_key(&self) -> Arc<CertifiedKey> {
+        self.current.read().unwrap().2.clone()
+    }
+
+    /// checks the files in the background for as long as the returned resolver lives.
+    pub fn watch(self) -> Arc<Self> {
+        let files = Arc::new(self);
+        let weak = Arc::downgrade(&files);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(files) = weak.upgrade() else { break };
+                match files.reload() {
+                    Ok(true) => info!("reloaded certificate {:?}", files.cert_path),
+                    Ok(false) => {}
+                    Err(e) => {
+                        warn!("keeping the old certificate, reloading {:?} failed : {}", files.cert_path, e);
+                        if let Some(status) = &files.status {
+                            status.failed(format!("reloading failed : {}", e));
+                        }
+                    }
+                }
+            }
+        });
+        files
+    }
+}
+
+impl ResolvesServerCert for CertFiles {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.certified_key())
+    }
+}
+
+pub(crate) fn certified_key(cert: &[u8], key: &[u8]) -> io::Result<Arc<CertifiedKey>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+    let chain: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut &cert[..])?
+        .into_iter().map(CertificateDer::from).collect();
+    if chain.is_empty() {
+        return Err(invalid("no certificate in the cert file"));
+    }
+    let key = rustls_pemfile::read_all(&mut &key[..])?.into_iter().find_map(|item| match item {
+        rustls_pemfile::Item::PKCS8Key(k) => Some(PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(k))),
+        rustls_pemfile::Item::RSAKey(k) => Some(PrivateKeyDer::Pkcs1(PrivatePkcs1KeyDer::from(k))),
+        rustls_pemfile::Item::ECKey(k) => Some(PrivateKeyDer::Sec1(PrivateSec1KeyDer::from(k))),
+        _ => None,
+    }).ok_or_else(|| invalid("no private key in the key file"))?;
+    let key = any_supported_type(&key).map_err(|e| invalid(&e.to_string()))?;
+    Ok(Arc::new(CertifiedKey::new(chain, key)))
+}
+
+///
+/// a certificate for the domains, localhost and 127.0.0.1, generated once into `dir`
+/// so browsers only have to trust it a single time. returns the cert and key paths.
+pub fn self_signed(dir: impl AsRef<Path>, domains: &[String]) -> io::Result<(PathBuf, PathBuf)> {
+    let dir = dir.as_ref();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let _generating = GENERATING.lock().unwrap_or_else(|e| e.into_inner());
+    if cert_path.exists() && key_path.exists() {
+        return Ok((cert_path, key_path));
+    }
+
+    let mut names = domains.to_vec();
+    names.push("localhost".to_string());
+    let mut params = rcgen::CertificateParams::new(names);
+    params.subject_alt_names.push(rcgen::SanType::IpAddress([127, 0, 0, 1].into()));
+    let cert = rcgen::Certificate::from_params(params)
+        .and_then(|c| Ok((c.serialize_pem()?, c.serialize_private_key_pem())))
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    fs::create_dir_all(dir)?;
+    fs::write(&cert_path, cert.0)?;
+    write_private(&key_path, cert.1.as_bytes())?;
+    info!("generated a self-signed certificate at {:?}", cert_path);
+    Ok((cert_path, key_path))
+}
+
+/// for keys : only the owner can read it, whatever the umask.
+pub fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // a file written before keeps its old mode otherwise.
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("https-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_self_signed_is_generated_once() -> io::Result<()> {
+        let dir = temp_dir("self-signed");
+        let (cert_path, key_path) = self_signed(&dir, &["play.local".to_string()])?;
+        let first = fs::read(&cert_path)?;
+        assert_eq!(self_signed(&dir, &[])?, (cert_path.clone(), key_path.clone()));
+        assert_eq!(fs::read(&cert_path)?, first);
+
+        let files = CertFiles::load(&cert_path, &key_path)?;
+        assert_eq!(files.certified_key().cert.len(), 1);
+        fs::remove_dir_all(&dir)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private() -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("private");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("key.pem");
+        fs::write(&path, "old")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+
+        write_private(&path, b"key")?;
+        assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&path)?, b"key");
+        fs::remove_dir_all(&dir)
+    }
+
+    #[test]
+    fn test_reload_on_change() -> io::Result<()> {
+        let (a, b) = (temp_dir("reload-a"), temp_dir("reload-b"));
+        let (cert_path, key_path) = self_signed(&a, &[])?;
+        let files = CertFiles::load(&cert_path, &key_path)?;
+        let old = files.certified_key();
+        assert!(!files.reload()?);
+
+        // a half written file keeps serving the old certificate.
+        fs::write(&cert_path, "-----BEGIN CERTIFICATE-----\n")?;
+        assert!(files.reload().is_err());
+        assert_eq!(files.certified_key().cert, old.cert);
+
+        let (other_cert, other_key) = self_signed(&b, &[])?;
+        fs::copy(&other_cert, &cert_path)?;
+        fs::copy(&other_key, &key_path)?;
+        assert!(files.reload()?);
+        assert_ne!(files.certified_key().cert, old.cert);
+        assert_eq!(files.certified_key().cert[0].as_ref(), rustls_pemfile::certs(&mut &fs::read(&other_cert)?[..])?[0].as_slice());
+
+        fs::remove_dir_all(&a)?;
+        fs::remove_dir_all(&b)
+    }
+}
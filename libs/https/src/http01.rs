@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{BoxError, Router};
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::get;
+use base64::URL_SAFE_NO_PAD;
+use log::{error, info};
+use ring::digest::{digest, SHA256};
+use ring::signature::KeyPair;
+use rustls_acme::acme::{Account, AuthStatus, ChallengeType, Directory, Identifier, OrderStatus};
+use rustls_acme::futures_rustls::pki_types::TrustAnchor;
+use rustls_acme::futures_rustls::rustls::{ClientConfig, RootCertStore};
+use rustls_acme::futures_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use rustls_acme::futures_rustls::rustls::sign::CertifiedKey;
+
+use crate::cert_files;
+use crate::status::{now, renew_at, StatusFile};
+
+/// the issued key and chain in one pem, found by `load_cached_certificate` like the tls-alpn ones.
+const CERT_FILE: &str = "cached_cert_http-01";
+const ACCOUNT_FILE: &str = "http-01-account.key";
+
+/// key authorizations of the pending challenges by token.
+#[derive(Clone, Default)]
+pub struct Http01Tokens(Arc<RwLock<HashMap<String, String>>>);
+
+impl Http01Tokens {
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.0.read().unwrap().get(token).cloned()
+    }
+
+    /// answers `/.well-known/acme-challenge/<token>`, to be merged into the http server.
+    pub fn router(&self) -> Router {
+        let tokens = self.clone();
+        Router::new().route("/.well-known/acme-challenge/:token", get(move |Path(token): Path<String>| async move {
+            tokens.get(&token).ok_or(StatusCode::NOT_FOUND)
+        }))
+    }
+}
+
+///
+/// orders certificates over http-01 and serves them, for hosts where port 443 is
+/// behind something that does not pass tls-alpn through.
+pub struct Http01Issuer {
+    pub(crate) directory_url: String,
+    pub(crate) domains: Vec<String>,
+    pub(crate) contact: Vec<String>,
+    pub(crate) cache_dir: PathBuf,
+    pub(crate) tokens: Http01Tokens,
+    pub(crate) status: Arc<StatusFile>,
+    cert: RwLock<Option<Arc<CertifiedKey>>>,
+}
+
+impl std::fmt::Debug for Http01Issuer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Http01Issuer").field("domains", &self.domains).finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for Http01Issuer {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.cert.read().unwrap().clone()
+    }
+}
+
+impl Http01Issuer {
+    pub(crate) fn new(directory_url: String, domains: Vec<String>, contact: Vec<String>, cache_dir: PathBuf, tokens: Http01Tokens, status: Arc<StatusFile>) -> Self {
+        let issuer = Http01Issuer { directory_url, domains, contact, cache_dir, tokens, status, cert: RwLock::new(None) };
+        match std::fs::read(issuer.cache_dir.join(CERT_FILE)) {
+            Ok(pem) => if let Err(e) = issuer.deploy(&pem) {
+                error!("ignoring the cached http-01 certificate : {}", e);
+            },
+            Err(_) => info!("no cached http-01 certificate yet"),
+        }
+        issuer
+    }
+
+    /// keeps the certificate fresh, retrying failed orders with a growing backoff.
+    pub(crate) fn start(self: &Arc<Self>) {
+        let issuer = self.clone();
+        tokio::spawn(async move {
+            let mut backoff = 0;
+            loop {
+                let renew_at = issuer.status_renew_at();
+                if renew_at > now() {
+                    tokio::time::sleep(Duration::from_secs((renew_at - now()) as u64)).await;
+                    continue;
+                }
+                match issuer.order().await.and_then(|pem| issuer.store(&pem)) {
+                    Ok(()) => backoff = 0,
+                    Err(e) => {
+                        error!("http-01 order failed : {}", e);
+                        issuer.status.failed(e);
+                        tokio::time::sleep(Duration::from_secs(1 << backoff)).await;
+                        backoff = (backoff + 1).min(12);
+                    }
+                }
+            }
+        });
+    }
+
+    fn status_renew_at(&self) -> i64 {
+        match self.cert.read().unwrap().as_ref().and_then(|c| crate::status::validity(&c.cert[0])) {
+            Some((not_before, not_after)) => renew_at(not_before, not_after),
+            None => 0,
+        }
+    }
+
+    fn deploy(&self, pem: &[u8]) -> Result<(), BoxError> {
+        let cert = crate::split_pem(&String::from_utf8_lossy(pem)).ok_or("expected a key and a certificate")?;
+        let key = cert_files::certified_key(cert.cert_chain.as_bytes(), cert.private_key.as_bytes())?;
+        self.status.served(&key.cert[0], true);
+        *self.cert.write().unwrap() = Some(key);
+        Ok(())
+    }
+
+    fn store(&self, pem: &[u8]) -> Result<(), BoxError> {
+        self.deploy(pem)?;
+        std::fs::create_dir_all(&self.cache_dir)?;
+        cert_files::write_private(&self.cache_dir.join(CERT_FILE), pem)?;
+        info!("deployed a new http-01 certificate for {:?}", self.domains);
+        Ok(())
+    }
+
+    fn account_key(&self) -> Result<Vec<u8>, BoxError> {
+        let path = self.cache_dir.join(ACCOUNT_FILE);
+        if path.exists() {
+            return Ok(std::fs::read(path)?);
+        }
+        let key = Account::generate_key_pair();
+        std::fs::create_dir_all(&self.cache_dir)?;
+        cert_files::write_private(&path, &key)?;
+        Ok(key)
+    }
+
+    /// one full order, returns the new key and chain as pem.
+    pub(crate) async fn order(&self) -> Result<Vec<u8>, BoxError> {
+        self.status.update(|s| s.state = if s.not_after == 0 { "pending" } else { "renewing" }.to_string());
+        let client = client_config();
+        let directory = Directory::discover(&client, &self.directory_url).await?;
+        let account = Account::create_with_keypair(&client, directory, &self.contact, &self.account_key()?).await?;
+
+        let mut params = rcgen::CertificateParams::new(self.domains.clone());
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+        let cert = rcgen::Certificate::from_params(params)?;
+
+        let (order_url, mut order) = account.new_order(&client, self.domains.clone()).await?;
+        for attempt in 0..10 {
+            match order.status {
+                OrderStatus::Pending => {
+                    for url in &order.authorizations {
+                        self.authorize(&client, &account, url).await?;
+                    }
+                }
+                OrderStatus::Ready => {
+                    order = account.finalize(&client, &order.finalize, cert.serialize_request_der()?).await?;
+                    continue;
+                }
+                OrderStatus::Valid { certificate } => {
+                    let chain = account.certificate(&client, certificate).await?;
+                    return Ok([cert.serialize_private_key_pem(), "\n".to_string(), chain].concat().into_bytes());
+                }
+                OrderStatus::Processing => tokio::time::sleep(Duration::from_secs(1 << attempt.min(5))).await,
+                OrderStatus::Invalid => return Err(format!("order is invalid : {:?}", order.error).into()),
+            }
+            order = account.order(&client, &order_url).await?;
+        }
+        Err("order did not complete in time".into())
+    }
+
+    async fn authorize(&self, client: &Arc<ClientConfig>, account: &Account, url: &str) -> Result<(), BoxError> {
+        let auth = account.auth(client, url).await?;
+        let Identifier::Dns(domain) = &auth.identifier;
+        let challenge = match auth.status {
+            AuthStatus::Valid => return Ok(()),
+            AuthStatus::Pending => auth.challenges.iter().find(|c| c.typ == ChallengeType::Http01)
+                .ok_or_else(|| format!("no http-01 challenge for {}", domain))?,
+            status => return Err(format!("authorization for {} is {:?}", domain, status).into()),
+        };
+
+        info!("answering the http-01 challenge for {}", domain);
+        let token = challenge.token.clone();
+        self.tokens.0.write().unwrap().insert(token.clone(), key_authorization(account, &token));
+        let r = self.confirm(client, account, url, &challenge.url).await;
+        self.tokens.0.write().unwrap().remove(&token);
+        r.map_err(|e| format!("{} : {}", domain, e).into())
+    }
+
+    async fn confirm(&self, client: &Arc<ClientConfig>, account: &Account, url: &str, challenge_url: &str) -> Result<(), BoxError> {
+        account.challenge(client, challenge_url).await?;
+        for attempt in 0..5 {
+            tokio::time::sleep(Duration::from_millis(250 << attempt)).await;
+            let auth = account.auth(client, url).await?;
+            match auth.status {
+                AuthStatus::Valid => return Ok(()),
+                AuthStatus::Pending => {}
+                status => {
+                    let problem = auth.challenges.iter().find_map(|c| c.error.as_ref()).and_then(|p| p.detail.clone());
+                    return Err(format!("authorization is {:?} {}", status, problem.unwrap_or_default()).into());
+                }
+            }
+        }
+        Err("authorization still pending".into())
+    }
+}
+
+/// `<token>.<base64url sha256 of the account jwk>` (rfc 8555 8.1).
+fn key_authorization(account: &Account, token: &str) -> String {
+    let (x, y) = account.key_pair.public_key().as_ref()[1..].split_at(32);
+    let jwk = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+                      base64::encode_config(x, URL_SAFE_NO_PAD), base64::encode_config(y, URL_SAFE_NO_PAD));
+    format!("{}.{}", token, base64::encode_config(digest(&SHA256, jwk.as_bytes()), URL_SAFE_NO_PAD))
+}
+
+fn client_config() -> Arc<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| TrustAnchor {
+        subject: ta.subject.into(),
+        subject_public_key_info: ta.spki.into(),
+        name_constraints: ta.name_constraints.map(Into::into),
+    }));
+    Arc::new(ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::extract::State;
+    use axum::http::HeaderMap;
+    use axum::Json;
+    use axum::response::IntoResponse;
+    use axum::routing::post;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use x509_parser::prelude::FromDer;
+
+    use crate::status::{CertStatus, load_status};
+
+    use super::*;
+
+    const TOKEN: &str = "tok3n";
+
+    /// a tiny acme server over plain http, checks the challenge on `http_port` of 127.0.0.1
+    /// instead of resolving the domain, and signs the csr with its own ca.
+    struct StandIn {
+        base: String,
+        http_port: u16,
+        ca: rcgen::Certificate,
+        authorized: Mutex<bool>,
+        issued: Mutex<Option<String>>,
+    }
+
+    type S = State<Arc<StandIn>>;
+
+    struct CsrKey(Vec<u8>);
+
+    impl rcgen::RemoteKeyPair for CsrKey {
+        fn public_key(&self) -> &[u8] {
+            &self.0
+        }
+        fn sign(&self, _msg: &[u8]) -> Result<Vec<u8>, rcgen::RcgenError> {
+            unreachable!("the ca signs")
+        }
+        fn algorithm(&self) -> &'static rcgen::SignatureAlgorithm {
+            &rcgen::PKCS_ECDSA_P256_SHA256
+        }
+    }
+
+    fn payload(body: &str) -> Value {
+        let body: Value = serde_json::from_str(body).unwrap();
+        let payload = base64::decode_config(body["payload"].as_str().unwrap(), URL_SAFE_NO_PAD).unwrap();
+        serde_json::from_slice(&payload).unwrap_or(Value::Null)
+    }
+
+    fn order_json(s: &StandIn) -> Value {
+        let status = match (s.issued.lock().unwrap().is_some(), *s.authorized.lock().unwrap()) {
+            (true, _) => json!({"status": "valid", "certificate": format!("{}/cert", s.base)}),
+            (false, true) => json!({"status": "ready"}),
+            (false, false) => json!({"status": "pending"}),
+        };
+        let mut order = json!({"authorizations": [format!("{}/authz", s.base)], "finalize": format!("{}/finalize", s.base)});
+        order.as_object_mut().unwrap().extend(status.as_object().unwrap().clone());
+        order
+    }
+
+    fn located(s: &StandIn, path: &str, body: Value) -> impl IntoResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert("location", format!("{}{}", s.base, path).parse().unwrap());
+        (StatusCode::CREATED, headers, Json(body))
+    }
+
+    async fn fetch_token(port: u16) -> String {
+        let Ok(mut tcp) = tokio::net::TcpStream::connect(("127.0.0.1", port)).await else { return String::new() };
+        let request = format!("GET /.well-known/acme-challenge/{} HTTP/1.1\r\nhost: x.test\r\nconnection: close\r\n\r\n", TOKEN);
+        tcp.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        tcp.read_to_string(&mut response).await.unwrap();
+        response.split("\r\n\r\n").nth(1).unwrap_or_default().to_string()
+    }
+
+    async fn start_stand_in(http_port: u16) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let mut ca = rcgen::CertificateParams::new(vec![]);
+        ca.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let state = Arc::new(StandIn {
+            base: base.clone(),
+            http_port,
+            ca: rcgen::Certificate::from_params(ca).unwrap(),
+            authorized: Mutex::new(false),
+            issued: Mutex::new(None),
+        });
+
+        let app = Router::new()
+            .route("/directory", get(|s: S| async move {
+                Json(json!({"newNonce": format!("{}/nonce", s.base), "newAccount": format!("{}/account", s.base), "newOrder": format!("{}/order", s.base)}))
+            }))
+            .route("/nonce", get(|| async { ([("replay-nonce", "n0nce")], "") }))
+            .route("/account", post(|s: S| async move { located(&s, "/account/1", json!({"status": "valid"})) }))
+            .route("/order", post(|s: S, body: String| async move {
+                assert_eq!(payload(&body)["identifiers"][0]["value"], "x.test");
+                located(&s, "/order/1", order_json(&s))
+            }))
+            .route("/order/1", post(|s: S| async move { Json(order_json(&s)) }))
+            .route("/authz", post(|s: S| async move {
+                let status = if *s.authorized.lock().unwrap() { "valid" } else { "pending" };
+                Json(json!({"status": status, "identifier": {"type": "dns", "value": "x.test"},
+                    "challenges": [{"type": "tls-alpn-01", "url": format!("{}/nope", s.base), "token": "x"},
+                                   {"type": "http-01", "url": format!("{}/challenge", s.base), "token": TOKEN}]}))
+            }))
+            .route("/challenge", post(|s: S| async move {
+                let answer = fetch_token(s.http_port).await;
+                *s.authorized.lock().unwrap() = answer.starts_with(&format!("{}.", TOKEN));
+                Json(json!({}))
+            }))
+            .route("/finalize", post(|s: S, body: String| async move {
+                let csr = base64::decode_config(payload(&body)["csr"].as_str().unwrap(), URL_SAFE_NO_PAD).unwrap();
+                let (_, csr) = x509_parser::certification_request::X509CertificationRequest::from_der(&csr).unwrap();
+                let key = CsrKey(csr.certification_request_info.subject_pki.subject_public_key.data.to_vec());
+                let mut params = rcgen::CertificateParams::new(vec!["x.test".to_string()]);
+                params.alg = &rcgen::PKCS_ECDSA_P256_SHA256;
+                params.key_pair = Some(rcgen::KeyPair::from_remote(Box::new(key)).unwrap());
+                let cert = rcgen::Certificate::from_params(params).unwrap();
+                *s.issued.lock().unwrap() = Some(cert.serialize_pem_with_signer(&s.ca).unwrap());
+                Json(order_json(&s))
+            }))
+            .route("/cert", post(|s: S| async move { s.issued.lock().unwrap().clone().unwrap() }))
+            .with_state(state);
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        format!("{}/directory", base)
+    }
+
+    fn issuer(name: &str, directory_url: String, tokens: Http01Tokens) -> Http01Issuer {
+        let cache_dir = std::env::temp_dir().join(format!("https-http01-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let status = Arc::new(StatusFile::new(&cache_dir, CertStatus { mode: "acme".to_string(), ..Default::default() }));
+        Http01Issuer::new(directory_url, vec!["x.test".to_string()], vec![], cache_dir, tokens, status)
+    }
+
+    #[tokio::test]
+    async fn test_order_from_stand_in() -> Result<(), BoxError> {
+        let tokens = Http01Tokens::default();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let http_port = listener.local_addr()?.port();
+        tokio::spawn(axum::Server::from_tcp(listener)?.serve(tokens.router().into_make_service()));
+
+        let issuer = issuer("ok", start_stand_in(http_port).await, tokens.clone());
+        let pem = issuer.order().await?;
+        issuer.store(&pem)?;
+        assert_eq!(tokens.get(TOKEN), None);
+
+        let cached = crate::load_cached_certificate(&issuer.cache_dir.to_string_lossy())?.unwrap();
+        assert!(cached.private_key.contains("PRIVATE KEY"));
+        let served = issuer.cert.read().unwrap().clone().unwrap();
+        let (_, cert) = x509_parser::parse_x509_certificate(&served.cert[0])?;
+        assert_eq!(cert.subject_alternative_name()?.unwrap().value.general_names.len(), 1);
+
+        let status = load_status(&issuer.cache_dir)?.unwrap();
+        assert_eq!(status.state, "valid");
+        assert!(status.renew_at > now());
+
+        // a restart picks the cached certificate up again.
+        let again = Http01Issuer::new(issuer.directory_url.clone(), issuer.domains.clone(), vec![], issuer.cache_dir.clone(), tokens, issuer.status.clone());
+        assert_eq!(again.cert.read().unwrap().as_ref().unwrap().cert, served.cert);
+        std::fs::remove_dir_all(&issuer.cache_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_challenge() -> Result<(), BoxError> {
+        // nothing listens on the http port.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let issuer = issuer("unanswered", start_stand_in(port).await, Http01Tokens::default());
+        let error = issuer.order().await.unwrap_err();
+        assert!(error.to_string().starts_with("x.test : authorization still pending"), "{}", error);
+        assert!(issuer.cert.read().unwrap().is_none());
+        std::fs::remove_dir_all(&issuer.cache_dir)?;
+        Ok(())
+    }
+}
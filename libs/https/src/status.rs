@@ -0,0 +1,140 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use rustls_acme::futures_rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+
+const STATUS_FILE: &str = "status.json";
+/// a certificate this close to expiry counts as unhealthy, renewals start long before.
+const EXPIRY_MARGIN_SECS: i64 = 7 * 24 * 3600;
+
+///
+/// what the https server is serving, kept in `cache_dir/status.json` so it
+/// can be shown outside of the server (admin page, health checks).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct CertStatus {
+    /// `acme`, `static` or `self_signed`.
+    pub mode: String,
+    /// the acme challenge, empty for the other modes.
+    pub challenge: String,
+    pub directory: String,
+    pub domains: Vec<String>,
+    /// `pending` until there is a certificate, then `valid` or `failed` after each attempt.
+    pub state: String,
+    /// validity of the served certificate in unix seconds, 0 without one.
+    pub not_before: i64,
+    pub not_after: i64,
+    /// when acme orders a new one.
+    pub renew_at: i64,
+    pub last_error: String,
+    pub updated_at: i64,
+}
+
+impl CertStatus {
+    /// false without a certificate or when it expires within a week. a failed renewal
+    /// alone is only reported in `last_error`, the served certificate is still good.
+    pub fn is_healthy(&self, now: i64) -> bool {
+        self.not_after > now + EXPIRY_MARGIN_SECS
+    }
+}
+
+pub fn load_status(cache_dir: impl AsRef<Path>) -> io::Result<Option<CertStatus>> {
+    let path = cache_dir.as_ref().join(STATUS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let status = serde_json::from_slice(&fs::read(path)?)?;
+    Ok(Some(status))
+}
+
+#[derive(Debug)]
+pub(crate) struct StatusFile {
+    path: PathBuf,
+    status: Mutex<CertStatus>,
+}
+
+impl StatusFile {
+    pub(crate) fn new(cache_dir: impl AsRef<Path>, status: CertStatus) -> Self {
+        let file = StatusFile { path: cache_dir.as_ref().join(STATUS_FILE), status: Mutex::new(status) };
+        file.update(|_| {});
+        file
+    }
+
+    /// changes the status and writes it out, a failed write is only logged.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut CertStatus)) {
+        let mut status = self.status.lock().unwrap();
+        f(&mut status);
+        status.updated_at = now();
+        let r = fs::create_dir_all(self.path.parent().unwrap())
+            .and_then(|_| fs::write(&self.path, serde_json::to_vec_pretty(&*status)?));
+        if let Err(e) = r {
+            warn!("writing {:?} failed : {}", self.path, e);
+        }
+    }
+
+    pub(crate) fn served(&self, cert: &CertificateDer, renew: bool) {
+        let validity = validity(cert);
+        self.update(|s| {
+            s.state = "valid".to_string();
+            s.last_error.clear();
+            (s.not_before, s.not_after) = validity.unwrap_or_default();
+            s.renew_at = if renew { renew_at(s.not_before, s.not_after) } else { 0 };
+        });
+    }
+
+    pub(crate) fn failed(&self, error: impl ToString) {
+        self.update(|s| {
+            s.state = "failed".to_string();
+            s.last_error = error.to_string();
+        });
+    }
+}
+
+pub(crate) fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+/// not before and not after of a der certificate, in unix seconds.
+pub(crate) fn validity(cert: &[u8]) -> Option<(i64, i64)> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    Some((cert.validity().not_before.timestamp(), cert.validity().not_after.timestamp()))
+}
+
+/// a third of the lifetime before expiry, the same as rustls-acme.
+pub(crate) fn renew_at(not_before: i64, not_after: i64) -> i64 {
+    not_after - (not_after - not_before) / 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_file() -> io::Result<()> {
+        let dir = std::env::temp_dir().join(format!("https-status-{}", std::process::id()));
+        assert_eq!(load_status(&dir)?, None);
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let file = StatusFile::new(&dir, CertStatus { mode: "acme".to_string(), state: "pending".to_string(), ..Default::default() });
+        file.served(&CertificateDer::from(cert.serialize_der().unwrap()), true);
+        let status = load_status(&dir)?.unwrap();
+        assert_eq!(status.state, "valid");
+        assert!(status.not_before < status.renew_at && status.renew_at < status.not_after);
+        assert!(status.is_healthy(now()));
+        assert!(!status.is_healthy(status.not_after - 3600));
+        assert!(!status.is_healthy(status.not_after + 1));
+
+        // a failed renewal keeps serving the old certificate.
+        file.failed("rate limited");
+        let status = load_status(&dir)?.unwrap();
+        assert_eq!((status.state.as_str(), status.last_error.as_str()), ("failed", "rate limited"));
+        assert!(status.is_healthy(now()));
+        assert!(!CertStatus { state: "failed".to_string(), ..Default::default() }.is_healthy(now()));
+        fs::remove_dir_all(&dir)
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use axum::body::Body;
+use axum::extract::Host;
+use axum::http::Request;
+use axum::http::uri::Authority;
+use axum::Router;
+use tower::ServiceExt;
+
+///
+/// one router per host name, e.g. an api domain and a site domain served by the same port.
+/// hosts are matched without the port and case insensitive, the rest goes to the default router.
+#[derive(Default)]
+pub struct VirtualHosts {
+    hosts: HashMap<String, Router>,
+}
+
+impl VirtualHosts {
+    pub fn host(mut self, domain: &str, router: Router) -> Self {
+        self.hosts.insert(domain.to_lowercase(), router);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    pub fn into_router(self, default: Router) -> Router {
+        let hosts = self.hosts;
+        Router::new().fallback(move |host: Option<Host>, request: Request<Body>| {
+            let router = host.and_then(|Host(h)| hosts.get(&host_name(&h))).unwrap_or(&default).clone();
+            async move {
+                let response: Result<_, Infallible> = router.oneshot(request).await;
+                response.unwrap_or_else(|e| match e {})
+            }
+        })
+    }
+}
+
+/// `Example.com:8443` -> `example.com`.
+pub(crate) fn host_name(host: &str) -> String {
+    match host.parse::<Authority>() {
+        Ok(authority) => authority.host().to_lowercase(),
+        Err(_) => host.to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+
+    async fn get_text(router: &Router, host: Option<&str>, path: &str) -> (u16, String) {
+        let mut request = Request::get(path);
+        if let Some(host) = host {
+            request = request.header("host", host);
+        }
+        let response = router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let status = response.status().as_u16();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_host() {
+        let router = VirtualHosts::default()
+            .host("api.example.com", Router::new().route("/v1", get(|| async { "api" })))
+            .host("WWW.example.com", Router::new().fallback(|| async { "site" }))
+            .into_router(Router::new().route("/v1", get(|| async { "default" })));
+
+        assert_eq!(get_text(&router, Some("api.example.com"), "/v1").await, (200, "api".to_string()));
+        assert_eq!(get_text(&router, Some("API.example.com:8443"), "/v1").await, (200, "api".to_string()));
+        assert_eq!(get_text(&router, Some("api.example.com"), "/index.html").await.0, 404);
+        assert_eq!(get_text(&router, Some("www.example.com"), "/v1").await, (200, "site".to_string()));
+        assert_eq!(get_text(&router, Some("other.example.com"), "/v1").await, (200, "default".to_string()));
+        assert_eq!(get_text(&router, None, "/v1").await, (200, "default".to_string()));
+    }
+}
@@ -0,0 +1,491 @@
+//!
+//! DKIM (RFC 6376) signature checks, rsa-sha256 and ed25519-sha256 (RFC 8463).
+//! rsa-sha1 is refused (RFC 8301), as are rsa keys under 1024 bits.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+use crate::auth::AuthResult;
+use crate::auth::dns::{DnsError, DnsResolver};
+
+/// more signatures than that are ignored, each one costs a dns lookup.
+const MAX_SIGNATURES: usize = 5;
+const MIN_RSA_BITS: usize = 1024;
+
+/// one `DKIM-Signature` and how it went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignatureResult {
+    pub result: AuthResult,
+    /// `d=`, the domain taking responsibility.
+    pub domain: String,
+    pub selector: String,
+    /// why it didn't pass.
+    pub reason: String,
+}
+
+impl fmt::Display for SignatureResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dkim={}", self.result)?;
+        if !self.reason.is_empty() {
+            write!(f, " ({})", self.reason)?;
+        }
+        write!(f, " header.d={} header.s={}", self.domain, self.selector)
+    }
+}
+
+/// pass if any signature passes, otherwise the worst of them.
+pub fn overall(signatures: &[SignatureResult]) -> AuthResult {
+    let has = |r: AuthResult| signatures.iter().any(|s| s.result == r);
+    [AuthResult::Pass, AuthResult::Fail, AuthResult::TempError, AuthResult::PermError].into_iter()
+        .find(|r| has(*r))
+        .unwrap_or(AuthResult::None)
+}
+
+struct Header {
+    /// lowercase.
+    name: String,
+    /// the whole field, folding and the final CRLF included.
+    raw: Vec<u8>,
+}
+
+impl Header {
+    fn value(&self) -> String {
+        let raw = String::from_utf8_lossy(&self.raw);
+        raw.split_once(':').map(|(_, v)| v.to_string()).unwrap_or_default()
+    }
+}
+
+/// the message with every line ending as CRLF, mailin hands us whatever the client sent.
+fn normalize(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len() + raw.len() / 32);
+    for (i, b) in raw.iter().enumerate() {
+        if *b == b'\n' && (i == 0 || raw[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(*b);
+    }
+    out
+}
+
+/// header fields in order, and the body.
+fn split(message: &[u8]) -> (Vec<Header>, &[u8]) {
+    let mut headers: Vec<Header> = vec![];
+    let mut pos = 0;
+    while pos < message.len() {
+        let end = message[pos..].windows(2).position(|w| w == b"\r\n").map(|i| pos + i + 2).unwrap_or(message.len());
+        let line = &message[pos..end];
+        if line == b"\r\n" {
+            return (headers, &message[end..]);
+        }
+        match (line[0], headers.last_mut()) {
+            (b' ' | b'\t', Some(last)) => last.raw.extend_from_slice(line),
+            _ => {
+                let name = line.split(|b| *b == b':').next().unwrap_or_default();
+                headers.push(Header { name: String::from_utf8_lossy(name).trim().to_lowercase(), raw: line.to_vec() });
+            }
+        }
+        pos = end;
+    }
+    (headers, &[])
+}
+
+fn is_wsp(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// runs of spaces and tabs as one space.
+fn compress_wsp(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len());
+    for b in bytes {
+        if is_wsp(*b) {
+            if out.last() != Some(&b' ') {
+                out.push(b' ');
+            }
+        } else {
+            out.push(*b);
+        }
+    }
+    out
+}
+
+fn canonical_header(raw: &[u8], relaxed: bool) -> Vec<u8> {
+    if !relaxed {
+        return raw.to_vec();
+    }
+    let unfolded: Vec<u8> = raw.iter().copied().filter(|b| *b != b'\r' && *b != b'\n').collect();
+    let colon = unfolded.iter().position(|b| *b == b':').unwrap_or(unfolded.len());
+    let name = String::from_utf8_lossy(&unfolded[..colon]).trim().to_lowercase();
+    let value = compress_wsp(unfolded.get(colon + 1..).unwrap_or_default());
+    let value = value.trim_ascii();
+    let mut out = name.into_bytes();
+    out.push(b':');
+    out.extend_from_slice(value);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+fn canonical_body(body: &[u8], relaxed: bool) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = body.split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .map(|line| match relaxed {
+            true => {
+                let mut line = compress_wsp(line);
+                while line.last().map(|b| is_wsp(*b)).unwrap_or(false) {
+                    line.pop();
+                }
+                line
+            }
+            false => line.to_vec(),
+        })
+        .collect();
+    while lines.last().map(|l| l.is_empty()).unwrap_or(false) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        // "simple" still hashes one empty line.
+        return match relaxed {
+            true => vec![],
+            false => b"\r\n".to_vec(),
+        };
+    }
+    lines.iter().flat_map(|l| l.iter().chain(b"\r\n")).copied().collect()
+}
+
+/// `a=b; c=d` tag lists, of signatures and keys. `None` when a tag is repeated.
+fn tag_list(value: &str) -> Option<HashMap<String, String>> {
+    let mut tags = HashMap::new();
+    for tag in value.split(';') {
+        if tag.trim().is_empty() {
+            continue;
+        }
+        let (name, value) = tag.split_once('=')?;
+        let value: String = value.split([' ', '\t', '\r', '\n']).collect();
+        if tags.insert(name.trim().to_string(), value).is_some() {
+            return None;
+        }
+    }
+    Some(tags)
+}
+
+/// the signature header as it was hashed, with an empty `b=`.
+fn without_signature(raw: &[u8]) -> Vec<u8> {
+    let raw = String::from_utf8_lossy(raw);
+    let Some((name, value)) = raw.split_once(':') else { return raw.as_bytes().to_vec() };
+    let value = value.split(';')
+        .map(|tag| match tag.split_once('=') {
+            Some((name, value)) if name.trim() == "b" => {
+                // the line break after the signature stays, it is part of the field.
+                let tail = if value.ends_with("\r\n") { "\r\n" } else { "" };
+                format!("{}={}", name, tail)
+            }
+            _ => tag.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";");
+    format!("{}:{}", name, value).into_bytes()
+}
+
+/// the domain of the (single) `From:` header, what DMARC is about.
+pub fn from_domain(raw: &[u8]) -> Option<String> {
+    let message = normalize(raw);
+    let (headers, _) = split(&message);
+    let mut from = headers.iter().filter(|h| h.name == "from");
+    let value = from.next()?.value();
+    if from.next().is_some() {
+        return None;
+    }
+    let address = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.as_str(),
+    };
+    let domain = address.rsplit_once('@')?.1.trim().trim_end_matches(['>', ';', ',']).to_lowercase();
+    match domain.is_empty() {
+        true => None,
+        false => Some(domain),
+    }
+}
+
+/// the first header field called `name` (any case), unfolded and trimmed.
+pub fn header(raw: &[u8], name: &str) -> Option<String> {
+    let message = normalize(raw);
+    let (headers, _) = split(&message);
+    let header = headers.iter().find(|h| h.name.eq_ignore_ascii_case(name))?;
+    Some(header.value().replace("\r\n", "").trim().to_string())
+}
+
+///
+/// every `DKIM-Signature` of the raw message, in header order.
+pub async fn verify(resolver: &dyn DnsResolver, raw: &[u8]) -> Vec<SignatureResult> {
+    let message = normalize(raw);
+    let (headers, body) = split(&message);
+    let mut results = vec![];
+    let signatures = headers.iter().enumerate().filter(|(_, h)| h.name == "dkim-signature").map(|(i, _)| i);
+    for i in signatures.take(MAX_SIGNATURES) {
+        let (result, reason, domain, selector) = match verify_one(resolver, &headers, i, body).await {
+            Ok((domain, selector)) => (AuthResult::Pass, String::new(), domain, selector),
+            Err(failure) => (failure.result, failure.reason, failure.domain, failure.selector),
+        };
+        results.push(SignatureResult { result, domain, selector, reason });
+    }
+    results
+}
+
+struct Failure {
+    result: AuthResult,
+    reason: String,
+    domain: String,
+    selector: String,
+}
+
+async fn verify_one(resolver: &dyn DnsResolver, headers: &[Header], index: usize, body: &[u8]) -> Result<(String, String), Failure> {
+    let signature = &headers[index];
+    let tags = tag_list(&signature.value()).unwrap_or_default();
+    let tag = |name: &str| tags.get(name).map(|v| v.as_str()).unwrap_or("");
+    let domain = tag("d").to_lowercase();
+    let selector = tag("s").to_string();
+    let fail = |result: AuthResult, reason: &str| Failure { result, reason: reason.to_string(), domain: domain.to_string(), selector: selector.to_string() };
+
+    for required in ["v", "a", "b", "bh", "d", "h", "s"] {
+        if tag(required).is_empty() {
+            return Err(fail(AuthResult::PermError, &format!("missing {}=", required)));
+        }
+    }
+    if tag("v") != "1" {
+        return Err(fail(AuthResult::PermError, "unknown version"));
+    }
+    let signed: Vec<String> = tag("h").split(':').map(|h| h.trim().to_lowercase()).collect();
+    if !signed.iter().any(|h| h == "from") {
+        return Err(fail(AuthResult::PermError, "from not signed"));
+    }
+    let identity = tag("i").rsplit_once('@').map(|(_, d)| d.to_lowercase()).unwrap_or_default();
+    if !identity.is_empty() && identity != domain && !identity.ends_with(&format!(".{}", domain)) {
+        return Err(fail(AuthResult::PermError, "i= outside d="));
+    }
+    if let Ok(expires) = tag("x").parse::<i64>() {
+        if expires < Utc::now().timestamp() {
+            return Err(fail(AuthResult::PermError, "signature expired"));
+        }
+    }
+    let (header_relaxed, body_relaxed) = match tag("c") {
+        "" | "simple" | "simple/simple" => (false, false),
+        "relaxed" | "relaxed/simple" => (true, false),
+        "simple/relaxed" => (false, true),
+        "relaxed/relaxed" => (true, true),
+        _ => return Err(fail(AuthResult::PermError, "unknown canonicalization")),
+    };
+
+    let mut body = canonical_body(body, body_relaxed);
+    if !tag("l").is_empty() {
+        let length: usize = tag("l").parse().map_err(|_| fail(AuthResult::PermError, "bad l="))?;
+        if length > body.len() {
+            return Err(fail(AuthResult::PermError, "l= beyond the body"));
+        }
+        body.truncate(length);
+    }
+    let body_hash = STANDARD.decode(tag("bh")).map_err(|_| fail(AuthResult::PermError, "bad bh="))?;
+    if Sha256::digest(&body).as_slice() != body_hash.as_slice() {
+        return Err(fail(AuthResult::Fail, "body hash mismatch"));
+    }
+
+    // the signed headers, each name taken from the bottom up, then the signature itself.
+    let mut hasher = Sha256::new();
+    let mut used: HashMap<&str, usize> = HashMap::new();
+    for name in &signed {
+        let skip = used.entry(name).or_default();
+        if let Some(header) = headers.iter().rev().filter(|h| &h.name == name).nth(*skip) {
+            hasher.update(canonical_header(&header.raw, header_relaxed));
+        }
+        *skip += 1;
+    }
+    let mut own = canonical_header(&without_signature(&signature.raw), header_relaxed);
+    while own.last().map(|b| *b == b'\n' || *b == b'\r').unwrap_or(false) {
+        own.pop();
+    }
+    hasher.update(own);
+    let digest = hasher.finalize();
+    let signature_bytes = STANDARD.decode(tag("b")).map_err(|_| fail(AuthResult::PermError, "bad b="))?;
+
+    let records = match resolver.txt(&format!("{}._domainkey.{}", selector, domain)).await {
+        Ok(records) => records,
+        Err(DnsError::NotFound) => return Err(fail(AuthResult::PermError, "no key")),
+        Err(DnsError::Temporary(e)) => return Err(fail(AuthResult::TempError, &e)),
+    };
+    let key = records.iter().filter_map(|r| tag_list(r)).next().ok_or_else(|| fail(AuthResult::PermError, "bad key"))?;
+    let key_tag = |name: &str| key.get(name).map(|v| v.as_str()).unwrap_or("");
+    if key_tag("p").is_empty() {
+        return Err(fail(AuthResult::PermError, "key revoked"));
+    }
+    let public_key = STANDARD.decode(key_tag("p")).map_err(|_| fail(AuthResult::PermError, "bad key"))?;
+    let key_type = match key_tag("k") {
+        "" => "rsa",
+        k => k,
+    };
+
+    let valid = match (tag("a"), key_type) {
+        ("rsa-sha256", "rsa") => {
+            use rsa::pkcs1::DecodeRsaPublicKey;
+            use rsa::pkcs8::DecodePublicKey;
+            use rsa::traits::PublicKeyParts;
+
+            let key = rsa::RsaPublicKey::from_public_key_der(&public_key)
+                .or_else(|_| rsa::RsaPublicKey::from_pkcs1_der(&public_key))
+                .map_err(|_| fail(AuthResult::PermError, "bad key"))?;
+            if key.size() * 8 < MIN_RSA_BITS {
+                return Err(fail(AuthResult::PermError, "key too short"));
+            }
+            key.verify(rsa::Pkcs1v15Sign::new::<Sha256>(), &digest, &signature_bytes).is_ok()
+        }
+        ("ed25519-sha256", "ed25519") => {
+            use ed25519_dalek::Verifier;
+
+            let key: [u8; 32] = public_key.as_slice().try_into().map_err(|_| fail(AuthResult::PermError, "bad key"))?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|_| fail(AuthResult::PermError, "bad key"))?;
+            let signature = ed25519_dalek::Signature::from_slice(&signature_bytes).map_err(|_| fail(AuthResult::PermError, "bad b="))?;
+            key.verify(&digest, &signature).is_ok()
+        }
+        ("rsa-sha1", _) => return Err(fail(AuthResult::PermError, "rsa-sha1 not accepted")),
+        ("rsa-sha256" | "ed25519-sha256", _) => return Err(fail(AuthResult::PermError, "key type mismatch")),
+        _ => return Err(fail(AuthResult::PermError, "unknown algorithm")),
+    };
+    match valid {
+        true => Ok((domain.to_string(), selector.to_string())),
+        false => Err(fail(AuthResult::Fail, "signature mismatch")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::Signer;
+
+    use crate::auth::dns::StaticResolver;
+
+    use super::*;
+
+    /// the example of RFC 8463, its ed25519 key is the first test key of RFC 8032.
+    const MESSAGE: &str = concat!(
+        "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed;\r\n",
+        " d=football.example.com; i=@football.example.com;\r\n",
+        " q=dns/txt; s=brisbane; t=1528637909; h=from : to :\r\n",
+        " subject : date : message-id : from : subject : date;\r\n",
+        " bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\r\n",
+        " b=/gCrinpcQOoIfuHNQIbq4pgh9kyIK3AQUdt9OdqQehSwhEIug4D11Bus\r\n",
+        " Fa3bT3FY5OsU7ZbnKELq+eXdp1Q1Dw==\r\n",
+        "From: Joe SixPack <joe@football.example.com>\r\n",
+        "To: Suzie Q <suzie@shopping.example.net>\r\n",
+        "Subject: Is dinner ready?\r\n",
+        "Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r\n",
+        "Message-ID: <20030712040037.46341.5F8J@football.example.com>\r\n",
+        "\r\n",
+        "Hi.\r\n",
+        "\r\n",
+        "We lost the game.  Are you hungry yet?\r\n",
+        "\r\n",
+        "Joe.\r\n",
+    );
+
+    fn resolver() -> StaticResolver {
+        StaticResolver::default()
+            .with_txt("brisbane._domainkey.football.example.com", "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=")
+    }
+
+    #[tokio::test]
+    async fn test_rfc8463_example() {
+        let results = verify(&resolver(), MESSAGE.as_bytes()).await;
+        assert_eq!(results, vec![SignatureResult {
+            result: AuthResult::Pass,
+            domain: "football.example.com".to_string(),
+            selector: "brisbane".to_string(),
+            reason: String::new(),
+        }]);
+
+        // bare LF line endings, as some clients send them.
+        assert_eq!(overall(&verify(&resolver(), MESSAGE.replace("\r\n", "\n").as_bytes()).await), AuthResult::Pass);
+
+        let tampered = MESSAGE.replace("We lost", "We won");
+        assert_eq!(verify(&resolver(), tampered.as_bytes()).await[0].reason, "body hash mismatch");
+        let tampered = MESSAGE.replace("Is dinner ready?", "Is lunch ready?");
+        assert_eq!(verify(&resolver(), tampered.as_bytes()).await[0].result, AuthResult::Fail);
+        // relaxed canonicalization doesn't mind whitespace.
+        let reformatted = MESSAGE.replace("Subject: Is dinner", "Subject:  Is \t dinner").replace("hungry yet?", "hungry yet?  ");
+        assert_eq!(overall(&verify(&resolver(), reformatted.as_bytes()).await), AuthResult::Pass);
+
+        assert_eq!(verify(&StaticResolver::default(), MESSAGE.as_bytes()).await[0].reason, "no key");
+        let revoked = StaticResolver::default().with_txt("brisbane._domainkey.football.example.com", "v=DKIM1; k=ed25519; p=");
+        assert_eq!(verify(&revoked, MESSAGE.as_bytes()).await[0].reason, "key revoked");
+        assert_eq!(from_domain(MESSAGE.as_bytes()).as_deref(), Some("football.example.com"));
+    }
+
+    /// a simple/simple signature over `headers` and `body`, by the RFC 8032 key.
+    fn sign(headers: &str, body: &str, extra_tags: &str) -> String {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[
+            0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec, 0x2c, 0xc4,
+            0x44, 0x49, 0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03, 0x1c, 0xae, 0x7f, 0x60,
+        ]);
+        let body_hash = STANDARD.encode(Sha256::digest(canonical_body(body.as_bytes(), false)));
+        let unsigned = format!(
+            "DKIM-Signature: v=1; a=ed25519-sha256; d=football.example.com; s=brisbane; h=from:subject;{} bh={}; b=",
+            extra_tags, body_hash,
+        );
+        let digest = Sha256::digest(format!("{}{}", headers, unsigned));
+        format!("{}{}\r\n{}\r\n{}", unsigned, STANDARD.encode(key.sign(&digest).to_bytes()), headers, body)
+    }
+
+    #[tokio::test]
+    async fn test_simple_canonicalization_and_tags() {
+        let headers = "From: joe@football.example.com\r\nSubject: hi\r\n";
+        let signed = sign(headers, "body\r\n\r\n\r\n", "");
+        assert_eq!(overall(&verify(&resolver(), signed.as_bytes()).await), AuthResult::Pass);
+        // simple is strict about whitespace.
+        let reformatted = signed.replace("Subject: hi", "Subject:  hi");
+        assert_eq!(overall(&verify(&resolver(), reformatted.as_bytes()).await), AuthResult::Fail);
+
+        // only the first l= bytes of the body are signed.
+        let signed = sign(headers, "body\r\n", " l=6;");
+        assert_eq!(overall(&verify(&resolver(), format!("{}appended\r\n", signed).as_bytes()).await), AuthResult::Pass);
+
+        let signed = sign(headers, "body\r\n", " x=1000000000;");
+        assert_eq!(verify(&resolver(), signed.as_bytes()).await[0].reason, "signature expired");
+        let signed = sign(headers, "body\r\n", "").replace("ed25519-sha256", "rsa-sha1");
+        assert_eq!(verify(&resolver(), signed.as_bytes()).await[0].result, AuthResult::PermError);
+        assert_eq!(overall(&verify(&resolver(), b"From: a@x.com\r\n\r\nbody\r\n").await), AuthResult::None);
+    }
+
+    #[tokio::test]
+    async fn test_rsa() {
+        use rsa::pkcs8::EncodePublicKey;
+
+        let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let public_key = STANDARD.encode(private_key.to_public_key().to_public_key_der().unwrap().as_bytes());
+        let resolver = StaticResolver::default().with_txt("s1._domainkey.x.com", &format!("v=DKIM1; k=rsa; p={}", public_key));
+
+        let headers = "From: a@x.com\r\n";
+        let body_hash = STANDARD.encode(Sha256::digest(b"hello\r\n"));
+        let unsigned = format!("DKIM-Signature: v=1; a=rsa-sha256; d=x.com; s=s1; h=from; bh={}; b=", body_hash);
+        let digest = Sha256::digest(format!("{}{}", headers, unsigned));
+        let signature = private_key.sign(rsa::Pkcs1v15Sign::new::<Sha256>(), &digest).unwrap();
+        let signed = format!("{}{}\r\n{}\r\nhello\r\n", unsigned, STANDARD.encode(signature), headers);
+        assert_eq!(overall(&verify(&resolver, signed.as_bytes()).await), AuthResult::Pass);
+        assert_eq!(overall(&verify(&resolver, signed.replace("a@x.com", "b@x.com").as_bytes()).await), AuthResult::Fail);
+    }
+
+    #[test]
+    fn test_header() {
+        let raw = b"From: a@x.com\nAuto-Submitted: auto-replied;\n\towner=x\nSubject: hi\n\nList-Id: body, not a header\n";
+        assert_eq!(header(raw, "auto-submitted").as_deref(), Some("auto-replied;\towner=x"));
+        assert_eq!(header(raw, "SUBJECT").as_deref(), Some("hi"));
+        assert_eq!(header(raw, "list-id"), None);
+    }
+
+    #[test]
+    fn test_from_domain() {
+        assert_eq!(from_domain(b"From: \"a@b\" <Me@X.com>\r\n\r\n").as_deref(), Some("x.com"));
+        assert_eq!(from_domain(b"From: me@x.com\r\nFrom: me@y.com\r\n\r\n"), None);
+        assert_eq!(from_domain(b"Subject: none\r\n\r\n"), None);
+    }
+}
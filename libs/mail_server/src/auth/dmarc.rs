@@ -0,0 +1,129 @@
+//!
+//! DMARC (RFC 7489) : does a passing SPF or DKIM domain line up with the `From:` domain.
+//! the policy is reported, acting on it is up to the mail rules.
+
+use crate::auth::AuthResult;
+use crate::auth::dns::{DnsError, DnsResolver};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DmarcResult {
+    pub result: AuthResult,
+    /// `none`, `quarantine` or `reject`, empty without a record.
+    pub policy: String,
+}
+
+///
+/// the organizational domain (RFC 7489 3.2) : one label below the public suffix.
+/// private suffixes count too, `a.github.io` and `b.github.io` belong to different people.
+pub fn organizational_domain(domain: &str) -> String {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    psl::domain_str(&domain).unwrap_or(&domain).to_string()
+}
+
+fn aligned(domain: &str, from_domain: &str, strict: bool) -> bool {
+    match strict {
+        true => domain.eq_ignore_ascii_case(from_domain),
+        false => organizational_domain(domain) == organizational_domain(from_domain),
+    }
+}
+
+/// the record of `_dmarc.<domain>`, `Ok(None)` without one.
+async fn record(resolver: &dyn DnsResolver, domain: &str) -> Result<Option<String>, AuthResult> {
+    let records = match resolver.txt(&format!("_dmarc.{}", domain)).await {
+        Ok(records) => records,
+        Err(DnsError::NotFound) => return Ok(None),
+        Err(DnsError::Temporary(_)) => return Err(AuthResult::TempError),
+    };
+    let mut records = records.into_iter().filter(|r| r.trim_start().starts_with("v=DMARC1"));
+    match (records.next(), records.next()) {
+        (Some(record), None) => Ok(Some(record)),
+        // several records count as none.
+        _ => Ok(None),
+    }
+}
+
+pub async fn check(resolver: &dyn DnsResolver, from_domain: &str, spf: AuthResult, spf_domain: &str, dkim_domains: &[&str]) -> DmarcResult {
+    if from_domain.is_empty() {
+        return DmarcResult::default();
+    }
+    let org_domain = organizational_domain(from_domain);
+    let found = match record(resolver, from_domain).await {
+        Ok(None) if org_domain != from_domain => record(resolver, &org_domain).await.map(|r| r.map(|r| (r, true))),
+        r => r.map(|r| r.map(|r| (r, false))),
+    };
+    let (record, inherited) = match found {
+        Ok(Some(found)) => found,
+        Ok(None) => return DmarcResult::default(),
+        Err(result) => return DmarcResult { result, policy: String::new() },
+    };
+
+    let tags: Vec<(String, String)> = record.split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_lowercase()))
+        .collect();
+    let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str()).unwrap_or("");
+
+    let policy = match (inherited, tag("sp")) {
+        (true, sp) if !sp.is_empty() => sp,
+        _ => tag("p"),
+    };
+    if !["none", "quarantine", "reject"].contains(&policy) {
+        return DmarcResult { result: AuthResult::PermError, policy: String::new() };
+    }
+
+    let spf_aligned = spf == AuthResult::Pass && aligned(spf_domain, from_domain, tag("aspf") == "s");
+    let dkim_aligned = dkim_domains.iter().any(|d| aligned(d, from_domain, tag("adkim") == "s"));
+    DmarcResult {
+        result: if spf_aligned || dkim_aligned { AuthResult::Pass } else { AuthResult::Fail },
+        policy: policy.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::dns::StaticResolver;
+
+    use super::*;
+
+    #[test]
+    fn test_organizational_domain() {
+        assert_eq!(organizational_domain("mail.x.com"), "x.com");
+        assert_eq!(organizational_domain("x.com."), "x.com");
+        assert_eq!(organizational_domain("a.b.x.co.uk"), "x.co.uk");
+        assert_eq!(organizational_domain("localhost"), "localhost");
+        assert_eq!(organizational_domain("attacker.github.io"), "attacker.github.io");
+        assert_eq!(organizational_domain("x.bucket.s3.amazonaws.com"), "bucket.s3.amazonaws.com");
+    }
+
+    #[tokio::test]
+    async fn test_shared_suffixes_dont_align() {
+        let r = StaticResolver::default().with_txt("_dmarc.victim.github.io", "v=DMARC1; p=reject");
+        let c = check(&r, "victim.github.io", AuthResult::Pass, "attacker.github.io", &["attacker.github.io"]).await;
+        assert_eq!(c.result, AuthResult::Fail);
+        // and nothing is inherited from the suffix.
+        let r = StaticResolver::default().with_txt("_dmarc.github.io", "v=DMARC1; p=reject");
+        assert_eq!(check(&r, "victim.github.io", AuthResult::Pass, "victim.github.io", &[]).await, DmarcResult::default());
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let r = StaticResolver::default()
+            .with_txt("_dmarc.x.com", "v=DMARC1; p=reject; sp=quarantine; adkim=s")
+            .with_txt("_dmarc.y.com", "v=DMARC1; p=bogus");
+
+        // relaxed spf, strict dkim.
+        let c = check(&r, "x.com", AuthResult::Pass, "bounce.x.com", &[]).await;
+        assert_eq!(c, DmarcResult { result: AuthResult::Pass, policy: "reject".to_string() });
+        assert_eq!(check(&r, "x.com", AuthResult::SoftFail, "bounce.x.com", &[]).await.result, AuthResult::Fail);
+        assert_eq!(check(&r, "x.com", AuthResult::None, "", &["mail.x.com"]).await.result, AuthResult::Fail);
+        assert_eq!(check(&r, "x.com", AuthResult::None, "", &["other.com", "x.com"]).await.result, AuthResult::Pass);
+
+        // a subdomain falls back to the organizational record and its sp=.
+        let c = check(&r, "news.x.com", AuthResult::Pass, "evil.com", &[]).await;
+        assert_eq!(c, DmarcResult { result: AuthResult::Fail, policy: "quarantine".to_string() });
+
+        assert_eq!(check(&r, "z.com", AuthResult::Pass, "z.com", &[]).await, DmarcResult::default());
+        assert_eq!(check(&r, "y.com", AuthResult::Pass, "y.com", &[]).await.result, AuthResult::PermError);
+    }
+}
@@ -0,0 +1,121 @@
+//!
+//! dns lookups for the sender checks, behind a trait so tests can answer from a table.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DnsError {
+    /// NXDOMAIN, or no records of that type.
+    NotFound,
+    /// timeouts, SERVFAIL and the like, the checks report a temperror.
+    Temporary(String),
+}
+
+pub type DnsResult<T> = Result<T, DnsError>;
+
+#[async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// one string per TXT record, its character strings joined.
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>>;
+    /// A and AAAA records.
+    async fn ips(&self, name: &str) -> DnsResult<Vec<IpAddr>>;
+    /// the exchanges, most preferred first.
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>>;
+}
+
+fn key(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+///
+/// answers from what it was given, everything else is `NotFound`.
+/// `StaticResolver::default().with_txt("x.com", "v=spf1 -all")`
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    txt: HashMap<String, Vec<String>>,
+    ips: HashMap<String, Vec<IpAddr>>,
+    mx: HashMap<String, Vec<String>>,
+}
+
+impl StaticResolver {
+    pub fn with_txt(mut self, name: &str, value: &str) -> Self {
+        self.txt.entry(key(name)).or_default().push(value.to_string());
+        self
+    }
+
+    pub fn with_ip(mut self, name: &str, ip: IpAddr) -> Self {
+        self.ips.entry(key(name)).or_default().push(ip);
+        self
+    }
+
+    /// in preference order.
+    pub fn with_mx(mut self, name: &str, exchange: &str) -> Self {
+        self.mx.entry(key(name)).or_default().push(exchange.to_string());
+        self
+    }
+}
+
+fn lookup<T: Clone>(map: &HashMap<String, Vec<T>>, name: &str) -> DnsResult<Vec<T>> {
+    map.get(&key(name)).cloned().ok_or(DnsError::NotFound)
+}
+
+#[async_trait]
+impl DnsResolver for StaticResolver {
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>> {
+        lookup(&self.txt, name)
+    }
+
+    async fn ips(&self, name: &str) -> DnsResult<Vec<IpAddr>> {
+        lookup(&self.ips, name)
+    }
+
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>> {
+        lookup(&self.mx, name)
+    }
+}
+
+/// the resolvers of the os, `/etc/resolv.conf` or its equivalent.
+pub struct SystemResolver(hickory_resolver::TokioAsyncResolver);
+
+impl SystemResolver {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(SystemResolver(hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()?))
+    }
+}
+
+fn dns_error(e: hickory_resolver::error::ResolveError) -> DnsError {
+    match e.kind() {
+        hickory_resolver::error::ResolveErrorKind::NoRecordsFound { .. } => DnsError::NotFound,
+        _ => DnsError::Temporary(e.to_string()),
+    }
+}
+
+/// fully qualified, so the search domains of the host are never appended.
+fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+#[async_trait]
+impl DnsResolver for SystemResolver {
+    async fn txt(&self, name: &str) -> DnsResult<Vec<String>> {
+        let lookup = self.0.txt_lookup(fqdn(name)).await.map_err(dns_error)?;
+        Ok(lookup.iter()
+            .map(|txt| txt.txt_data().iter().map(|s| String::from_utf8_lossy(s)).collect::<String>())
+            .collect())
+    }
+
+    async fn ips(&self, name: &str) -> DnsResult<Vec<IpAddr>> {
+        let lookup = self.0.lookup_ip(fqdn(name)).await.map_err(dns_error)?;
+        Ok(lookup.iter().collect())
+    }
+
+    async fn mx(&self, name: &str) -> DnsResult<Vec<String>> {
+        let lookup = self.0.mx_lookup(fqdn(name)).await.map_err(dns_error)?;
+        let mut records: Vec<_> = lookup.iter().map(|mx| (mx.preference(), mx.exchange().to_utf8())).collect();
+        records.sort();
+        Ok(records.into_iter().map(|(_, exchange)| exchange.trim_end_matches('.').to_string()).collect())
+    }
+}
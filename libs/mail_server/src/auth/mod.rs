@@ -0,0 +1,127 @@
+//!
+//! who really sent an inbound mail : SPF for the connecting ip, DKIM signatures, DMARC alignment
+//! of both with the `From:` header. only reported, nothing is rejected here.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::auth::dns::DnsResolver;
+use crate::models::message::Message;
+
+pub mod dns;
+pub mod spf;
+pub mod dkim;
+pub mod dmarc;
+
+/// the result names of RFC 8601, `none` when there was nothing to check.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthResult {
+    #[default]
+    None,
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+    TempError,
+    PermError,
+}
+
+impl AuthResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthResult::None => "none",
+            AuthResult::Pass => "pass",
+            AuthResult::Fail => "fail",
+            AuthResult::SoftFail => "softfail",
+            AuthResult::Neutral => "neutral",
+            AuthResult::TempError => "temperror",
+            AuthResult::PermError => "permerror",
+        }
+    }
+}
+
+impl fmt::Display for AuthResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AuthResults {
+    pub spf: AuthResult,
+    pub dkim: AuthResult,
+    pub dmarc: AuthResult,
+    /// `Authentication-Results` style, `spf=pass smtp.mailfrom=x.com; dkim=...`.
+    pub details: String,
+}
+
+///
+/// checks a received message. the smtp session has to be known for SPF,
+/// without `remote_ip` it is `none`.
+pub async fn verify(resolver: &dyn DnsResolver, message: &Message) -> AuthResults {
+    let spf_domain = match message.envelope_from.rsplit_once('@') {
+        Some((_, domain)) if !domain.is_empty() => domain.to_lowercase(),
+        _ => message.helo.to_lowercase(),
+    };
+    let spf = match message.remote_ip {
+        Some(ip) => spf::check_host(resolver, ip, &message.envelope_from, &message.helo).await,
+        None => AuthResult::None,
+    };
+
+    let signatures = dkim::verify(resolver, &message.source).await;
+    let dkim = dkim::overall(&signatures);
+    let dkim_domains: Vec<&str> = signatures.iter()
+        .filter(|s| s.result == AuthResult::Pass)
+        .map(|s| s.domain.as_str())
+        .collect();
+
+    let from_domain = dkim::from_domain(&message.source).unwrap_or_default();
+    let dmarc = dmarc::check(resolver, &from_domain, spf, &spf_domain, &dkim_domains).await;
+
+    let mut details = vec![format!("spf={} smtp.mailfrom={}", spf, spf_domain)];
+    match signatures.is_empty() {
+        true => details.push("dkim=none".to_string()),
+        false => details.extend(signatures.iter().map(|s| s.to_string())),
+    }
+    details.push(match dmarc.policy.is_empty() {
+        true => format!("dmarc={} header.from={}", dmarc.result, from_domain),
+        false => format!("dmarc={} policy.dmarc={} header.from={}", dmarc.result, dmarc.policy, from_domain),
+    });
+
+    AuthResults { spf, dkim, dmarc: dmarc.result, details: details.join("; ") }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::dns::StaticResolver;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify() {
+        let resolver = StaticResolver::default()
+            .with_txt("x.com", "v=spf1 ip4:192.0.2.0/24 -all")
+            .with_txt("_dmarc.x.com", "v=DMARC1; p=reject");
+        let mut message = Message {
+            envelope_from: "a@x.com".to_string(),
+            helo: "mail.x.com".to_string(),
+            remote_ip: Some("192.0.2.1".parse().unwrap()),
+            source: b"From: A <a@x.com>\r\nSubject: hi\r\n\r\nhello\r\n".to_vec(),
+            ..Default::default()
+        };
+        let results = verify(&resolver, &message).await;
+        assert_eq!((results.spf, results.dkim, results.dmarc), (AuthResult::Pass, AuthResult::None, AuthResult::Pass));
+        assert_eq!(results.details, "spf=pass smtp.mailfrom=x.com; dkim=none; dmarc=pass policy.dmarc=reject header.from=x.com");
+
+        message.remote_ip = Some("203.0.113.1".parse().unwrap());
+        let results = verify(&resolver, &message).await;
+        assert_eq!((results.spf, results.dmarc), (AuthResult::Fail, AuthResult::Fail));
+
+        message.remote_ip = None;
+        assert_eq!(verify(&resolver, &message).await.spf, AuthResult::None);
+        assert_eq!(serde_json::to_string(&AuthResult::SoftFail).unwrap(), "\"softfail\"");
+    }
+}
@@ -0,0 +1,371 @@
+//!
+//! SPF (RFC 7208) : may this ip send mail for the MAIL FROM domain.
+//! `ptr` never matches, the RFC itself says not to use it.
+
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+
+use crate::auth::AuthResult;
+use crate::auth::dns::{DnsError, DnsResolver};
+
+/// dns querying mechanisms and modifiers allowed per check.
+const LOOKUP_LIMIT: u32 = 10;
+/// lookups answered with nothing.
+const VOID_LOOKUP_LIMIT: u32 = 2;
+
+struct Check<'a> {
+    resolver: &'a dyn DnsResolver,
+    ip: IpAddr,
+    /// `local@domain`, `postmaster@<helo>` for bounces.
+    sender: String,
+    helo: String,
+    lookups: u32,
+    void_lookups: u32,
+}
+
+/// a mechanism that can't finish, it ends the whole check with that result.
+type Abort = AuthResult;
+
+///
+/// `check_host()` of the RFC. `sender` is the MAIL FROM address, or empty for a bounce :
+/// then the HELO name is checked instead.
+pub async fn check_host(resolver: &dyn DnsResolver, ip: IpAddr, sender: &str, helo: &str) -> AuthResult {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    };
+    let sender = match sender.rsplit_once('@') {
+        Some((_, domain)) if !domain.is_empty() => sender.to_string(),
+        _ => format!("postmaster@{}", helo),
+    };
+    let domain = sender.rsplit_once('@').map(|(_, d)| d.to_string()).unwrap_or_default();
+    if !domain.contains('.') {
+        return AuthResult::None;
+    }
+    let mut check = Check { resolver, ip, sender, helo: helo.to_string(), lookups: 0, void_lookups: 0 };
+    check.run(domain).await
+}
+
+impl<'a> Check<'a> {
+    fn run(&mut self, domain: String) -> Pin<Box<dyn Future<Output=AuthResult> + Send + '_>> {
+        Box::pin(async move {
+            let records = match self.resolver.txt(&domain).await {
+                Ok(records) => records,
+                Err(DnsError::NotFound) => return AuthResult::None,
+                Err(DnsError::Temporary(_)) => return AuthResult::TempError,
+            };
+            let records: Vec<&String> = records.iter()
+                .filter(|r| r.eq_ignore_ascii_case("v=spf1") || r.to_lowercase().starts_with("v=spf1 "))
+                .collect();
+            let record = match records.as_slice() {
+                [] => return AuthResult::None,
+                [record] => record.to_string(),
+                _ => return AuthResult::PermError,
+            };
+
+            let mut redirect = None;
+            for term in record.split_whitespace().skip(1) {
+                let (qualifier, mechanism) = match term.chars().next() {
+                    Some(q @ ('+' | '-' | '~' | '?')) => (q, &term[1..]),
+                    _ => ('+', term),
+                };
+                let name = mechanism.split([':', '/']).next().unwrap_or("").to_lowercase();
+                if !["all", "include", "a", "mx", "ptr", "ip4", "ip6", "exists"].contains(&name.as_str()) {
+                    match term.split_once('=') {
+                        Some((modifier, value)) if modifier.eq_ignore_ascii_case("redirect") => redirect = Some(value.to_string()),
+                        // exp= and unknown modifiers are ignored.
+                        Some(_) => {}
+                        None => return AuthResult::PermError,
+                    }
+                    continue;
+                }
+                match self.matches(&name, &mechanism[name.len()..], &domain).await {
+                    Ok(true) => return match qualifier {
+                        '-' => AuthResult::Fail,
+                        '~' => AuthResult::SoftFail,
+                        '?' => AuthResult::Neutral,
+                        _ => AuthResult::Pass,
+                    },
+                    Ok(false) => {}
+                    Err(abort) => return abort,
+                }
+            }
+
+            match redirect {
+                Some(target) => {
+                    let Some(target) = self.expand(&target, &domain) else { return AuthResult::PermError };
+                    if let Err(abort) = self.count_lookup() {
+                        return abort;
+                    }
+                    match self.run(target).await {
+                        AuthResult::None => AuthResult::PermError,
+                        r => r,
+                    }
+                }
+                None => AuthResult::Neutral,
+            }
+        })
+    }
+
+    fn count_lookup(&mut self) -> Result<(), Abort> {
+        self.lookups += 1;
+        match self.lookups > LOOKUP_LIMIT {
+            true => Err(AuthResult::PermError),
+            false => Ok(()),
+        }
+    }
+
+    /// the addresses of a name, none for a void lookup.
+    async fn ips(&mut self, name: &str) -> Result<Vec<IpAddr>, Abort> {
+        match self.resolver.ips(name).await {
+            Ok(ips) => Ok(ips),
+            Err(DnsError::NotFound) => {
+                self.void_lookups += 1;
+                match self.void_lookups > VOID_LOOKUP_LIMIT {
+                    true => Err(AuthResult::PermError),
+                    false => Ok(vec![]),
+                }
+            }
+            Err(DnsError::Temporary(_)) => Err(AuthResult::TempError),
+        }
+    }
+
+    /// `args` is what follows the mechanism name, e.g. `:x.com/24` for `a:x.com/24`.
+    async fn matches(&mut self, name: &str, args: &str, domain: &str) -> Result<bool, Abort> {
+        let (target, cidr) = match args.find('/') {
+            Some(i) => (&args[..i], &args[i..]),
+            None => (args, ""),
+        };
+        let target = match target.strip_prefix(':') {
+            Some(spec) => self.expand(spec, domain).ok_or(AuthResult::PermError)?,
+            None if target.is_empty() => domain.to_string(),
+            None => return Err(AuthResult::PermError),
+        };
+
+        match name {
+            "all" => Ok(true),
+            "include" => {
+                self.count_lookup()?;
+                match self.run(target).await {
+                    AuthResult::Pass => Ok(true),
+                    AuthResult::Fail | AuthResult::SoftFail | AuthResult::Neutral => Ok(false),
+                    AuthResult::TempError => Err(AuthResult::TempError),
+                    _ => Err(AuthResult::PermError),
+                }
+            }
+            "a" => {
+                self.count_lookup()?;
+                let (v4, v6) = dual_cidr(cidr).ok_or(AuthResult::PermError)?;
+                Ok(self.ips(&target).await?.iter().any(|ip| in_network(self.ip, *ip, v4, v6)))
+            }
+            "mx" => {
+                self.count_lookup()?;
+                let (v4, v6) = dual_cidr(cidr).ok_or(AuthResult::PermError)?;
+                let exchanges = match self.resolver.mx(&target).await {
+                    Ok(exchanges) => exchanges,
+                    Err(DnsError::NotFound) => vec![],
+                    Err(DnsError::Temporary(_)) => return Err(AuthResult::TempError),
+                };
+                if exchanges.len() > LOOKUP_LIMIT as usize {
+                    return Err(AuthResult::PermError);
+                }
+                for exchange in exchanges {
+                    if self.ips(&exchange).await?.iter().any(|ip| in_network(self.ip, *ip, v4, v6)) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            "ptr" => {
+                self.count_lookup()?;
+                Ok(false)
+            }
+            "exists" => {
+                self.count_lookup()?;
+                Ok(self.ips(&target).await?.iter().any(|ip| ip.is_ipv4()))
+            }
+            // ip4 and ip6 : the "target" is the address itself.
+            _ => {
+                let net: IpAddr = target.parse().map_err(|_| AuthResult::PermError)?;
+                let len = match cidr {
+                    "" => None,
+                    c => Some(c[1..].parse::<u8>().map_err(|_| AuthResult::PermError)?),
+                };
+                match (name, net) {
+                    ("ip4", IpAddr::V4(_)) => Ok(in_network(self.ip, net, len.unwrap_or(32), 128)),
+                    ("ip6", IpAddr::V6(_)) => Ok(in_network(self.ip, net, 32, len.unwrap_or(128))),
+                    _ => Err(AuthResult::PermError),
+                }
+            }
+        }
+    }
+
+    ///
+    /// macro expansion of a domain spec, `%{i}`, `%{d2}`, `%{ir}` and friends. `None` on a syntax error.
+    fn expand(&self, spec: &str, domain: &str) -> Option<String> {
+        let (local, sender_domain) = self.sender.rsplit_once('@').unwrap_or(("postmaster", &self.sender));
+        let mut out = String::new();
+        let mut chars = spec.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next()? {
+                '%' => out.push('%'),
+                '_' => out.push(' '),
+                '-' => out.push_str("%20"),
+                '{' => {
+                    let body: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                    let mut body = body.chars();
+                    let letter = body.next()?.to_ascii_lowercase();
+                    let rest: String = body.collect();
+                    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+                    let rest = &rest[digits.len()..];
+                    let (reverse, delimiters) = match rest.strip_prefix(['r', 'R']) {
+                        Some(d) => (true, d),
+                        None => (false, rest),
+                    };
+                    if !delimiters.chars().all(|c| ".-+,/_=".contains(c)) {
+                        return None;
+                    }
+                    let delimiters = if delimiters.is_empty() { "." } else { delimiters };
+                    let value = match letter {
+                        's' => self.sender.to_string(),
+                        'l' => local.to_string(),
+                        'o' => sender_domain.to_string(),
+                        'd' => domain.to_string(),
+                        'i' => ip_macro(self.ip),
+                        'p' => "unknown".to_string(),
+                        'v' => if self.ip.is_ipv4() { "in-addr" } else { "ip6" }.to_string(),
+                        'h' => self.helo.to_string(),
+                        _ => return None,
+                    };
+                    let mut parts: Vec<&str> = value.split(|c| delimiters.contains(c)).collect();
+                    if reverse {
+                        parts.reverse();
+                    }
+                    if !digits.is_empty() {
+                        let keep: usize = digits.parse().ok().filter(|n| *n > 0)?;
+                        parts = parts.split_off(parts.len().saturating_sub(keep));
+                    }
+                    out.push_str(&parts.join("."));
+                }
+                _ => return None,
+            }
+        }
+        Some(out)
+    }
+}
+
+/// `1.2.3.4`, or one dotted nibble per hex digit for ipv6.
+fn ip_macro(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => v4.to_string(),
+        IpAddr::V6(v6) => v6.octets().iter()
+            .flat_map(|b| [b >> 4, b & 0xf])
+            .map(|n| format!("{:x}", n))
+            .collect::<Vec<_>>()
+            .join("."),
+    }
+}
+
+/// `/24//64` into the ipv4 and ipv6 prefix lengths, full length when absent.
+fn dual_cidr(cidr: &str) -> Option<(u8, u8)> {
+    let (v4, v6) = match cidr.split_once("//") {
+        Some((v4, v6)) => (v4, Some(v6)),
+        None => (cidr, None),
+    };
+    let v4 = match v4.strip_prefix('/') {
+        Some(len) => len.parse().ok().filter(|l| *l <= 32)?,
+        None if v4.is_empty() => 32,
+        None => return None,
+    };
+    let v6 = match v6 {
+        Some(len) => len.parse().ok().filter(|l| *l <= 128)?,
+        None => 128,
+    };
+    Some((v4, v6))
+}
+
+fn in_network(ip: IpAddr, net: IpAddr, v4_len: u8, v6_len: u8) -> bool {
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) if v4_len <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - v4_len as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) if v6_len <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - v6_len as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(net) & mask
+        }
+        _ => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::dns::StaticResolver;
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn resolver() -> StaticResolver {
+        StaticResolver::default()
+            .with_txt("x.com", "v=spf1 ip4:192.0.2.0/24 a:mail.x.com mx include:_spf.partner.com ~all")
+            .with_txt("x.com", "google-site-verification=abc")
+            .with_ip("mail.x.com", ip("198.51.100.7"))
+            .with_mx("x.com", "mx.x.com")
+            .with_ip("mx.x.com", ip("2001:db8::25"))
+            .with_txt("_spf.partner.com", "v=spf1 ip6:2001:db8:1::/48 -all")
+            .with_txt("strict.com", "v=spf1 redirect=x.com")
+            .with_txt("two.com", "v=spf1 -all")
+            .with_txt("two.com", "v=spf1 +all")
+            .with_txt("loop.com", "v=spf1 include:loop.com")
+            .with_txt("macro.com", "v=spf1 exists:%{ir}.%{l1r-}.allow.macro.com -all")
+            .with_ip("7.2.0.192.some.allow.macro.com", ip("127.0.0.2"))
+    }
+
+    async fn check(ip_addr: &str, sender: &str) -> AuthResult {
+        check_host(&resolver(), ip(ip_addr), sender, "client.example").await
+    }
+
+    #[tokio::test]
+    async fn test_check_host() {
+        assert_eq!(check("192.0.2.9", "a@x.com").await, AuthResult::Pass);
+        assert_eq!(check("::ffff:192.0.2.9", "a@x.com").await, AuthResult::Pass);
+        assert_eq!(check("198.51.100.7", "a@x.com").await, AuthResult::Pass);
+        assert_eq!(check("2001:db8::25", "a@x.com").await, AuthResult::Pass);
+        assert_eq!(check("2001:db8:1::9", "a@x.com").await, AuthResult::Pass);
+        assert_eq!(check("203.0.113.1", "a@x.com").await, AuthResult::SoftFail);
+        assert_eq!(check("203.0.113.1", "a@strict.com").await, AuthResult::SoftFail);
+        assert_eq!(check("192.0.2.9", "a@nothing.com").await, AuthResult::None);
+        assert_eq!(check("192.0.2.9", "a@two.com").await, AuthResult::PermError);
+        assert_eq!(check("192.0.2.9", "a@loop.com").await, AuthResult::PermError);
+        assert_eq!(check("192.0.2.7", "some-bob@macro.com").await, AuthResult::Pass);
+        assert_eq!(check("192.0.2.8", "some-bob@macro.com").await, AuthResult::Fail);
+    }
+
+    #[tokio::test]
+    async fn test_bounce_checks_helo() {
+        let r = StaticResolver::default().with_txt("client.example", "v=spf1 ip4:192.0.2.1 -all");
+        assert_eq!(check_host(&r, ip("192.0.2.1"), "", "client.example").await, AuthResult::Pass);
+        assert_eq!(check_host(&r, ip("192.0.2.2"), "", "client.example").await, AuthResult::Fail);
+    }
+
+    #[test]
+    fn test_cidr() {
+        assert_eq!(dual_cidr(""), Some((32, 128)));
+        assert_eq!(dual_cidr("/24//64"), Some((24, 64)));
+        assert_eq!(dual_cidr("//64"), Some((32, 64)));
+        assert_eq!(dual_cidr("/33"), None);
+        assert!(in_network(ip("10.1.2.3"), ip("10.0.0.0"), 8, 128));
+        assert!(in_network(ip("10.1.2.3"), ip("0.0.0.0"), 0, 128));
+        assert!(!in_network(ip("10.1.2.3"), ip("10.0.0.0"), 16, 128));
+        assert_eq!(ip_macro(ip("2001:db8::cb01")), "2.0.0.1.0.d.b.8.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.c.b.0.1");
+    }
+}
@@ -1,3 +1,6 @@
 pub mod models;
 pub mod smtp;
-
+pub mod outbound;
+pub mod auth;
+#[cfg(feature = "pop3")]
+pub mod pop3;
@@ -1,4 +1,6 @@
-use anyhow::{Context, Result};
+use std::net::IpAddr;
+
+use anyhow::{Context, ensure, Result};
 use chrono::Local;
 use mail_parser;
 use serde::Serialize;
@@ -8,7 +10,18 @@ use uuid::Uuid;
 pub struct Message {
     pub id: Option<usize>,
     pub sender: String,
+    /// the `To:` header addresses.
     pub recipients: Vec<String>,
+    /// the `Cc:` header addresses.
+    pub cc: Vec<String>,
+    /// `MAIL FROM` of the smtp session, may differ from the `From:` header.
+    pub envelope_from: String,
+    /// `RCPT TO` of the smtp session, where the message was really delivered to, bcc included.
+    pub envelope_recipients: Vec<String>,
+    /// the connecting client, what SPF checks.
+    pub remote_ip: Option<IpAddr>,
+    /// the name it gave in HELO / EHLO.
+    pub helo: String,
     pub subject: String,
     pub created_at: Option<String>,
     pub attachments: Vec<Attachment>,
@@ -42,46 +55,36 @@ impl Message {
     pub fn from(data: &Vec<u8>) -> Result<Self> {
         use mail_parser::HeaderValue;
 
+        ensure!(!data.iter().all(|b| b.is_ascii_whitespace()), "empty message");
         let message = mail_parser::Message::parse(data.as_ref()).context("parse message")?;
 
-        let sender = {
-            if let HeaderValue::Address(addr) = message.from() {
-                format!(
-                    "{} {}",
-                    addr.name.as_ref().context("parse sender name")?,
-                    addr.address.as_ref().context("parse sender address")?
-                )
-            } else {
-                "".to_owned()
-            }
-        };
-
-        let recipients = {
-            let mut list: Vec<String> = vec![];
-
-            if let HeaderValue::Address(addr) = message.to() {
-                list.push(format!(
-                    "{}",
-                    addr.address.as_ref().context("parse recipient address")?
-                ));
-            }
-
-            list
-        };
+        let sender = match message.from() {
+            HeaderValue::Address(addr) => Some(addr),
+            HeaderValue::AddressList(list) => list.first(),
+            _ => None,
+        }.map(|addr| match (&addr.name, &addr.address) {
+            (Some(name), Some(address)) => format!("{} {}", name, address),
+            (None, Some(address)) => address.to_string(),
+            (Some(name), None) => name.to_string(),
+            (None, None) => "".to_owned(),
+        }).unwrap_or_default();
+
+        let recipients = addresses(message.to());
+        let cc = addresses(message.cc());
         let subject = message.subject().unwrap_or("").to_string();
 
         let mut formats = vec!["source".to_owned()];
         let mut html: Option<String> = None;
         let mut plain: Option<String> = None;
 
-        if message.html_body_count() > 0 {
+        if let Some(body) = message.body_html(0) {
             formats.push("html".to_owned());
-            html = Some(message.body_html(0).unwrap().to_string());
+            html = Some(body.to_string());
         }
 
-        if message.text_body_count() > 0 {
+        if let Some(body) = message.body_text(0) {
             formats.push("plain".to_owned());
-            plain = Some(message.body_text(0).unwrap().to_string());
+            plain = Some(body.to_string());
         }
 
         use mail_parser::MimeHeaders;
@@ -93,9 +96,15 @@ impl Message {
                     .attachment_name()
                     .unwrap_or("unknown")
                     .to_string(),
-                file_type: attachment.content_type().unwrap().ctype().to_string(),
+                file_type: attachment.content_type()
+                    .map(|t| match t.subtype() {
+                        Some(subtype) => format!("{}/{}", t.ctype(), subtype),
+                        None => t.ctype().to_string(),
+                    })
+                    .unwrap_or("application/octet-stream".to_string()),
                 body: attachment.contents().to_vec(),
-                cid: Uuid::new_v4().to_string(),
+                // inline parts are referenced by their Content-ID from the html.
+                cid: attachment.content_id().map(|id| id.to_string()).unwrap_or(Uuid::new_v4().to_string()),
             })
             .collect();
 
@@ -103,6 +112,7 @@ impl Message {
             id: None,
             sender,
             recipients,
+            cc,
             subject,
             created_at: Some(Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
             attachments,
@@ -110,8 +120,39 @@ impl Message {
             formats,
             html,
             plain,
+            ..Default::default()
         })
     }
+
+    /// envelope recipients that no header mentions, i.e. the bcc ones.
+    pub fn bcc(&self) -> Vec<String> {
+        bcc(&self.envelope_recipients, &[&self.recipients, &self.cc])
+    }
+}
+
+/// the addresses of an address header, groups flattened, names dropped.
+fn addresses(value: &mail_parser::HeaderValue) -> Vec<String> {
+    use mail_parser::HeaderValue;
+
+    let list: Vec<&mail_parser::Addr> = match value {
+        HeaderValue::Address(addr) => vec![addr],
+        HeaderValue::AddressList(list) => list.iter().collect(),
+        HeaderValue::Group(group) => group.addresses.iter().collect(),
+        HeaderValue::GroupList(groups) => groups.iter().flat_map(|g| g.addresses.iter()).collect(),
+        _ => vec![],
+    };
+    list.into_iter()
+        .filter_map(|addr| addr.address.as_ref())
+        .map(|address| address.to_string())
+        .collect()
+}
+
+/// the envelope recipients missing from every header list, compared case insensitively.
+pub fn bcc(envelope: &[String], headers: &[&Vec<String>]) -> Vec<String> {
+    envelope.iter()
+        .filter(|e| !headers.iter().any(|h| h.iter().any(|a| a.eq_ignore_ascii_case(e))))
+        .cloned()
+        .collect()
 }
 
 #[cfg(test)]
@@ -163,6 +204,60 @@ mod test {
         assert_eq!(message.subject, "This is a test email");
     }
 
+    #[test]
+    fn test_attachments() {
+        let data = concat!(
+            "From: Private Person <me@fromdomain.com>\n",
+            "To: A Test User <test@todomain.com>\n",
+            "Subject: with files\n",
+            "Content-Type: multipart/mixed; boundary=foobar\n",
+            "\n",
+            "--foobar\n",
+            "Content-Type: text/plain\n",
+            "\n",
+            "see attached.\n",
+            "--foobar\n",
+            "Content-Type: image/png; name=\"dot.png\"\n",
+            "Content-Disposition: attachment; filename=\"dot.png\"\n",
+            "Content-ID: <dot@play>\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "aGVsbG8=\n",
+            "--foobar--\n"
+        )
+        .as_bytes()
+        .to_vec();
+
+        let message = Message::from(&data).unwrap();
+        assert_eq!(message.source, data);
+        assert_eq!(message.attachments.len(), 1);
+        let a = &message.attachments[0];
+        assert_eq!((a.filename.as_str(), a.file_type.as_str(), a.cid.as_str()), ("dot.png", "image/png", "dot@play"));
+        assert_eq!(a.body, b"hello");
+    }
+
+    #[test]
+    fn test_recipients() {
+        let data = concat!(
+            "From: me@fromdomain.com\n",
+            "To: A Test User <a@todomain.com>, b@todomain.com\n",
+            "Cc: Team: c@todomain.com, D <d@todomain.com>;, e@todomain.com\n",
+            "Subject: many recipients\n",
+            "\n",
+            "hi all.\n"
+        )
+        .as_bytes()
+        .to_vec();
+
+        let mut message = Message::from(&data).unwrap();
+        assert_eq!(message.sender, "me@fromdomain.com");
+        assert_eq!(message.recipients, vec!["a@todomain.com", "b@todomain.com"]);
+        assert_eq!(message.cc, vec!["c@todomain.com", "d@todomain.com", "e@todomain.com"]);
+
+        message.envelope_recipients = vec!["A@todomain.com".to_string(), "d@todomain.com".to_string(), "hidden@todomain.com".to_string()];
+        assert_eq!(message.bcc(), vec!["hidden@todomain.com"]);
+    }
+
     #[test]
     fn test_subject_is_not_found() {
         let data = concat!(
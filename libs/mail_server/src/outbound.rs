@@ -0,0 +1,262 @@
+//!
+//! outgoing mail : compose a MIME message from an `OutgoingMail` and hand it to a smarthost.
+//! queueing and retries are up to the caller, `SendError` tells whether trying again makes sense.
+
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::transport::smtp::authentication::Credentials;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OutgoingMail {
+    /// `me@x.com` or `Me <me@x.com>`, the same for every address field.
+    pub from: String,
+    #[serde(default)]
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub subject: String,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub html: String,
+    #[serde(default)]
+    pub attachments: Vec<OutgoingAttachment>,
+    /// extra header fields, e.g. `Auto-Submitted` or `In-Reply-To`.
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OutgoingAttachment {
+    pub filename: String,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    /// base64 in json.
+    #[serde(with = "base64_body")]
+    pub body: Vec<u8>,
+}
+
+fn default_content_type() -> String {
+    "application/octet-stream".to_string()
+}
+
+mod base64_body {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD.decode(String::deserialize(d)?).map_err(serde::de::Error::custom)
+    }
+}
+
+impl OutgoingMail {
+    /// every address the smarthost delivers to, bcc included.
+    pub fn recipients(&self) -> Vec<String> {
+        self.to.iter().chain(&self.cc).chain(&self.bcc).cloned().collect()
+    }
+
+    /// `from` without the display name, bounces go there.
+    pub fn from_address(&self) -> String {
+        self.from.parse::<Mailbox>().map(|m| m.email.to_string()).unwrap_or(self.from.to_string())
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox> {
+    address.parse().with_context(|| format!("invalid address : {}", address))
+}
+
+/// the MIME message : text and html as alternatives, attachments around them.
+pub fn compose(mail: &OutgoingMail) -> Result<lettre::Message> {
+    ensure!(!mail.recipients().is_empty(), "no recipients");
+
+    let mut builder = lettre::Message::builder()
+        .from(mailbox(&mail.from)?)
+        .subject(&mail.subject);
+    for to in &mail.to {
+        builder = builder.to(mailbox(to)?);
+    }
+    for cc in &mail.cc {
+        builder = builder.cc(mailbox(cc)?);
+    }
+    for bcc in &mail.bcc {
+        builder = builder.bcc(mailbox(bcc)?);
+    }
+    for (name, value) in &mail.headers {
+        let name = HeaderName::new_from_ascii(name.to_string()).with_context(|| format!("invalid header name : {}", name))?;
+        builder = builder.raw_header(HeaderValue::new(name, value.to_string()));
+    }
+
+    let alternative = !mail.text.is_empty() && !mail.html.is_empty();
+    let alternative_part = || MultiPart::alternative_plain_html(mail.text.to_string(), mail.html.to_string());
+    let single_part = || match mail.text.is_empty() && !mail.html.is_empty() {
+        true => SinglePart::html(mail.html.to_string()),
+        false => SinglePart::plain(mail.text.to_string()),
+    };
+
+    let message = if mail.attachments.is_empty() {
+        match alternative {
+            true => builder.multipart(alternative_part())?,
+            false => builder.singlepart(single_part())?,
+        }
+    } else {
+        let mut mixed = match alternative {
+            true => MultiPart::mixed().multipart(alternative_part()),
+            false => MultiPart::mixed().singlepart(single_part()),
+        };
+        for a in &mail.attachments {
+            let content_type = ContentType::parse(&a.content_type)
+                .with_context(|| format!("invalid content type : {}", a.content_type))?;
+            mixed = mixed.singlepart(Attachment::new(a.filename.to_string()).body(a.body.clone(), content_type));
+        }
+        builder.multipart(mixed)?
+    };
+    Ok(message)
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmarthostTls {
+    /// plain text, only for a local relay or a test sink.
+    None,
+    #[default]
+    Starttls,
+    /// implicit tls, usually port 465.
+    Tls,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SmarthostConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmarthostTls,
+    /// no AUTH when empty.
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SendError {
+    /// rejected for good (5xx, or the mail can't even be composed), it bounces.
+    Permanent(String),
+    /// 4xx, network errors, timeouts, worth another try later.
+    Transient(String),
+}
+
+/// delivers through one smarthost, cheap to keep around, connections are pooled.
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Mailer {
+    pub fn new(config: &SmarthostConfig) -> Result<Self> {
+        ensure!(!config.host.is_empty(), "no smarthost configured");
+        let mut builder = match config.tls {
+            SmarthostTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            SmarthostTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            SmarthostTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+        };
+        builder = builder.port(config.port).timeout(Some(Duration::from_secs(30)));
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(config.username.to_string(), config.password.to_string()));
+        }
+        Ok(Mailer { transport: builder.build() })
+    }
+
+    pub async fn send(&self, mail: &OutgoingMail) -> std::result::Result<(), SendError> {
+        let message = compose(mail).map_err(|e| SendError::Permanent(format!("{:#}", e)))?;
+        match self.transport.send(message).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_permanent() => Err(SendError::Permanent(e.to_string())),
+            Err(e) => Err(SendError::Transient(e.to_string())),
+        }
+    }
+}
+
+/// how long to wait before attempt number `attempts + 1` : a minute, doubling, at most six hours.
+pub fn retry_delay(attempts: u32) -> Duration {
+    Duration::from_secs((60u64 << attempts.min(16)).min(6 * 3600))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail() -> OutgoingMail {
+        OutgoingMail {
+            from: "Me <me@x.com>".to_string(),
+            to: vec!["a@x.com".to_string(), "b@x.com".to_string()],
+            bcc: vec!["hidden@x.com".to_string()],
+            subject: "héllo".to_string(),
+            text: "plain body".to_string(),
+            html: "<p>html body</p>".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compose() -> Result<()> {
+        let mut m = mail();
+        m.attachments.push(OutgoingAttachment { filename: "a.txt".to_string(), content_type: "text/plain".to_string(), body: b"attached".to_vec() });
+        m.headers.push(("Auto-Submitted".to_string(), "auto-replied".to_string()));
+        let message = compose(&m)?;
+        let envelope = message.envelope();
+        assert_eq!(envelope.to().len(), 3);
+        assert_eq!(envelope.from().unwrap().to_string(), "me@x.com");
+        assert_eq!(m.from_address(), "me@x.com");
+
+        let raw = String::from_utf8(message.formatted())?;
+        assert!(raw.contains("multipart/mixed"));
+        assert!(raw.contains("multipart/alternative"));
+        assert!(raw.contains("filename=\"a.txt\""));
+        assert!(raw.contains("Auto-Submitted: auto-replied\r\n"));
+        assert!(!raw.contains("hidden@x.com"));
+        // non ascii subjects are encoded.
+        assert!(raw.contains("Subject: =?utf-8?"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose_rejects_bad_input() {
+        assert!(compose(&OutgoingMail { from: "me@x.com".to_string(), ..Default::default() }).is_err());
+        assert!(compose(&OutgoingMail { from: "not an address".to_string(), to: vec!["a@x.com".to_string()], ..Default::default() }).is_err());
+        let mut m = mail();
+        m.attachments.push(OutgoingAttachment { filename: "a".to_string(), content_type: "nope".to_string(), body: vec![] });
+        assert!(compose(&m).is_err());
+        let mut m = mail();
+        m.headers.push(("Bad Name".to_string(), "x".to_string()));
+        assert!(compose(&m).is_err());
+    }
+
+    #[test]
+    fn test_json() -> Result<()> {
+        let m: OutgoingMail = serde_json::from_str(r#"{"from": "me@x.com", "to": ["a@x.com"], "subject": "s", "attachments": [{"filename": "a.bin", "body": "aGVsbG8="}]}"#)?;
+        assert_eq!(m.attachments[0].body, b"hello");
+        assert_eq!(m.attachments[0].content_type, "application/octet-stream");
+        assert_eq!(serde_json::from_str::<OutgoingMail>(&serde_json::to_string(&m)?)?, m);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0), Duration::from_secs(60));
+        assert_eq!(retry_delay(3), Duration::from_secs(480));
+        assert_eq!(retry_delay(30), Duration::from_secs(6 * 3600));
+    }
+}
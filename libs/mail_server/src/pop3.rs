@@ -0,0 +1,361 @@
+//!
+//! a small POP3 server (RFC 1939) : USER / PASS against the smtp accounts, then
+//! STAT, LIST, UIDL, RETR, TOP, DELE, RSET, NOOP and QUIT over whatever a `Maildrop` holds.
+//! plain text, no STLS, meant for local mail clients and test harnesses.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{info, warn};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::smtp::Authenticator;
+
+/// RFC 1939 wants at least ten minutes before an idle client is dropped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// longer command lines are refused, the RFC allows 255 bytes of arguments.
+const MAX_LINE: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaildropEntry {
+    /// stable across sessions, it is also the UIDL of the message.
+    pub id: i64,
+    /// of the raw message, in bytes.
+    pub size: u64,
+}
+
+/// where the messages come from, the server has them in its inbox table.
+#[async_trait]
+pub trait Maildrop: Send + Sync {
+    /// the messages of `user`, oldest first, numbered from 1 in that order.
+    async fn list(&self, user: &str) -> Result<Vec<MaildropEntry>>;
+    /// the raw message.
+    async fn retrieve(&self, id: i64) -> Result<Vec<u8>>;
+    /// called on QUIT with what was marked by DELE.
+    async fn delete(&self, ids: &[i64]) -> Result<()>;
+}
+
+enum State {
+    Authorization { user: Option<String> },
+    Transaction { messages: Vec<MaildropEntry>, deleted: Vec<bool> },
+    Done,
+}
+
+/// one connection, fed a command line at a time.
+pub struct Session {
+    maildrop: Arc<dyn Maildrop>,
+    authenticator: Arc<dyn Authenticator>,
+    state: State,
+}
+
+fn ok(text: &str) -> Vec<u8> {
+    format!("+OK {}\r\n", text).into_bytes()
+}
+
+fn err(text: &str) -> Vec<u8> {
+    format!("-ERR {}\r\n", text).into_bytes()
+}
+
+/// a multi-line answer : the lines, dot-stuffed, then the final dot.
+fn multi_line(first: &str, lines: impl IntoIterator<Item=String>) -> Vec<u8> {
+    let mut out = ok(first);
+    for line in lines {
+        out.extend_from_slice(line.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b".\r\n");
+    out
+}
+
+///
+/// the raw message as sent by RETR, or TOP with `body_lines` : CRLF line endings,
+/// lines starting with a dot get another one, ends with the final dot.
+pub fn dot_stuff(raw: &[u8], body_lines: Option<usize>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len() + 64);
+    let mut in_body = false;
+    let mut sent_body_lines = 0;
+    let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+    for line in raw.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if in_body {
+            if body_lines.map(|max| sent_body_lines >= max).unwrap_or(false) {
+                break;
+            }
+            sent_body_lines += 1;
+        }
+        if line.starts_with(b".") {
+            out.push(b'.');
+        }
+        out.extend_from_slice(line);
+        out.extend_from_slice(b"\r\n");
+        in_body = in_body || line.is_empty();
+    }
+    out.extend_from_slice(b".\r\n");
+    out
+}
+
+impl Session {
+    pub fn new(maildrop: Arc<dyn Maildrop>, authenticator: Arc<dyn Authenticator>) -> Self {
+        Session { maildrop, authenticator, state: State::Authorization { user: None } }
+    }
+
+    /// QUIT was handled, the connection can be closed.
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// the answer to one command line, without its line ending.
+    pub async fn command(&mut self, line: &str) -> Vec<u8> {
+        let mut parts = line.trim_end().splitn(2, ' ');
+        let keyword = parts.next().unwrap_or("").to_uppercase();
+        let arg = parts.next().unwrap_or("").trim();
+
+        if keyword == "CAPA" {
+            return multi_line("capability list follows", ["USER", "UIDL", "TOP"].map(String::from));
+        }
+        match &mut self.state {
+            State::Authorization { user } => match keyword.as_str() {
+                "USER" if !arg.is_empty() => {
+                    *user = Some(arg.to_string());
+                    ok("send PASS")
+                }
+                "PASS" => {
+                    let Some(username) = user.take() else { return err("USER first") };
+                    if !self.authenticator.authenticate(&username, arg) {
+                        warn!("pop3 login failed for {}", username);
+                        return err("invalid credentials");
+                    }
+                    match self.maildrop.list(&username).await {
+                        Ok(messages) => {
+                            info!("pop3 login of {}, {} messages", username, messages.len());
+                            let reply = ok(&format!("{} messages", messages.len()));
+                            let deleted = vec![false; messages.len()];
+                            self.state = State::Transaction { messages, deleted };
+                            reply
+                        }
+                        Err(e) => {
+                            warn!("pop3 maildrop of {} : {:?}", username, e);
+                            err("maildrop unavailable")
+                        }
+                    }
+                }
+                "QUIT" => {
+                    self.state = State::Done;
+                    ok("bye")
+                }
+                _ => err("unknown command"),
+            },
+            State::Transaction { messages, deleted } => {
+                // `n` of the commands taking a message number, 1 based and not deleted.
+                let message = |arg: &str| -> Option<usize> {
+                    let n: usize = arg.split(' ').next()?.parse().ok()?;
+                    (n >= 1 && n <= messages.len() && !deleted[n - 1]).then_some(n - 1)
+                };
+                let listed = |field: fn(usize, &MaildropEntry) -> String| -> Vec<u8> {
+                    if arg.is_empty() {
+                        let lines = messages.iter().enumerate().filter(|(i, _)| !deleted[*i]).map(|(i, m)| field(i, m));
+                        return multi_line("listing follows", lines.collect::<Vec<_>>());
+                    }
+                    match message(arg) {
+                        Some(i) => ok(&field(i, &messages[i])),
+                        None => err("no such message"),
+                    }
+                };
+                match keyword.as_str() {
+                    "STAT" => {
+                        let kept = messages.iter().enumerate().filter(|(i, _)| !deleted[*i]);
+                        let (count, size) = kept.fold((0, 0), |(c, s), (_, m)| (c + 1, s + m.size));
+                        ok(&format!("{} {}", count, size))
+                    }
+                    "LIST" => listed(|i, m| format!("{} {}", i + 1, m.size)),
+                    "UIDL" => listed(|i, m| format!("{} {}", i + 1, m.id)),
+                    "RETR" | "TOP" => {
+                        let Some(i) = message(arg) else { return err("no such message") };
+                        let body_lines = match keyword.as_str() {
+                            "TOP" => match arg.split(' ').nth(1).and_then(|n| n.parse().ok()) {
+                                Some(n) => Some(n),
+                                None => return err("TOP needs a message and a line count"),
+                            },
+                            _ => None,
+                        };
+                        match self.maildrop.retrieve(messages[i].id).await {
+                            Ok(raw) => {
+                                let mut reply = ok(&format!("{} octets", messages[i].size));
+                                reply.extend(dot_stuff(&raw, body_lines));
+                                reply
+                            }
+                            Err(e) => {
+                                warn!("pop3 retrieve {} : {:?}", messages[i].id, e);
+                                err("message unavailable")
+                            }
+                        }
+                    }
+                    "DELE" => match message(arg) {
+                        Some(i) => {
+                            deleted[i] = true;
+                            ok("marked")
+                        }
+                        None => err("no such message"),
+                    },
+                    "RSET" => {
+                        deleted.iter_mut().for_each(|d| *d = false);
+                        ok("reset")
+                    }
+                    "NOOP" => ok(""),
+                    "QUIT" => {
+                        let ids: Vec<i64> = messages.iter().zip(deleted.iter()).filter(|(_, d)| **d).map(|(m, _)| m.id).collect();
+                        let reply = match self.maildrop.delete(&ids).await {
+                            Ok(()) => ok(&format!("bye, {} deleted", ids.len())),
+                            Err(e) => {
+                                warn!("pop3 delete {:?} : {:?}", ids, e);
+                                err("some deleted messages not removed")
+                            }
+                        };
+                        self.state = State::Done;
+                        reply
+                    }
+                    _ => err("unknown command"),
+                }
+            }
+            State::Done => err("session is over"),
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, maildrop: Arc<dyn Maildrop>, authenticator: Arc<dyn Authenticator>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut session = Session::new(maildrop, authenticator);
+    writer.write_all(&ok("POP3 server ready")).await?;
+    while !session.is_done() {
+        let mut line = String::new();
+        let read = tokio::time::timeout(IDLE_TIMEOUT, (&mut reader).take(MAX_LINE).read_line(&mut line)).await;
+        match read {
+            Ok(Ok(0)) | Err(_) => break,
+            Ok(Ok(_)) if !line.ends_with('\n') => {
+                writer.write_all(&err("line too long")).await?;
+                break;
+            }
+            Ok(Ok(_)) => writer.write_all(&session.command(&line).await).await?,
+            Ok(Err(e)) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// accepts clients till the listener fails, one task per connection.
+pub async fn serve(listener: TcpListener, maildrop: Arc<dyn Maildrop>, authenticator: Arc<dyn Authenticator>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (maildrop, authenticator) = (maildrop.clone(), authenticator.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, maildrop, authenticator).await {
+                warn!("pop3 connection from {} : {:?}", peer, e);
+            }
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Memory {
+        messages: Mutex<Vec<(i64, &'static str)>>,
+    }
+
+    #[async_trait]
+    impl Maildrop for Memory {
+        async fn list(&self, _user: &str) -> Result<Vec<MaildropEntry>> {
+            Ok(self.messages.lock().unwrap().iter().map(|(id, raw)| MaildropEntry { id: *id, size: raw.len() as u64 }).collect())
+        }
+
+        async fn retrieve(&self, id: i64) -> Result<Vec<u8>> {
+            let messages = self.messages.lock().unwrap();
+            messages.iter().find(|(i, _)| *i == id).map(|(_, raw)| raw.as_bytes().to_vec()).ok_or(anyhow!("gone"))
+        }
+
+        async fn delete(&self, ids: &[i64]) -> Result<()> {
+            self.messages.lock().unwrap().retain(|(id, _)| !ids.contains(id));
+            Ok(())
+        }
+    }
+
+    struct OneAccount;
+
+    impl Authenticator for OneAccount {
+        fn authenticate(&self, username: &str, password: &str) -> bool {
+            username == "me@x.com" && password == "secret"
+        }
+    }
+
+    fn session(memory: &Arc<Memory>) -> Session {
+        Session::new(memory.clone(), Arc::new(OneAccount))
+    }
+
+    async fn reply(s: &mut Session, line: &str) -> String {
+        String::from_utf8(s.command(line).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let memory = Arc::new(Memory::default());
+        *memory.messages.lock().unwrap() = vec![(7, "Subject: a\r\n\r\none\r\n.dot\r\n"), (9, "Subject: b\n\nline 1\nline 2\n")];
+        let mut s = session(&memory);
+
+        assert!(reply(&mut s, "STAT").await.starts_with("-ERR"));
+        assert!(reply(&mut s, "PASS secret").await.starts_with("-ERR"));
+        reply(&mut s, "USER me@x.com").await;
+        assert_eq!(reply(&mut s, "PASS wrong").await, "-ERR invalid credentials\r\n");
+        reply(&mut s, "user me@x.com").await;
+        assert_eq!(reply(&mut s, "PASS secret").await, "+OK 2 messages\r\n");
+
+        assert_eq!(reply(&mut s, "STAT").await, "+OK 2 51\r\n");
+        assert_eq!(reply(&mut s, "LIST").await, "+OK listing follows\r\n1 25\r\n2 26\r\n.\r\n");
+        assert_eq!(reply(&mut s, "UIDL 2").await, "+OK 2 9\r\n");
+        assert_eq!(reply(&mut s, "RETR 1").await, "+OK 25 octets\r\nSubject: a\r\n\r\none\r\n..dot\r\n.\r\n");
+        assert_eq!(reply(&mut s, "TOP 2 1").await, "+OK 26 octets\r\nSubject: b\r\n\r\nline 1\r\n.\r\n");
+        assert!(reply(&mut s, "RETR 3").await.starts_with("-ERR"));
+
+        reply(&mut s, "DELE 1").await;
+        assert!(reply(&mut s, "RETR 1").await.starts_with("-ERR"));
+        assert_eq!(reply(&mut s, "STAT").await, "+OK 1 26\r\n");
+        reply(&mut s, "RSET").await;
+        assert_eq!(reply(&mut s, "STAT").await, "+OK 2 51\r\n");
+
+        reply(&mut s, "DELE 2").await;
+        assert_eq!(reply(&mut s, "QUIT").await, "+OK bye, 1 deleted\r\n");
+        assert!(s.is_done());
+        assert_eq!(memory.messages.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_serve() -> Result<()> {
+        let memory = Arc::new(Memory::default());
+        *memory.messages.lock().unwrap() = vec![(1, "Subject: hi\r\n\r\nhello\r\n")];
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(serve(listener, memory.clone(), Arc::new(OneAccount)));
+
+        let stream = TcpStream::connect(addr).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        assert!(lines.next_line().await?.unwrap().starts_with("+OK"));
+        writer.write_all(b"USER me@x.com\r\nPASS secret\r\nRETR 1\r\nQUIT\r\n").await?;
+        let mut replies = vec![];
+        while let Some(line) = lines.next_line().await? {
+            replies.push(line);
+        }
+        assert_eq!(replies, vec!["+OK send PASS", "+OK 1 messages", "+OK 22 octets", "Subject: hi", "", "hello", ".", "+OK bye, 0 deleted"]);
+        Ok(())
+    }
+}
@@ -1,15 +1,24 @@
 use std::io;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
 use anyhow::Result;
 use async_channel::{Receiver, Sender};
-use log::info;
+use log::{error, info, warn};
 use mailin::AuthMechanism;
 use mailin_embedded::{Handler, SslConfig};
 use mailin_embedded::response::{self, Response};
 
 use crate::models::message::Message;
 
+/// 25 MB, what most providers accept.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 25 * 1024 * 1024;
+
+/// checks AUTH PLAIN credentials, called from the smtp worker threads.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, username: &str, password: &str) -> bool;
+}
+
 pub struct Server(mailin_embedded::Server<MyHandler>);
 
 impl Server {
@@ -23,7 +32,8 @@ pub struct Builder {
     ssl_config: SslConfig,
     socket: Option<SocketAddr>,
     auth: bool,
-
+    authenticator: Option<Arc<dyn Authenticator>>,
+    max_message_size: usize,
 }
 
 impl Default for Builder {
@@ -39,15 +49,30 @@ impl Builder {
             ssl_config: SslConfig::None,
             socket: None,
             auth: false,
+            authenticator: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
         }
     }
 
 
+    /// clients have to AUTH before MAIL, every credential is refused without an authenticator.
+    /// mailin only takes AUTH PLAIN after STARTTLS, so without `with_ssl` nobody can send.
     pub fn with_auth(mut self, value: bool) -> Self {
         self.auth = value;
         self
     }
 
+    pub fn with_authenticator(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// bigger messages are read to the end of DATA, then rejected with 552.
+    pub fn with_max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
     pub fn with_ssl(mut self, cert_path: Option<String>, key_path: Option<String>) -> Self {
         if let (Some(cert_path), Some(key_path)) = (cert_path, key_path) {
             self.ssl_config = SslConfig::SelfSigned {
@@ -65,10 +90,8 @@ impl Builder {
 
     pub fn build(self) -> (Server, Receiver<Message>) {
         let (tx, rx) = async_channel::unbounded();
-        let handler = MyHandler {
-            data: vec![],
-            tx,
-        };
+        let mut handler = MyHandler::new(tx, self.max_message_size);
+        handler.authenticator = self.authenticator.clone();
         let mut server = mailin_embedded::Server::new(handler);
 
         server
@@ -87,34 +110,249 @@ impl Builder {
     }
 }
 
+/// why a message was refused at the end of DATA.
+#[derive(Debug)]
+pub enum Rejection {
+    TooLarge,
+    Malformed(anyhow::Error),
+}
+
 #[derive(Clone)]
 pub struct MyHandler {
     pub data: Vec<u8>,
+    pub envelope_from: String,
+    pub envelope_recipients: Vec<String>,
+    /// the client of the session and its HELO name, for the sender checks.
+    pub remote_ip: Option<IpAddr>,
+    pub helo: String,
+    /// the username of a successful AUTH in this session.
+    pub authenticated: Option<String>,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    max_message_size: usize,
+    too_large: bool,
     tx: Sender<Message>
 }
 
+impl MyHandler {
+    pub fn new(tx: Sender<Message>, max_message_size: usize) -> Self {
+        MyHandler {
+            data: vec![],
+            envelope_from: "".to_string(),
+            envelope_recipients: vec![],
+            remote_ip: None,
+            helo: "".to_string(),
+            authenticated: None,
+            authenticator: None,
+            max_message_size,
+            too_large: false,
+            tx,
+        }
+    }
+
+    /// the message of the current transaction, the buffer is empty afterwards either way.
+    pub fn take_message(&mut self) -> std::result::Result<Message, Rejection> {
+        let data = std::mem::take(&mut self.data);
+        if std::mem::take(&mut self.too_large) {
+            return Err(Rejection::TooLarge);
+        }
+        let mut message = Message::from(&data).map_err(Rejection::Malformed)?;
+        message.envelope_from = self.envelope_from.to_string();
+        message.envelope_recipients = self.envelope_recipients.clone();
+        message.remote_ip = self.remote_ip;
+        message.helo = self.helo.to_string();
+        Ok(message)
+    }
+
+    /// mailin only gets here once STARTTLS ran.
+    pub fn authenticate(&mut self, authorization_id: &str, username: &str, password: &str) -> bool {
+        // acting on behalf of another user isn't supported.
+        let valid = (authorization_id.is_empty() || authorization_id == username)
+            && self.authenticator.as_ref().map(|a| a.authenticate(username, password)).unwrap_or(false);
+        if !valid {
+            warn!("smtp auth failed for {}", username);
+            return false;
+        }
+        self.authenticated = Some(username.to_string());
+        true
+    }
+}
+
 impl Handler for MyHandler {
+    fn mail(&mut self, ip: IpAddr, domain: &str, _from: &str) -> Response {
+        self.remote_ip = Some(ip);
+        self.helo = domain.to_string();
+
+        response::OK
+    }
+
+    fn data_start(&mut self, _domain: &str, from: &str, _is8bit: bool, to: &[String]) -> Response {
+        // the envelope of this message, bcc recipients only ever show up here.
+        self.envelope_from = from.to_string();
+        self.envelope_recipients = to.to_vec();
+        self.data.clear();
+        self.too_large = false;
+
+        response::OK
+    }
+
     fn data(&mut self, buf: &[u8]) -> io::Result<()> {
-        self.data.append(&mut buf.to_owned());
+        if self.too_large || self.data.len() + buf.len() > self.max_message_size {
+            // the client keeps sending till the final dot anyway, drop it and answer in data_end.
+            self.too_large = true;
+            self.data.clear();
+            return Ok(());
+        }
+        self.data.extend_from_slice(buf);
 
         Ok(())
     }
 
     fn data_end(&mut self) -> mailin_embedded::Response {
-        let message = Message::from(&self.data).unwrap();
+        let message = match self.take_message() {
+            Ok(message) => message,
+            Err(Rejection::TooLarge) => {
+                warn!("rejecting message from {} : larger than {} bytes", self.envelope_from, self.max_message_size);
+                return Response::custom(552, format!("5.3.4 message exceeds the maximum size of {} bytes", self.max_message_size));
+            }
+            Err(Rejection::Malformed(e)) => {
+                warn!("rejecting malformed message from {} : {:?}", self.envelope_from, e);
+                return Response::custom(554, "5.6.0 malformed message".to_string());
+            }
+        };
 
-        // info!("message>> {:?}", message);
-        self.tx.send_blocking(message).unwrap();
+        if let Err(e) = self.tx.send_blocking(message) {
+            error!("mail receiver is gone : {:?}", e);
+            return response::INTERNAL_ERROR;
+        }
 
         mailin_embedded::response::OK
     }
 
     fn auth_plain(
         &mut self,
-        _authorization_id: &str,
+        authorization_id: &str,
         authentication_id: &str,
         password: &str,
     ) -> Response {
-        response::AUTH_OK
+        if self.authenticate(authorization_id, authentication_id, password) {
+            response::AUTH_OK
+        } else {
+            response::INVALID_CREDENTIALS
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler(max_message_size: usize) -> (MyHandler, Receiver<Message>) {
+        let (tx, rx) = async_channel::unbounded();
+        (MyHandler::new(tx, max_message_size), rx)
+    }
+
+    fn send(h: &mut MyHandler, to: &[&str], data: &str) -> std::result::Result<Message, Rejection> {
+        let to: Vec<String> = to.iter().map(|t| t.to_string()).collect();
+        h.mail("192.0.2.1".parse().unwrap(), "client.example", "me@fromdomain.com");
+        h.data_start("client.example", "me@fromdomain.com", false, &to);
+        for line in data.split_inclusive('\n') {
+            h.data(line.as_bytes()).unwrap();
+        }
+        h.take_message()
+    }
+
+    #[test]
+    fn test_buffer_is_reset_per_message() {
+        let (mut h, _rx) = handler(DEFAULT_MAX_MESSAGE_SIZE);
+        let first = send(&mut h, &["a@x.com"], "Subject: first\n\none\n").unwrap();
+        let second = send(&mut h, &["b@x.com", "c@x.com"], "Subject: second\n\ntwo\n").unwrap();
+
+        assert_eq!(first.subject, "first");
+        assert_eq!(second.subject, "second");
+        assert!(!String::from_utf8_lossy(&second.source).contains("first"));
+        assert_eq!(second.envelope_from, "me@fromdomain.com");
+        assert_eq!(second.envelope_recipients, vec!["b@x.com", "c@x.com"]);
+        assert_eq!(second.remote_ip, Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(second.helo, "client.example");
+        assert!(h.data.is_empty());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let (mut h, _rx) = handler(64);
+        let big = format!("Subject: big\n\n{}\n", "x".repeat(100));
+        assert!(matches!(send(&mut h, &["a@x.com"], &big), Err(Rejection::TooLarge)));
+        assert!(h.data.is_empty());
+
+        // the next transaction on the connection starts over.
+        assert_eq!(send(&mut h, &["a@x.com"], "Subject: small\n\nok\n").unwrap().subject, "small");
+    }
+
+    struct Accounts;
+
+    impl Authenticator for Accounts {
+        fn authenticate(&self, username: &str, password: &str) -> bool {
+            (username, password) == ("me@x.com", "secret")
+        }
+    }
+
+    fn session(tls: bool) -> (mailin::Session<MyHandler>, Receiver<Message>) {
+        let (mut h, rx) = handler(DEFAULT_MAX_MESSAGE_SIZE);
+        h.authenticator = Some(Arc::new(Accounts));
+        let mut builder = mailin::SessionBuilder::new("mx.example");
+        builder.enable_auth(AuthMechanism::Plain);
+        if tls {
+            builder.enable_start_tls();
+        }
+        let mut session = builder.build("192.0.2.1".parse().unwrap(), h);
+        if tls {
+            assert_eq!(session.process(b"ehlo client.example\r\n").code, 250);
+            assert_eq!(session.process(b"starttls\r\n").code, 220);
+            session.tls_active();
+        }
+        assert_eq!(session.process(b"ehlo client.example\r\n").code, 250);
+        (session, rx)
+    }
+
+    #[test]
+    fn test_auth_session() {
+        // no STARTTLS, no AUTH, and no MAIL without it.
+        let (mut s, _rx) = session(false);
+        assert_eq!(s.process(b"auth plain AG1lQHguY29tAHNlY3JldA==\r\n").code, 503);
+        assert_eq!(s.process(b"mail from:<me@x.com>\r\n").code, 503);
+
+        let (mut s, rx) = session(true);
+        assert_eq!(s.process(b"mail from:<me@x.com>\r\n").code, 503);
+        assert_eq!(s.process(b"auth plain AG1lQHguY29tAHdyb25n\r\n").code, 535);
+        // acting on behalf of another user.
+        assert_eq!(s.process(b"auth plain YWRtaW4AbWVAeC5jb20Ac2VjcmV0\r\n").code, 535);
+        assert_eq!(s.process(b"auth plain AG1lQHguY29tAHNlY3JldA==\r\n").code, 235);
+
+        assert_eq!(s.process(b"mail from:<me@x.com>\r\n").code, 250);
+        assert_eq!(s.process(b"rcpt to:<a@x.com>\r\n").code, 250);
+        assert_eq!(s.process(b"data\r\n").code, 354);
+        s.process(b"Subject: hi\r\n");
+        s.process(b"\r\n");
+        s.process(b"hello\r\n");
+        assert_eq!(s.process(b".\r\n").code, 250);
+        assert_eq!(rx.try_recv().unwrap().subject, "hi");
+    }
+
+    #[test]
+    fn test_no_authenticator() {
+        let (mut h, _rx) = handler(DEFAULT_MAX_MESSAGE_SIZE);
+        assert!(!h.authenticate("", "me@x.com", "secret"));
+        assert_eq!(h.authenticated, None);
+        h.authenticator = Some(Arc::new(Accounts));
+        assert!(h.authenticate("me@x.com", "me@x.com", "secret"));
+        assert_eq!(h.authenticated.as_deref(), Some("me@x.com"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let (mut h, _rx) = handler(DEFAULT_MAX_MESSAGE_SIZE);
+        assert!(matches!(send(&mut h, &["a@x.com"], ""), Err(Rejection::Malformed(_))));
+        assert!(matches!(send(&mut h, &["a@x.com"], "\r\n\r\n"), Err(Rejection::Malformed(_))));
     }
 }
@@ -0,0 +1,67 @@
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use mail_server::outbound::{Mailer, OutgoingAttachment, OutgoingMail, SendError, SmarthostConfig, SmarthostTls};
+use mail_server::smtp::Builder;
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// our own smtp server, standing in for the smarthost.
+fn start_sink(auth: bool) -> (SmarthostConfig, async_channel::Receiver<mail_server::models::message::Message>) {
+    let port = free_port();
+    let (server, rx) = Builder::new()
+        .with_auth(auth)
+        .bind(SocketAddr::from(([127, 0, 0, 1], port)))
+        .build();
+    std::thread::spawn(move || server.serve());
+    std::thread::sleep(Duration::from_millis(100));
+    (SmarthostConfig { host: "127.0.0.1".to_string(), port, tls: SmarthostTls::None, ..Default::default() }, rx)
+}
+
+#[tokio::test]
+async fn test_send_through_sink() -> anyhow::Result<()> {
+    let (config, rx) = start_sink(false);
+    let mailer = Mailer::new(&config)?;
+
+    mailer.send(&OutgoingMail {
+        from: "Me <me@x.com>".to_string(),
+        to: vec!["a@x.com".to_string()],
+        cc: vec!["b@x.com".to_string()],
+        bcc: vec!["hidden@x.com".to_string()],
+        subject: "outbound".to_string(),
+        text: "plain".to_string(),
+        html: "<p>html</p>".to_string(),
+        attachments: vec![OutgoingAttachment { filename: "a.txt".to_string(), content_type: "text/plain".to_string(), body: b"attached".to_vec() }],
+        ..Default::default()
+    }).await.map_err(|e| anyhow::anyhow!("{:?}", e))?;
+
+    let message = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await??;
+    assert_eq!(message.subject, "outbound");
+    assert_eq!(message.recipients, vec!["a@x.com"]);
+    assert_eq!(message.cc, vec!["b@x.com"]);
+    assert_eq!(message.envelope_from, "me@x.com");
+    assert_eq!(message.envelope_recipients.len(), 3);
+    assert_eq!(message.bcc(), vec!["hidden@x.com"]);
+    assert_eq!(message.attachments[0].body, b"attached");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rejected_mail_is_permanent() -> anyhow::Result<()> {
+    // the sink wants AUTH, the mailer has no credentials.
+    let (config, _rx) = start_sink(true);
+    let mailer = Mailer::new(&config)?;
+    let r = mailer.send(&OutgoingMail { from: "me@x.com".to_string(), to: vec!["a@x.com".to_string()], text: "hi".to_string(), ..Default::default() }).await;
+    assert!(matches!(r, Err(SendError::Permanent(_))), "{:?}", r);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unreachable_smarthost_is_transient() -> anyhow::Result<()> {
+    let mailer = Mailer::new(&SmarthostConfig { host: "127.0.0.1".to_string(), port: free_port(), tls: SmarthostTls::None, ..Default::default() })?;
+    let r = mailer.send(&OutgoingMail { from: "me@x.com".to_string(), to: vec!["a@x.com".to_string()], text: "hi".to_string(), ..Default::default() }).await;
+    assert!(matches!(r, Err(SendError::Transient(_))), "{:?}", r);
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+
+use proptest::prelude::*;
+
+use mail_server::models::message::Message;
+
+fn corpus(dir: &str) -> Vec<(String, Vec<u8>)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus").join(dir);
+    let mut files: Vec<(String, Vec<u8>)> = fs::read_dir(dir).unwrap()
+        .map(|e| e.unwrap().path())
+        .map(|p| (p.file_name().unwrap().to_string_lossy().to_string(), fs::read(&p).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_valid_corpus() {
+    for (name, data) in corpus("valid") {
+        let message = Message::from(&data).unwrap_or_else(|e| panic!("{} : {:?}", name, e));
+        assert_eq!(message.source, data, "{}", name);
+        assert!(!message.subject.is_empty(), "{}", name);
+    }
+}
+
+#[test]
+fn test_many_recipients() {
+    let data = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/valid/many_recipients.eml")).unwrap();
+    let message = Message::from(&data).unwrap();
+    assert_eq!(message.sender, "Last, First me@fromdomain.com");
+    assert_eq!(message.recipients, vec!["a@todomain.com", "b@todomain.com", "c@todomain.com"]);
+    assert_eq!(message.cc, vec!["d@todomain.com", "e@todomain.com"]);
+}
+
+/// the mailer answers 554 for these, as long as nothing panics the session survives.
+#[test]
+fn test_malformed_corpus_does_not_panic() {
+    for (name, data) in corpus("malformed") {
+        let r = std::panic::catch_unwind(|| Message::from(&data).map(|_| ()));
+        assert!(r.is_ok(), "{} panicked", name);
+    }
+    for name in ["empty.eml", "whitespace.eml"] {
+        assert!(Message::from(&fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/malformed").join(name)).unwrap()).is_err());
+    }
+}
+
+proptest! {
+    #[test]
+    fn parse_never_panics(data in proptest::collection::vec(any::<u8>(), 0..4096)) {
+        let _ = Message::from(&data);
+    }
+
+    #[test]
+    fn parse_header_soup_never_panics(headers in proptest::collection::vec("[A-Za-z-]{1,20}: [^\r\n]{0,80}", 0..20), body in ".{0,200}") {
+        let data = format!("{}\r\n\r\n{}", headers.join("\r\n"), body).into_bytes();
+        let _ = Message::from(&data);
+    }
+
+    #[test]
+    fn truncated_corpus_never_panics(index in 0usize..64, cut in 0usize..4096) {
+        let files = corpus("valid");
+        let (_, data) = &files[index % files.len()];
+        let _ = Message::from(&data[..cut.min(data.len())].to_vec());
+    }
+}
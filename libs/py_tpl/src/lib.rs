@@ -4,4 +4,5 @@ mod py_runner;
 
 // the name `TplEngine` should always be.
 pub type TplEngine = py_runner::PyRunner;
+pub use py_runner::OUTGOING_MAILS;
 pub static TEMPLATES_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/../../server/templates");
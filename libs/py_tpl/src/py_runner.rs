@@ -57,11 +57,24 @@ fn read_file(filename : String) -> PyResult<String> {
     Ok(c)
 }
 
+lazy_static! {
+    /// mails queued by templates through `foo.send_mail`, the server drains them into its outbox.
+    pub static ref OUTGOING_MAILS: (async_channel::Sender<String>, Receiver<String>) = async_channel::unbounded();
+}
+
+/// `foo.send_mail(json.dumps({"from": .., "to": [..], "subject": .., "text": ..}))`
+#[pyfunction]
+fn send_mail(mail_json: String) -> PyResult<()> {
+    OUTGOING_MAILS.0.try_send(mail_json)
+        .map_err(|e| pyo3::exceptions::PyRuntimeError::new_err(e.to_string()))
+}
+
 #[pymodule]
 fn foo(_py: Python<'_>, foo_module: &PyModule) -> PyResult<()> {
     foo_module.add_function(wrap_pyfunction!(add_one, foo_module)?)?;
     foo_module.add_function(wrap_pyfunction!(read_file, foo_module)?)?;
     foo_module.add_function(wrap_pyfunction!(parse_create_sql_str, foo_module)?)?;
+    foo_module.add_function(wrap_pyfunction!(send_mail, foo_module)?)?;
     Ok(())
 }
 
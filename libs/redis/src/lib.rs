@@ -1,4 +1,77 @@
+#[cfg(feature = "single")]
 mod redis_single_service;
+#[cfg(feature = "cluster")]
+mod redis_cluster_service;
+#[cfg(test)]
+mod stand_in;
 
+use anyhow::bail;
+use tracing::warn;
+
+use shared::redis_api::RedisAPI;
+
+/// lua for `RedisAPI::del_if_eq`, a GET then DEL from the client could delete someone else's value.
+const DEL_IF_EQ: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+/// lua for `RedisAPI::expire_if_eq`
+const EXPIRE_IF_EQ: &str = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('EXPIRE', KEYS[1], ARGV[2]) else return 0 end";
+
+#[cfg(feature = "single")]
 pub type RedisService = redis_single_service::RedisService;
+#[cfg(feature = "cluster")]
+pub type RedisClusterService = redis_cluster_service::RedisClusterService;
+
+///
+/// connect to redis, `mode` is `single`, `cluster`, or empty to go by the uri count.
+/// empty only picks cluster when the `cluster` feature is compiled in, and means cluster when it's the only one.
+#[allow(unreachable_code)]
+pub async fn connect(redis_uri: Vec<String>, mode: &str, is_test: bool) -> anyhow::Result<Box<dyn RedisAPI + Send + Sync>> {
+    let cluster = match mode {
+        "cluster" => true,
+        "single" => false,
+        "" if cfg!(not(feature = "cluster")) => {
+            if redis_uri.len() > 1 {
+                warn!("{} redis uris but the `cluster` feature isn't compiled in, connecting to {} alone.", redis_uri.len(), redis_uri[0]);
+            }
+            false
+        }
+        "" => redis_uri.len() > 1 || cfg!(not(feature = "single")),
+        _ => bail!("unknown redis mode : {}", mode),
+    };
+
+    if cluster {
+        #[cfg(feature = "cluster")]
+        return Ok(Box::new(RedisClusterService::new(redis_uri, is_test).await?));
+    } else {
+        #[cfg(feature = "single")]
+        return Ok(Box::new(RedisService::new(redis_uri, is_test).await?));
+    }
+    bail!("redis {} mode is not compiled in, check the `single` / `cluster` features of libs/redis.", if cluster { "cluster" } else { "single" })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect() -> anyhow::Result<()> {
+        let uri = stand_in::start().await;
+        assert!(connect(vec![uri.to_string()], "sentinel", true).await.is_err());
+
+        for mode in ["", "single", "cluster"] {
+            let r = connect(vec![uri.to_string()], mode, true).await;
+            if r.is_err() {
+                // the mode isn't compiled in.
+                continue;
+            }
+            let service = r?;
+            service.set("mode", mode).await?;
+            assert_eq!(service.get("mode").await?, mode);
+        }
 
+        // without the cluster feature, more uris still connect to the first one.
+        #[cfg(not(feature = "cluster"))]
+        connect(vec![uri.to_string(), "redis://127.0.0.1:1".to_string()], "", true).await?.set("mode", "first").await?;
+        Ok(())
+    }
+}
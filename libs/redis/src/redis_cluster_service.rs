@@ -1,105 +1,280 @@
-use std::time::Duration;
-use bb8_redis::{
-    bb8,
-    redis::AsyncCommands,
-    RedisConnectionManager,
-};
-use tracing::instrument::WithSubscriber;
-
-use crate::service::redis::redis_mock_service;
+use std::collections::{BTreeSet, HashMap};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
 use futures_util::StreamExt;
-use tracing::{error, info};
+use redis_rs::{AsyncCommands, ConnectionAddr, ConnectionInfo, IntoConnectionInfo};
+use redis_rs::cluster::ClusterClient;
+use redis_rs::cluster_async::ClusterConnection;
 
-pub type RedisPool = bb8::Pool<RedisConnectionManager>;
+use shared::redis_api::RedisAPI;
 
-pub struct RedisService {
-    pool: Option<RedisPool>,
-    test_pool: Option<redis_mock_service::RedisService>,
-    is_test: bool,
+///
+/// redis cluster backed `RedisAPI`, every configured uri is used as a seed node.
+/// the cluster connection is multiplexed and cheap to clone, so there's no pool.
+pub struct RedisClusterService {
+    conn: ClusterConnection,
+    redis_uri: Vec<String>,
 }
 
-impl RedisService {
-
-    pub async fn new(redis_uri: Vec<String>, is_test: bool) -> anyhow::Result<Self> {
-        if is_test {
-            let test_redis_service = redis_mock_service::RedisService::new(redis_uri).await.unwrap();
-            Ok(Self {
-                pool: None,
-                test_pool: Some(test_redis_service),
-                is_test,
-            })
-        } else {
-            let manager = RedisConnectionManager::new(redis_uri.get(0).unwrap().as_str())?;
-            let pool = bb8::Pool::builder()
-                .connection_timeout(Duration::from_secs(1))
-                .max_size(100)
-                .build(manager).await?;
-
-
-            let r = Self {
-                pool: Some(pool),
-                test_pool: None,
-                is_test,
-            };
 
+impl RedisClusterService {
+    /// the masters of the cluster, as told by the first seed that answers.
+    async fn masters(&self) -> anyhow::Result<Vec<ConnectionInfo>> {
+        let mut last_error = anyhow::anyhow!("no redis uri configured.");
+        for uri in &self.redis_uri {
+            match masters_from(uri).await {
+                Ok(masters) => return Ok(masters),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+async fn masters_from(seed: &str) -> anyhow::Result<Vec<ConnectionInfo>> {
+    let seed = seed.into_connection_info()?;
+    let mut conn = redis_rs::Client::open(seed.clone())?.get_async_connection().await?;
+    let slots: Vec<redis_rs::Value> = redis_rs::cmd("CLUSTER").arg("SLOTS").query_async(&mut conn).await?;
+    let seed_host = match &seed.addr {
+        ConnectionAddr::Tcp(host, _) | ConnectionAddr::TcpTls { host, .. } => host.to_string(),
+        ConnectionAddr::Unix(_) => String::new(),
+    };
+    Ok(slot_masters(&slots).into_iter().map(|(host, port)| {
+        // an empty ip is the node that answered.
+        let host = if host.is_empty() { seed_host.to_string() } else { host };
+        // the password and tls of the seed go for every node.
+        let mut info = seed.clone();
+        match &mut info.addr {
+            ConnectionAddr::Tcp(h, p) | ConnectionAddr::TcpTls { host: h, port: p, .. } => (*h, *p) = (host, port),
+            ConnectionAddr::Unix(_) => info.addr = ConnectionAddr::Tcp(host, port),
+        }
+        info
+    }).collect())
+}
 
+/// the `[ip, port, id]` after the start and end of every `CLUSTER SLOTS` range is its master.
+fn slot_masters(slots: &[redis_rs::Value]) -> BTreeSet<(String, u16)> {
+    slots.iter().filter_map(|range| match range {
+        redis_rs::Value::Bulk(range) => match range.get(2) {
+            Some(redis_rs::Value::Bulk(master)) if master.len() >= 2 => Some((
+                redis_rs::from_redis_value::<String>(&master[0]).ok()?,
+                redis_rs::from_redis_value::<u16>(&master[1]).ok()?,
+            )),
+            _ => None,
+        },
+        _ => None,
+    }).collect()
+}
 
-            //start a subscribe task.
-            r.initialise_subscriptions();
+#[async_trait]
+impl RedisAPI for RedisClusterService {
+    async fn new(redis_uri: Vec<String>, _is_test: bool) -> anyhow::Result<Self> {
+        let client = ClusterClient::new(redis_uri.clone())?;
+        let conn = client.get_async_connection().await?;
+        Ok(Self {
+            conn,
+            redis_uri,
+        })
+    }
 
-            Ok(r)
-        }
+    async fn set(&self, key: &str, val: &str) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        conn.set::<_, _, ()>(key, val).await?;
+        Ok(())
     }
 
+    async fn get(&self, key: &str) -> anyhow::Result<String> {
+        let mut conn = self.conn.clone();
+        Ok(conn.get(key).await?)
+    }
 
-    pub async fn set(&self, key: &str, val: &str) -> anyhow::Result<()> {
-        if self.is_test {
-            self.test_pool.as_ref().unwrap().set(key, val).await
-        } else {
-            let mut conn = self.pool.as_ref().unwrap().get().await?;
+    async fn publish(&self, channel: &str, message: &str) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        conn.publish::<_, _, ()>(channel, message).await?;
+        Ok(())
+    }
 
-            conn.set(key, val).await?;
-            Ok(())
+    async fn subscribe(&self, channel: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        // cluster PUBLISH reaches every node, so listening on any one of them is enough.
+        let mut last_error = anyhow::anyhow!("no redis uri configured.");
+        for uri in &self.redis_uri {
+            let conn = match redis_rs::Client::open(uri.as_str()) {
+                Ok(client) => client.get_async_connection().await,
+                Err(e) => Err(e),
+            };
+            match conn {
+                Ok(conn) => {
+                    let mut pubsub = conn.into_pubsub();
+                    pubsub.subscribe(channel).await?;
+                    return Ok(pubsub.into_on_message()
+                        .filter_map(|msg| async move { msg.get_payload::<String>().ok() })
+                        .boxed());
+                }
+                Err(e) => last_error = e.into(),
+            }
         }
+        Err(last_error)
     }
 
-    pub async fn get(&self, key: &str) -> anyhow::Result<String> {
-        if self.is_test {
-            self.test_pool.as_ref().unwrap().get(key).await
-        } else {
-            let mut conn = self.pool.as_ref().unwrap().get().await?;
+    async fn set_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        conn.set_ex::<_, _, ()>(key, val, seconds as usize).await?;
+        Ok(())
+    }
 
-            Ok(conn.get(key).await?)
-        }
+    async fn del(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.del::<_, i64>(key).await? > 0)
     }
-    pub async fn publish(&self, channel: &str, message: &str) -> anyhow::Result<()> {
-        if self.is_test {
-            error!("not implemented 'publish' for test pool.");
-            Ok(())
-        } else {
-            let mut conn = self.pool.as_ref().unwrap().get().await?;
-            conn.publish(channel, message).await?;
-            Ok(())
-        }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.exists(key).await?)
     }
 
-    // This is the main bit here
-    fn initialise_subscriptions(&self) {
-        let pool = self.pool.as_ref().unwrap().clone();
-        tokio::task::spawn(async move {
-            info!("Started task");
-            let conn = bb8::Pool::dedicated_connection(&pool).await.unwrap();
-            let mut pubsub = conn.into_pubsub();
-            let subscribed = pubsub.subscribe("a").await;
-            info!("Subscribed Response: {:?}", subscribed);
-            while let Some(result) = pubsub.on_message().next().await {
-                let payload = result.get_payload::<String>().unwrap();
+    async fn expire(&self, key: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.expire(key, seconds as usize).await?)
+    }
+
+    async fn incr_by(&self, key: &str, delta: i64) -> anyhow::Result<i64> {
+        let mut conn = self.conn.clone();
+        Ok(conn.incr(key, delta).await?)
+    }
 
-                info!("<MSG>: {}", payload);
+    async fn hset(&self, key: &str, field: &str, val: &str) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        conn.hset::<_, _, _, ()>(key, field, val).await?;
+        Ok(())
+    }
 
+    async fn hget(&self, key: &str, field: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.hget(key, field).await?)
+    }
+
+    async fn hgetall(&self, key: &str) -> anyhow::Result<HashMap<String, String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.hgetall(key).await?)
+    }
+
+    async fn lpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        let mut conn = self.conn.clone();
+        Ok(conn.lpush(key, val).await?)
+    }
+
+    async fn rpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        let mut conn = self.conn.clone();
+        Ok(conn.rpush(key, val).await?)
+    }
+
+    async fn lpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.lpop(key, None).await?)
+    }
+
+    async fn rpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.rpop(key, None).await?)
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.lrange(key, start, stop).await?)
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        Ok(conn.sadd::<_, _, i64>(key, member).await? > 0)
+    }
+
+    async fn smembers(&self, key: &str) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.conn.clone();
+        Ok(conn.smembers(key).await?)
+    }
+
+    async fn scan(&self, pattern: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        // SCAN only walks one node, so ask every master once and merge.
+        let mut keys = BTreeSet::new();
+        for master in self.masters().await? {
+            let mut conn = redis_rs::Client::open(master)?.get_async_connection().await?;
+            let mut iter = conn.scan_match::<_, String>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.insert(key);
             }
-        });
+        }
+        Ok(futures::stream::iter(keys).boxed())
+    }
+
+    async fn set_nx_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        let r: Option<String> = redis_rs::cmd("SET").arg(key).arg(val).arg("NX").arg("EX").arg(seconds).query_async(&mut conn).await?;
+        Ok(r.is_some())
+    }
+
+    async fn del_if_eq(&self, key: &str, val: &str) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        let n: i64 = redis_rs::Script::new(crate::DEL_IF_EQ).key(key).arg(val).invoke_async(&mut conn).await?;
+        Ok(n > 0)
+    }
+
+    async fn expire_if_eq(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.conn.clone();
+        let n: i64 = redis_rs::Script::new(crate::EXPIRE_IF_EQ).key(key).arg(val).arg(seconds).invoke_async(&mut conn).await?;
+        Ok(n > 0)
     }
 }
 
 
+#[cfg(test)]
+mod tests {
+    use crate::stand_in;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cluster_service() -> anyhow::Result<()> {
+        let uri = stand_in::start().await;
+        let service = RedisClusterService::new(vec![uri], true).await?;
+
+        service.set("a", "1").await?;
+        assert_eq!(service.get("a").await?, "1");
+        assert!(service.get("missing").await.is_err());
+
+        let mut stream = service.subscribe("ch").await?;
+        service.publish("ch", "hello").await?;
+        assert_eq!(stream.next().await.unwrap(), "hello");
+
+        shared::redis_conformance::conformance_suite(&service, "cluster").await
+    }
+
+    #[tokio::test]
+    async fn test_scan_past_a_down_seed() -> anyhow::Result<()> {
+        let uri = stand_in::start().await;
+        // nothing listens on port 1, so the masters come from the next seed. the stand-in is listed
+        // twice but is a single master, so its keys are scanned once.
+        let service = RedisClusterService::new(vec!["redis://127.0.0.1:1".to_string(), uri.to_string(), uri], true).await?;
+        service.set("scan:a", "1").await?;
+        service.set("scan:b", "1").await?;
+
+        assert_eq!(service.masters().await?.len(), 1);
+        let keys: Vec<String> = service.scan("scan:*").await?.collect().await;
+        assert_eq!(keys, vec!["scan:a", "scan:b"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_slot_masters() {
+        use redis_rs::Value::{Bulk, Data, Int};
+        let node = |ip: &str, port: i64| Bulk(vec![Data(ip.as_bytes().to_vec()), Int(port), Data(b"id".to_vec())]);
+        let slots = vec![
+            Bulk(vec![Int(0), Int(5460), node("10.0.0.1", 6379), node("10.0.0.4", 6379)]),
+            Bulk(vec![Int(5461), Int(10922), node("10.0.0.2", 6379)]),
+            Bulk(vec![Int(10923), Int(16383), node("10.0.0.1", 6379)]),
+            Int(1),
+        ];
+        assert_eq!(slot_masters(&slots).into_iter().collect::<Vec<_>>(),
+                   vec![("10.0.0.1".to_string(), 6379), ("10.0.0.2".to_string(), 6379)]);
+    }
+}
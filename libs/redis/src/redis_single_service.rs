@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -6,6 +7,8 @@ use bb8_redis::{
     redis::AsyncCommands,
     RedisConnectionManager,
 };
+use futures::stream::BoxStream;
+use futures_util::StreamExt;
 
 use shared::redis_api::RedisAPI;
 
@@ -32,9 +35,6 @@ impl RedisAPI for RedisService {
         };
 
 
-        //start a subscribe task.
-        // r.initialise_subscriptions();
-
         Ok(r)
     }
     async fn set(&self, key: &str, val: &str) -> anyhow::Result<()> {
@@ -54,6 +54,143 @@ impl RedisAPI for RedisService {
         conn.publish(channel, message).await?;
         Ok(())
     }
+
+    async fn subscribe(&self, channel: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        // a subscribed connection can't run other commands, so it is taken out of the pool.
+        let conn = self.pool.dedicated_connection().await?;
+        let mut pubsub = conn.into_pubsub();
+        pubsub.subscribe(channel).await?;
+        Ok(pubsub.into_on_message()
+            .filter_map(|msg| async move { msg.get_payload::<String>().ok() })
+            .boxed())
+    }
+
+    async fn set_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        conn.set_ex::<_, _, ()>(key, val, seconds as usize).await?;
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.del::<_, i64>(key).await? > 0)
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.exists(key).await?)
+    }
+
+    async fn expire(&self, key: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.expire(key, seconds as usize).await?)
+    }
+
+    async fn incr_by(&self, key: &str, delta: i64) -> anyhow::Result<i64> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.incr(key, delta).await?)
+    }
+
+    async fn hset(&self, key: &str, field: &str, val: &str) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        conn.hset::<_, _, _, ()>(key, field, val).await?;
+        Ok(())
+    }
+
+    async fn hget(&self, key: &str, field: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.hget(key, field).await?)
+    }
+
+    async fn hgetall(&self, key: &str) -> anyhow::Result<HashMap<String, String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.hgetall(key).await?)
+    }
+
+    async fn lpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.lpush(key, val).await?)
+    }
+
+    async fn rpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.rpush(key, val).await?)
+    }
+
+    async fn lpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.lpop(key, None).await?)
+    }
+
+    async fn rpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.rpop(key, None).await?)
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.lrange(key, start, stop).await?)
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.sadd::<_, _, i64>(key, member).await? > 0)
+    }
+
+    async fn smembers(&self, key: &str) -> anyhow::Result<Vec<String>> {
+        let mut conn = self.pool.get().await?;
+        Ok(conn.smembers(key).await?)
+    }
+
+    async fn scan(&self, pattern: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        let mut conn = self.pool.get().await?;
+        // the cursor borrows the connection, so collect before handing the keys out.
+        let mut iter = conn.scan_match::<_, String>(pattern).await?;
+        let mut keys = vec![];
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        Ok(futures::stream::iter(keys).boxed())
+    }
+
+    async fn set_nx_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        let r: Option<String> = bb8_redis::redis::cmd("SET").arg(key).arg(val).arg("NX").arg("EX").arg(seconds).query_async(&mut *conn).await?;
+        Ok(r.is_some())
+    }
+
+    async fn del_if_eq(&self, key: &str, val: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        let n: i64 = bb8_redis::redis::Script::new(crate::DEL_IF_EQ).key(key).arg(val).invoke_async(&mut *conn).await?;
+        Ok(n > 0)
+    }
+
+    async fn expire_if_eq(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get().await?;
+        let n: i64 = bb8_redis::redis::Script::new(crate::EXPIRE_IF_EQ).key(key).arg(val).arg(seconds).invoke_async(&mut *conn).await?;
+        Ok(n > 0)
+    }
 }
 
 
+#[cfg(test)]
+mod tests {
+    use crate::stand_in;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_service() -> anyhow::Result<()> {
+        let uri = stand_in::start().await;
+        let service = RedisService::new(vec![uri], true).await?;
+
+        service.set("a", "1").await?;
+        assert_eq!(service.get("a").await?, "1");
+
+        let mut stream = service.subscribe("ch").await?;
+        service.publish("ch", "hello").await?;
+        assert_eq!(stream.next().await.unwrap(), "hello");
+
+        shared::redis_conformance::conformance_suite(&service, "single").await
+    }
+}
@@ -0,0 +1,318 @@
+//!
+//! a tiny in-process redis speaking just enough RESP for the tests.
+//! it claims every cluster slot, so it passes for a one node cluster as well.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+
+#[cfg(feature = "cluster")]
+use redis_rs as client;
+#[cfg(not(feature = "cluster"))]
+use bb8_redis::redis as client;
+
+enum Value {
+    Str(String),
+    Hash(HashMap<String, String>),
+    List(VecDeque<String>),
+    Set(BTreeSet<String>),
+}
+
+#[derive(Default)]
+struct Store {
+    data: HashMap<String, (Value, Option<Instant>)>,
+}
+
+impl Store {
+    /// drops the key first when it has expired.
+    fn live(&mut self, key: &str) -> Option<&mut Value> {
+        if matches!(self.data.get(key), Some((_, Some(at))) if *at <= Instant::now()) {
+            self.data.remove(key);
+        }
+        self.data.get_mut(key).map(|(v, _)| v)
+    }
+
+    fn entry(&mut self, key: &str, default: fn() -> Value) -> &mut Value {
+        if self.live(key).is_none() {
+            self.data.insert(key.to_string(), (default(), None));
+        }
+        &mut self.data.get_mut(key).unwrap().0
+    }
+}
+
+#[derive(Clone)]
+struct Shared {
+    store: Arc<Mutex<Store>>,
+    messages: broadcast::Sender<(String, String)>,
+    port: u16,
+}
+
+/// start a stand-in on a random port, returns its `redis://` uri.
+pub async fn start() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let shared = Shared {
+        store: Arc::new(Mutex::new(Store::default())),
+        messages: broadcast::channel(1024).0,
+        port,
+    };
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, shared.clone()));
+        }
+    });
+    format!("redis://127.0.0.1:{}", port)
+}
+
+async fn read_command(reader: &mut BufReader<OwnedReadHalf>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+        let mut buf = vec![0u8; len + 2];
+        reader.read_exact(&mut buf).await.ok()?;
+        buf.truncate(len);
+        args.push(String::from_utf8_lossy(&buf).to_string());
+    }
+    Some(args)
+}
+
+fn bulk(s: &str) -> String {
+    format!("${}\r\n{}\r\n", s.len(), s)
+}
+
+fn array(items: &[String]) -> String {
+    format!("*{}\r\n{}", items.len(), items.concat())
+}
+
+async fn serve(stream: TcpStream, shared: Shared) {
+    let (read, mut write) = stream.into_split();
+
+    // commands are parsed in their own task, so waiting for them never loses half a frame.
+    let (commands, mut incoming) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(read);
+        while let Some(cmd) = read_command(&mut reader).await {
+            if commands.send(cmd).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut messages = shared.messages.subscribe();
+    let mut channels: Vec<String> = vec![];
+    loop {
+        let reply = tokio::select! {
+            cmd = incoming.recv() => match cmd {
+                Some(cmd) => {
+                    let was_subscribed = !channels.is_empty();
+                    let reply = execute(&shared, &mut channels, cmd);
+                    // only what is published from now on counts.
+                    if !was_subscribed && !channels.is_empty() {
+                        messages = shared.messages.subscribe();
+                    }
+                    reply
+                }
+                None => return,
+            },
+            msg = messages.recv(), if !channels.is_empty() => match msg {
+                Ok((channel, payload)) if channels.contains(&channel) =>
+                    array(&[bulk("message"), bulk(&channel), bulk(&payload)]),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(_) => return,
+            },
+        };
+        if write.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+const WRONG_TYPE: &str = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+
+fn int(n: usize) -> String {
+    format!(":{}\r\n", n)
+}
+
+fn nil() -> String {
+    "$-1\r\n".to_string()
+}
+
+/// glob match supporting `*` and `?`
+fn glob(pattern: &[u8], s: &[u8]) -> bool {
+    match (pattern.first(), s.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => glob(&pattern[1..], s) || (!s.is_empty() && glob(pattern, &s[1..])),
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &s[1..]),
+        (Some(p), Some(c)) if p == c => glob(&pattern[1..], &s[1..]),
+        _ => false,
+    }
+}
+
+fn execute(shared: &Shared, channels: &mut Vec<String>, cmd: Vec<String>) -> String {
+    let name = cmd.first().map(|c| c.to_uppercase()).unwrap_or_default();
+    let arg = |i: usize| cmd.get(i).cloned().unwrap_or_default();
+    let key = arg(1);
+    let mut store = shared.store.lock().unwrap();
+    let expire_at = |seconds: &str| seconds.parse::<u64>().ok().map(|s| Instant::now() + Duration::from_secs(s));
+
+    match name.as_str() {
+        "PING" => "+PONG\r\n".to_string(),
+        "CLUSTER" if key.eq_ignore_ascii_case("SLOTS") => array(&[array(&[
+            int(0),
+            int(16383),
+            array(&[bulk("127.0.0.1"), int(shared.port as usize), bulk("stand-in")]),
+        ])]),
+        "SET" => {
+            let option = |name: &str| cmd.iter().skip(3).position(|a| a.eq_ignore_ascii_case(name)).map(|i| i + 3);
+            if option("NX").is_some() && store.live(&key).is_some() {
+                return nil();
+            }
+            let ttl = option("EX").and_then(|i| expire_at(&arg(i + 1)));
+            store.data.insert(key, (Value::Str(arg(2)), ttl));
+            "+OK\r\n".to_string()
+        }
+        // only the scripts this crate ships are understood, by their text or sha1.
+        "SCRIPT" if key.eq_ignore_ascii_case("LOAD") => bulk(client::Script::new(&arg(2)).get_hash()),
+        "EVAL" | "EVALSHA" => {
+            let script = [crate::DEL_IF_EQ, crate::EXPIRE_IF_EQ].into_iter()
+                .find(|s| if name == "EVAL" { *s == key } else { client::Script::new(s).get_hash() == key });
+            let (key, val) = (arg(3), arg(4));
+            let holds = matches!(store.live(&key), Some(Value::Str(v)) if *v == val);
+            match script {
+                None => "-NOSCRIPT No matching script.\r\n".to_string(),
+                Some(_) if !holds => int(0),
+                Some(crate::DEL_IF_EQ) => {
+                    store.data.remove(&key);
+                    int(1)
+                }
+                Some(_) => {
+                    store.data.get_mut(&key).unwrap().1 = expire_at(&arg(5));
+                    int(1)
+                }
+            }
+        }
+        "SETEX" => {
+            store.data.insert(key, (Value::Str(arg(3)), expire_at(&arg(2))));
+            "+OK\r\n".to_string()
+        }
+        "GET" => match store.live(&key) {
+            Some(Value::Str(v)) => bulk(v),
+            Some(_) => WRONG_TYPE.to_string(),
+            None => nil(),
+        },
+        "DEL" | "EXISTS" => {
+            let n = cmd[1..].iter().filter(|k| store.live(k).is_some()).count();
+            if name == "DEL" {
+                cmd[1..].iter().for_each(|k| { store.data.remove(k); });
+            }
+            int(n)
+        }
+        "EXPIRE" => match store.live(&key) {
+            Some(_) => {
+                store.data.get_mut(&key).unwrap().1 = expire_at(&arg(2));
+                int(1)
+            }
+            None => int(0),
+        },
+        "INCRBY" => match store.entry(&key, || Value::Str("0".to_string())) {
+            Value::Str(v) => match (v.parse::<i64>(), arg(2).parse::<i64>()) {
+                (Ok(n), Ok(delta)) => {
+                    *v = (n + delta).to_string();
+                    format!(":{}\r\n", n + delta)
+                }
+                _ => "-ERR value is not an integer or out of range\r\n".to_string(),
+            },
+            _ => WRONG_TYPE.to_string(),
+        },
+        "HSET" => match store.entry(&key, || Value::Hash(HashMap::new())) {
+            Value::Hash(h) => int(cmd[2..].chunks(2).filter(|kv| h.insert(kv[0].to_string(), kv.get(1).cloned().unwrap_or_default()).is_none()).count()),
+            _ => WRONG_TYPE.to_string(),
+        },
+        "HGET" => match store.live(&key) {
+            Some(Value::Hash(h)) => h.get(&arg(2)).map(|v| bulk(v)).unwrap_or(nil()),
+            Some(_) => WRONG_TYPE.to_string(),
+            None => nil(),
+        },
+        "HGETALL" => match store.live(&key) {
+            Some(Value::Hash(h)) => array(&h.iter().flat_map(|(k, v)| [bulk(k), bulk(v)]).collect::<Vec<String>>()),
+            Some(_) => WRONG_TYPE.to_string(),
+            None => array(&[]),
+        },
+        "LPUSH" | "RPUSH" => match store.entry(&key, || Value::List(VecDeque::new())) {
+            Value::List(l) => {
+                for v in &cmd[2..] {
+                    if name == "LPUSH" { l.push_front(v.to_string()) } else { l.push_back(v.to_string()) }
+                }
+                int(l.len())
+            }
+            _ => WRONG_TYPE.to_string(),
+        },
+        "LPOP" | "RPOP" => {
+            let (reply, empty) = match store.live(&key) {
+                Some(Value::List(l)) => {
+                    let v = if name == "LPOP" { l.pop_front() } else { l.pop_back() };
+                    (v.map(|v| bulk(&v)).unwrap_or(nil()), l.is_empty())
+                }
+                Some(_) => (WRONG_TYPE.to_string(), false),
+                None => (nil(), false),
+            };
+            // like redis, an emptied list is gone.
+            if empty {
+                store.data.remove(&key);
+            }
+            reply
+        }
+        "LRANGE" => match store.live(&key) {
+            Some(Value::List(l)) => {
+                let len = l.len() as isize;
+                let index = |i: isize| if i < 0 { (len + i).max(0) } else { i };
+                let (start, stop) = (index(arg(2).parse().unwrap_or(0)), index(arg(3).parse().unwrap_or(-1)).min(len - 1));
+                if start > stop {
+                    array(&[])
+                } else {
+                    array(&l.range(start as usize..=stop as usize).map(|v| bulk(v)).collect::<Vec<String>>())
+                }
+            }
+            Some(_) => WRONG_TYPE.to_string(),
+            None => array(&[]),
+        },
+        "SADD" => match store.entry(&key, || Value::Set(BTreeSet::new())) {
+            Value::Set(set) => int(cmd[2..].iter().filter(|m| set.insert(m.to_string())).count()),
+            _ => WRONG_TYPE.to_string(),
+        },
+        "SMEMBERS" => match store.live(&key) {
+            Some(Value::Set(set)) => array(&set.iter().map(|v| bulk(v)).collect::<Vec<String>>()),
+            Some(_) => WRONG_TYPE.to_string(),
+            None => array(&[]),
+        },
+        "SCAN" => {
+            // one pass over everything, the cursor is always done.
+            let pattern = cmd.iter().position(|a| a.eq_ignore_ascii_case("MATCH")).map(|i| arg(i + 1)).unwrap_or("*".to_string());
+            let keys: Vec<String> = store.data.keys().cloned().collect();
+            let keys: Vec<String> = keys.into_iter()
+                .filter(|k| store.live(k).is_some() && glob(pattern.as_bytes(), k.as_bytes()))
+                .map(|k| bulk(&k))
+                .collect();
+            array(&[bulk("0"), array(&keys)])
+        }
+        "PUBLISH" => {
+            let n = shared.messages.send((key, arg(2))).unwrap_or(0);
+            int(n)
+        }
+        "SUBSCRIBE" => cmd[1..].iter().map(|c| {
+            channels.push(c.to_string());
+            array(&[bulk("subscribe"), bulk(c), int(channels.len())])
+        }).collect(),
+        _ => format!("-ERR unknown command '{}'\r\n", name),
+    }
+}
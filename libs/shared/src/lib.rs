@@ -4,6 +4,8 @@ pub mod constants;
 #[cfg(feature = "utils")]
 pub mod utils;
 pub mod redis_api;
+#[cfg(any(test, feature = "conformance"))]
+pub mod redis_conformance;
 pub mod tpl_engine_api;
 
 
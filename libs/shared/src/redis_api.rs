@@ -1,4 +1,8 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 
 #[async_trait]
 pub trait RedisAPI {
@@ -7,7 +11,47 @@ pub trait RedisAPI {
 
     async fn get(&self, key: &str) -> anyhow::Result<String>;
 
-    async fn publish(&self, channel: &str, message: &str) -> anyhow::Result<()>{
-        todo!()
+    async fn publish(&self, _channel: &str, _message: &str) -> anyhow::Result<()>{
+        bail!("publish is not supported by this redis service.")
+    }
+
+    /// messages published on `channel`, the stream ends when the underlying connection is gone.
+    async fn subscribe(&self, _channel: &str) -> anyhow::Result<BoxStream<'static, String>>{
+        bail!("subscribe is not supported by this redis service.")
     }
+
+    async fn set_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<()>;
+    /// true when the key existed.
+    async fn del(&self, key: &str) -> anyhow::Result<bool>;
+    async fn exists(&self, key: &str) -> anyhow::Result<bool>;
+    /// false when there's no such key.
+    async fn expire(&self, key: &str, seconds: u64) -> anyhow::Result<bool>;
+    /// the value after adding `delta`, a missing key counts as 0.
+    async fn incr_by(&self, key: &str, delta: i64) -> anyhow::Result<i64>;
+
+    async fn hset(&self, key: &str, field: &str, val: &str) -> anyhow::Result<()>;
+    async fn hget(&self, key: &str, field: &str) -> anyhow::Result<Option<String>>;
+    async fn hgetall(&self, key: &str) -> anyhow::Result<HashMap<String, String>>;
+
+    /// the list length after the push.
+    async fn lpush(&self, key: &str, val: &str) -> anyhow::Result<usize>;
+    async fn rpush(&self, key: &str, val: &str) -> anyhow::Result<usize>;
+    async fn lpop(&self, key: &str) -> anyhow::Result<Option<String>>;
+    async fn rpop(&self, key: &str) -> anyhow::Result<Option<String>>;
+    /// inclusive range, negative indexes count from the end like redis.
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> anyhow::Result<Vec<String>>;
+
+    /// true when the member is new.
+    async fn sadd(&self, key: &str, member: &str) -> anyhow::Result<bool>;
+    async fn smembers(&self, key: &str) -> anyhow::Result<Vec<String>>;
+
+    /// keys matching a glob `pattern`, e.g. `session:*`
+    async fn scan(&self, pattern: &str) -> anyhow::Result<BoxStream<'static, String>>;
+
+    /// `SET key val NX EX seconds`, true when the key didn't exist and is now set.
+    async fn set_nx_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool>;
+    /// atomically delete the key only while it still holds `val`.
+    async fn del_if_eq(&self, key: &str, val: &str) -> anyhow::Result<bool>;
+    /// atomically reset the ttl only while the key still holds `val`.
+    async fn expire_if_eq(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool>;
 }
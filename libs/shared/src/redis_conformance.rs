@@ -0,0 +1,103 @@
+//!
+//! the behaviour every `RedisAPI` implementation must agree on.
+//! only built with the `conformance` feature, which their tests enable as a dev-dependency.
+
+use std::time::Duration;
+
+use anyhow::ensure;
+use futures::StreamExt;
+
+use crate::redis_api::RedisAPI;
+
+///
+/// keys are prefixed with `prefix` so the suite can run against a shared server.
+pub async fn conformance_suite(r: &(dyn RedisAPI + Send + Sync), prefix: &str) -> anyhow::Result<()> {
+    let k = |name: &str| format!("{}:{}", prefix, name);
+
+    // strings
+    r.set(&k("str"), "v").await?;
+    ensure!(r.get(&k("str")).await? == "v", "get after set");
+    ensure!(r.exists(&k("str")).await?, "exists after set");
+    ensure!(r.del(&k("str")).await?, "del existing key");
+    ensure!(!r.del(&k("str")).await?, "del missing key");
+    ensure!(!r.exists(&k("str")).await?, "exists after del");
+    ensure!(r.get(&k("str")).await.is_err(), "get missing key is an error");
+
+    // counters
+    ensure!(r.incr_by(&k("counter"), 5).await? == 5, "incr_by on missing key");
+    ensure!(r.incr_by(&k("counter"), -2).await? == 3, "incr_by negative");
+    ensure!(r.get(&k("counter")).await? == "3", "counter reads as string");
+
+    // hashes
+    r.hset(&k("hash"), "a", "1").await?;
+    r.hset(&k("hash"), "b", "2").await?;
+    r.hset(&k("hash"), "a", "3").await?;
+    ensure!(r.hget(&k("hash"), "a").await?.as_deref() == Some("3"), "hget overwritten field");
+    ensure!(r.hget(&k("hash"), "zz").await?.is_none(), "hget missing field");
+    let all = r.hgetall(&k("hash")).await?;
+    ensure!(all.len() == 2 && all["b"] == "2", "hgetall");
+    ensure!(r.hgetall(&k("no-hash")).await?.is_empty(), "hgetall missing key");
+
+    // lists
+    ensure!(r.rpush(&k("list"), "b").await? == 1, "rpush");
+    ensure!(r.rpush(&k("list"), "c").await? == 2, "rpush");
+    ensure!(r.lpush(&k("list"), "a").await? == 3, "lpush");
+    ensure!(r.lrange(&k("list"), 0, -1).await? == vec!["a", "b", "c"], "lrange all");
+    ensure!(r.lrange(&k("list"), -2, -1).await? == vec!["b", "c"], "lrange negative");
+    ensure!(r.lpop(&k("list")).await?.as_deref() == Some("a"), "lpop");
+    ensure!(r.rpop(&k("list")).await?.as_deref() == Some("c"), "rpop");
+    ensure!(r.rpop(&k("list")).await?.as_deref() == Some("b"), "rpop last");
+    ensure!(r.lpop(&k("list")).await?.is_none(), "lpop empty");
+    ensure!(!r.exists(&k("list")).await?, "an emptied list is gone");
+
+    // sets
+    ensure!(r.sadd(&k("set"), "x").await?, "sadd new");
+    ensure!(r.sadd(&k("set"), "y").await?, "sadd new");
+    ensure!(!r.sadd(&k("set"), "x").await?, "sadd existing");
+    let mut members = r.smembers(&k("set")).await?;
+    members.sort();
+    ensure!(members == vec!["x", "y"], "smembers");
+
+    // scan
+    let mut keys: Vec<String> = r.scan(&format!("{}:*", prefix)).await?.collect().await;
+    keys.sort();
+    ensure!(keys == vec![k("counter"), k("hash"), k("set")], "scan : {:?}", keys);
+
+    // compare and set
+    ensure!(r.set_nx_ex(&k("nx"), "a", 10).await?, "set_nx_ex on missing key");
+    ensure!(!r.set_nx_ex(&k("nx"), "b", 10).await?, "set_nx_ex on existing key");
+    ensure!(r.get(&k("nx")).await? == "a", "set_nx_ex keeps the first value");
+    ensure!(!r.expire_if_eq(&k("nx"), "b", 10).await?, "expire_if_eq with another value");
+    ensure!(r.expire_if_eq(&k("nx"), "a", 10).await?, "expire_if_eq with the value");
+    ensure!(!r.del_if_eq(&k("nx"), "b").await?, "del_if_eq with another value");
+    ensure!(r.del_if_eq(&k("nx"), "a").await?, "del_if_eq with the value");
+    ensure!(!r.exists(&k("nx")).await?, "del_if_eq deleted");
+
+    // ttl
+    r.set_ex(&k("ttl"), "v", 1).await?;
+    ensure!(r.set_nx_ex(&k("nx-ttl"), "v", 1).await?, "set_nx_ex with ttl");
+    r.set(&k("expire"), "v").await?;
+    ensure!(r.expire(&k("expire"), 1).await?, "expire existing key");
+    ensure!(!r.expire(&k("no-such-key"), 1).await?, "expire missing key");
+    ensure!(r.get(&k("ttl")).await? == "v", "get before ttl");
+    sleep(Duration::from_millis(1100)).await;
+    ensure!(!r.exists(&k("ttl")).await?, "set_ex expired");
+    ensure!(!r.exists(&k("expire")).await?, "expire expired");
+    ensure!(r.set_nx_ex(&k("nx-ttl"), "w", 1).await?, "set_nx_ex after the ttl");
+    r.del(&k("nx-ttl")).await?;
+
+    for name in ["counter", "hash", "set"] {
+        r.del(&k(name)).await?;
+    }
+    Ok(())
+}
+
+/// shared doesn't pull in tokio, a timer thread is good enough for the suite.
+async fn sleep(d: Duration) {
+    let (sender, receiver) = futures::channel::oneshot::channel::<()>();
+    std::thread::spawn(move || {
+        std::thread::sleep(d);
+        let _ = sender.send(());
+    });
+    let _ = receiver.await;
+}
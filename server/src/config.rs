@@ -1,8 +1,9 @@
 use std::{env, fs};
 use std::path::Path;
-use anyhow::anyhow;
+use anyhow::{anyhow, ensure, Context};
 
 use serde::Deserialize;
+use toml::{Table, Value};
 use tracing::info;
 
 use shared::{ file_path};
@@ -14,11 +15,180 @@ pub struct Config {
     #[serde(default)]
     pub use_test_pool: bool,
     pub redis_uri: Vec<String>,
+    /// `single`, `cluster`, or empty to pick cluster when several uris are given.
+    #[serde(default)]
+    pub redis_mode: String,
+    /// without the `redis` feature, the in-memory store is saved to this file under DATA_DIR. empty keeps it in memory only.
+    #[serde(default)]
+    pub redis_memory_file: String,
     pub database: Database,
     #[serde(default)]
     pub upgrade_url: String,
     #[serde(default)]
     pub https_cert: HttpsCert,
+    /// the first rule whose path prefix matches a request applies.
+    #[serde(default)]
+    pub rate_limit: Vec<RateLimitRule>,
+    /// addresses or cidrs of the balancers in front, only their `X-Forwarded-For` is believed.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub mail_server: MailServerConfig,
+    #[serde(default)]
+    pub outbound: OutboundConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+}
+
+/// outgoing mail is relayed through a smarthost, nothing is delivered while `smarthost` is empty.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct OutboundConfig {
+    pub smarthost: String,
+    pub port: u16,
+    /// `starttls`, `tls` or `none`.
+    pub tls: String,
+    /// no AUTH when empty.
+    pub username: String,
+    pub password: String,
+    /// a mail still failing after this many attempts bounces.
+    pub max_attempts: u32,
+    /// how often the queue is checked for due mails.
+    pub poll_secs: u64,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        OutboundConfig {
+            smarthost: "".to_string(),
+            port: 587,
+            tls: "starttls".to_string(),
+            username: "".to_string(),
+            password: "".to_string(),
+            max_attempts: 8,
+            poll_secs: 30,
+        }
+    }
+}
+
+impl std::fmt::Debug for OutboundConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboundConfig")
+            .field("smarthost", &self.smarthost)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &"***")
+            .field("max_attempts", &self.max_attempts)
+            .field("poll_secs", &self.poll_secs)
+            .finish()
+    }
+}
+
+/// old mails are purged in the background. the limits apply to every mailbox
+/// without an entry in `mailboxes`, 0 means no limit.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub max_age_days: u64,
+    pub max_count: u64,
+    /// raw messages and their attachments.
+    pub max_bytes: u64,
+    pub mailboxes: Vec<MailboxRetention>,
+    /// zip the purged mails under `DATA_DIR/mail-archive` before deleting them.
+    pub archive: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            max_age_days: 0,
+            max_count: 0,
+            max_bytes: 0,
+            mailboxes: vec![],
+            archive: false,
+            interval_secs: 3600,
+        }
+    }
+}
+
+/// the limits of one mailbox, the same meaning as in `RetentionConfig`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MailboxRetention {
+    pub mailbox: String,
+    pub max_age_days: u64,
+    pub max_count: u64,
+    pub max_bytes: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MailServerConfig {
+    pub smtp_port: u16,
+    /// bigger messages are refused with 552.
+    pub max_message_size: usize,
+    /// clients have to AUTH PLAIN with one of `accounts` before sending, only offered after STARTTLS.
+    pub auth: bool,
+    /// pem files for STARTTLS, the certificate of the https server (in its `https_cert.mode`) is used when empty.
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    /// per mailbox credentials, the username is usually the address.
+    pub accounts: Vec<MailAccount>,
+    /// POP3 for mail clients, built with the `pop3` feature, off when 0.
+    pub pop3_port: u16,
+    /// the address POP3 listens on. it has no tls, so passwords go in clear off this machine.
+    pub pop3_bind: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MailAccount {
+    pub username: String,
+    pub password: String,
+}
+
+// the whole config gets logged, keep the passwords out of it.
+impl std::fmt::Debug for MailAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MailAccount").field("username", &self.username).field("password", &"***").finish()
+    }
+}
+
+impl Default for MailServerConfig {
+    fn default() -> Self {
+        MailServerConfig {
+            smtp_port: 25,
+            max_message_size: 25 * 1024 * 1024,
+            auth: false,
+            tls_cert_path: "".to_string(),
+            tls_key_path: "".to_string(),
+            accounts: vec![],
+            pop3_port: 0,
+            pop3_bind: "127.0.0.1".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimitRule {
+    /// a path prefix, e.g. `/functions/`
+    pub path: String,
+    /// requests allowed per caller within `window_secs`
+    pub limit: u64,
+    pub window_secs: u64,
+    #[serde(default)]
+    pub by: RateLimitBy,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBy {
+    /// the first `X-Forwarded-For` address, or the peer address.
+    #[default]
+    Ip,
+    /// the `Authorization` header, callers without one are counted by ip.
+    User,
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -26,7 +196,59 @@ pub struct HttpsCert {
     pub https_port: u16,
     pub domains: Vec<String>,
     pub emails: Vec<String>,
+    #[serde(default)]
+    pub mode: CertMode,
+    /// pem files for the `static` mode, they are reloaded when changed.
+    #[serde(default)]
+    pub cert_path: String,
+    #[serde(default)]
+    pub key_path: String,
+    /// `production`, `staging` or the url of another acme directory (e.g. a local pebble).
+    #[serde(default)]
+    pub directory: String,
+    #[serde(default)]
+    pub challenge: AcmeChallenge,
+    /// `Strict-Transport-Security` max-age in seconds, 0 sends none.
+    #[serde(default)]
+    pub hsts_max_age: u64,
+    #[serde(default)]
+    pub hsts_include_subdomains: bool,
+    /// routes or a static root per domain, the other domains get the whole app.
+    #[serde(default)]
+    pub vhosts: Vec<VirtualHost>,
+}
 
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct VirtualHost {
+    pub domain: String,
+    /// only app paths starting with one of these, e.g. `/api/`.
+    pub routes: Vec<String>,
+    /// a directory under DATA_DIR served instead of the app.
+    pub static_root: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum AcmeChallenge {
+    /// answered on `https_port`.
+    #[default]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+    /// answered on `server_port`, when a proxy in front terminates tls.
+    #[serde(rename = "http-01")]
+    Http01,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CertMode {
+    /// let's encrypt for `domains`.
+    #[default]
+    Acme,
+    /// `cert_path` and `key_path`, e.g. from an internal CA.
+    Static,
+    /// generated under DATA_DIR/certs/self-signed, for development.
+    SelfSigned,
 }
 #[derive(Deserialize, Debug, Clone)]
 pub struct Database {
@@ -36,6 +258,10 @@ pub struct Database {
 
 const CONFIG: &str = include_str!(file_path!("/config.toml"));
 
+/// env vars override the config, e.g. `PLAY_SERVER_PORT=3001`. nested keys are joined
+/// with `__`, e.g. `PLAY_HTTPS_CERT__HTTPS_PORT=8443`.
+const ENV_PREFIX: &str = "PLAY_";
+
 pub fn read_config_file()->anyhow::Result<String>{
     let file_path = format!("config.toml");
     let final_path = Path::new(env::var(DATA_DIR)?.as_str()).join(file_path.as_str());
@@ -46,7 +272,10 @@ pub fn read_config_file()->anyhow::Result<String>{
     Ok(content)
 
 }
+
+/// refuses content the next boot would fail on.
 pub fn save_config_file(content: &str)->anyhow::Result<()>{
+    check_config(content)?;
     let file_path = format!("config.toml");
     let final_path = Path::new(env::var(DATA_DIR)?.as_str()).join(file_path.as_str());
 
@@ -57,6 +286,11 @@ pub fn save_config_file(content: &str)->anyhow::Result<()>{
 
 }
 
+/// a dry run of the file content on top of the defaults and the env vars.
+pub fn check_config(content: &str) -> anyhow::Result<Config> {
+    build_config(Some(content), env::vars(), vec![])
+}
+
 pub fn get_config_path()->anyhow::Result<String>{
     let file_path = format!("config.toml");
     let final_path = Path::new(env::var(DATA_DIR)?.as_str()).join(file_path.as_str());
@@ -65,26 +299,274 @@ pub fn get_config_path()->anyhow::Result<String>{
     Ok(final_path.to_str().unwrap().to_string())
 }
 
+///
+/// the config is layered, later layers win : the embedded config.toml, the file,
+/// `PLAY_*` env vars, then `--key.path=value` flags, e.g. `--https_cert.mode=self_signed`.
+pub fn build_config(file: Option<&str>, env: impl IntoIterator<Item=(String, String)>, args: impl IntoIterator<Item=String>) -> anyhow::Result<Config> {
+    let mut table = parse_layer("embedded config.toml", CONFIG)?;
+    if let Some(file) = file {
+        merge(&mut table, parse_layer("config.toml", file)?);
+    }
+    let mut overrides = env_overrides(env);
+    overrides.extend(args_overrides(args));
+
+    let config = match deserialize(with_overrides(&table, &overrides, false)) {
+        Ok(config) => config,
+        // a key the embedded config lacks may have been meant as a string, e.g. `PLAY_REDIS_MEMORY_FILE=2024`.
+        Err(e) => deserialize(with_overrides(&table, &overrides, true)).map_err(|_| e)?,
+    };
+    config.validate()?;
+    Ok(config)
+}
+
+/// the config the server boots with, the file under DATA_DIR is written from the embedded one on first run.
+pub fn load_config() -> anyhow::Result<Config> {
+    let final_path = Path::new(env::var(DATA_DIR)?.as_str()).join("config.toml");
+    info!("config path : {:?}", final_path);
+
+    if !final_path.exists() {
+        //copy content to output dir.
+        fs::write(&final_path, CONFIG)?;
+    }
+    let content = fs::read_to_string(&final_path).with_context(|| format!("reading {:?} failed", final_path))?;
+    let config = build_config(Some(&content), env::vars(), env::args().skip(1))?;
+    info!("using config file  content >>  {:?}",  config);
+    Ok(config)
+}
+
+/// `use_memory` only takes the embedded config, for tests.
 pub fn init_config(use_memory: bool) -> Config {
-    let config_content = if !use_memory {
+    let config = if use_memory { build_config(None, vec![], vec![]) } else { load_config() };
+    config.unwrap_or_else(|e| panic!("{:#}", e))
+}
+
+fn parse_layer(name: &str, content: &str) -> anyhow::Result<Table> {
+    toml::from_str(content).map_err(|e| anyhow!("{} : {}", name, e))
+}
+
+/// tables are merged key by key, anything else (arrays too) is replaced.
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => { base.insert(key, value); }
+        }
+    }
+}
+
+fn deserialize(table: Table) -> anyhow::Result<Config> {
+    Value::Table(table).try_into()
+        .map_err(|e: toml::de::Error| anyhow!("invalid config : {}", e.to_string().trim().replace('\n', " ")))
+}
+
+/// a key path and the raw text it is set to.
+type Override = (Vec<String>, String);
+
+fn env_overrides(env: impl IntoIterator<Item=(String, String)>) -> Vec<Override> {
+    env.into_iter()
+        .filter_map(|(key, value)| {
+            let key = key.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_lowercase().split("__").map(str::to_string).collect(), value))
+        })
+        .collect()
+}
+
+/// only `--key=value` args, the rest (positional args, `--nocapture` of the test harness) isn't ours.
+fn args_overrides(args: impl IntoIterator<Item=String>) -> Vec<Override> {
+    args.into_iter()
+        .filter_map(|arg| {
+            let (key, value) = arg.strip_prefix("--")?.split_once('=')?;
+            Some((key.split('.').map(str::to_string).collect(), value.to_string()))
+        })
+        .collect()
+}
+
+///
+/// overrides take the type of the value they replace : a string stays a string
+/// (`PLAY_OUTBOUND__PASSWORD=123456`), anything else is read as toml (`3001`, `true`, `["a", "b"]`).
+/// keys missing from the table are read as toml too, unless `missing_as_strings`.
+fn with_overrides(table: &Table, overrides: &[Override], missing_as_strings: bool) -> Table {
+    let mut table = table.clone();
+    for (path, raw) in overrides {
+        let value = match get_path(&table, path) {
+            Some(Value::String(_)) => Value::String(raw.to_string()),
+            None if missing_as_strings => Value::String(raw.to_string()),
+            _ => parse_value(raw),
+        };
+        set_path(&mut table, path, value);
+    }
+    table
+}
+
+fn get_path<'a>(table: &'a Table, path: &[String]) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table()?;
+    }
+    table.get(last)
+}
 
+fn set_path(table: &mut Table, path: &[String], value: Value) {
+    match path {
+        [] => {}
+        [key] => { table.insert(key.clone(), value); }
+        [key, rest @ ..] => {
+            let entry = table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(inner) = entry {
+                set_path(inner, rest, value);
+            }
+        }
+    }
+}
 
-        let file_path = format!("config.toml");
-        let final_path = Path::new(env::var(DATA_DIR).unwrap().as_str()).join(file_path.as_str());
+/// a toml value (`3001`, `true`, `["a", "b"]`), or the raw text as a string.
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {}", raw)).ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
 
-        info!("config path : {:?}", final_path);
+impl Config {
+    /// what serde can't check, all problems at once with the key they are about.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = vec![];
+        let mut check = |ok: bool, key: String, message: &str| if !ok {
+            problems.push(format!("{} : {}", key, message));
+        };
+
+        check((1..=65535).contains(&self.server_port), "server_port".into(), "should be between 1 and 65535");
+        check(["", "single", "cluster"].contains(&self.redis_mode.as_str()), "redis_mode".into(), "should be `single`, `cluster` or empty");
+        check(!self.database.url.is_empty(), "database.url".into(), "required");
+
+        let https = &self.https_cert;
+        match https.mode {
+            CertMode::Static => {
+                check(!https.cert_path.is_empty(), "https_cert.cert_path".into(), "required for the static mode");
+                check(!https.key_path.is_empty(), "https_cert.key_path".into(), "required for the static mode");
+            }
+            CertMode::Acme => {
+                // without the https feature no certificate is ever requested.
+                check(!cfg!(feature = "https") || !https.domains.is_empty(), "https_cert.domains".into(), "required for the acme mode");
+                check(["", "production", "staging"].contains(&https.directory.as_str())
+                          || https.directory.starts_with("https://") || https.directory.starts_with("http://"),
+                      "https_cert.directory".into(), "should be `production`, `staging` or an url");
+            }
+            CertMode::SelfSigned => {}
+        }
+        for (i, vhost) in https.vhosts.iter().enumerate() {
+            check(!vhost.domain.is_empty(), format!("https_cert.vhosts[{}].domain", i), "required");
+            check(vhost.routes.is_empty() || vhost.static_root.is_empty(), format!("https_cert.vhosts[{}]", i), "either routes or static_root, not both");
+            check(vhost.routes.iter().all(|r| r.starts_with('/')), format!("https_cert.vhosts[{}].routes", i), "should start with /");
+        }
 
-        if !final_path.exists() {
-            //copy content to output dir.
-            fs::write(&final_path, CONFIG).expect("write file error!");
+        for (i, rule) in self.rate_limit.iter().enumerate() {
+            check(rule.path.starts_with('/'), format!("rate_limit[{}].path", i), "should start with /");
+        }
+        for (i, proxy) in self.trusted_proxies.iter().enumerate() {
+            let (ip, bits) = proxy.split_once('/').unwrap_or((proxy.as_str(), "0"));
+            let width = match ip.parse::<std::net::IpAddr>() {
+                Ok(std::net::IpAddr::V4(_)) => 32,
+                Ok(std::net::IpAddr::V6(_)) => 128,
+                Err(_) => 0,
+            };
+            check(width > 0 && bits.parse::<u8>().is_ok_and(|bits| bits <= width), format!("trusted_proxies[{}]", i), "should be an ip or a cidr");
         }
 
+        let mail = &self.mail_server;
+        check(mail.tls_cert_path.is_empty() == mail.tls_key_path.is_empty(), "mail_server.tls_key_path".into(), "tls_cert_path and tls_key_path go together");
+        check(mail.pop3_port == 0 || mail.pop3_port != mail.smtp_port, "mail_server.pop3_port".into(), "the same as smtp_port");
+        check(mail.pop3_bind.parse::<std::net::IpAddr>().is_ok(), "mail_server.pop3_bind".into(), "should be an ip");
+        check(!mail.auth || !mail.accounts.is_empty(), "mail_server.accounts".into(), "required when auth is on");
 
-        fs::read_to_string(&final_path).expect(format!("config file : {}  not existed!", file_path).as_str())
-    } else {
-        CONFIG.to_string()
-    };
-    let  config: Config = toml::from_str(&config_content).unwrap();
-    info!("using config file  content >>  {:?}",  config);
-    config
-}
\ No newline at end of file
+        check(["starttls", "tls", "none"].contains(&self.outbound.tls.as_str()), "outbound.tls".into(), "should be `starttls`, `tls` or `none`");
+        for (i, mailbox) in self.retention.mailboxes.iter().enumerate() {
+            check(!mailbox.mailbox.is_empty(), format!("retention.mailboxes[{}].mailbox", i), "required");
+        }
+
+        ensure!(problems.is_empty(), "invalid config : {}", problems.join(", "));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_layers() -> anyhow::Result<()> {
+        let config = build_config(None, vec![], vec![])?;
+        assert_eq!((config.server_port, config.https_cert.https_port), (3000, 443));
+
+        // the file only needs what it changes, the rest comes from the embedded config.
+        let file = "server_port = 4000\n[https_cert]\nhttps_port = 8443\n[outbound]\nsmarthost = \"smtp.x.com\"";
+        let config = build_config(Some(file), vec![], vec![])?;
+        assert_eq!((config.server_port, config.https_cert.https_port), (4000, 8443));
+        assert_eq!((config.outbound.smarthost.as_str(), config.outbound.port), ("smtp.x.com", 587));
+
+        let config = build_config(Some(file), env(&[
+            ("PLAY_SERVER_PORT", "5000"),
+            ("PLAY_HTTPS_CERT__DOMAINS", r#"["a.com", "b.com"]"#),
+            ("PLAY_DATABASE__URL", "sqlite://play.db"),
+            ("HOME", "/root"),
+        ]), args(&["--server_port=6000", "--https_cert.mode=self_signed"]))?;
+        assert_eq!(config.server_port, 6000);
+        assert_eq!(config.https_cert.https_port, 8443);
+        assert_eq!(config.https_cert.domains, vec!["a.com", "b.com"]);
+        assert_eq!(config.https_cert.mode, CertMode::SelfSigned);
+        assert_eq!(config.database.url, "sqlite://play.db");
+        Ok(())
+    }
+
+    #[test]
+    fn test_overrides_keep_the_type() -> anyhow::Result<()> {
+        let config = build_config(None, env(&[
+            ("PLAY_OUTBOUND__PASSWORD", "123456"),
+            ("PLAY_OUTBOUND__USERNAME", "true"),
+            ("PLAY_OUTBOUND__PORT", "465"),
+            // not in the embedded config, a string field all the same.
+            ("PLAY_REDIS_MEMORY_FILE", "2024"),
+        ]), args(&["--https_cert.cert_path=1979-05-27"]))?;
+        assert_eq!((config.outbound.password.as_str(), config.outbound.username.as_str()), ("123456", "true"));
+        assert_eq!(config.outbound.port, 465);
+        assert_eq!((config.redis_memory_file.as_str(), config.https_cert.cert_path.as_str()), ("2024", "1979-05-27"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |file: &str| build_config(Some(file), vec![], vec![]).unwrap_err().to_string();
+
+        assert!(error("server_port = 4000\n[https_cert\n").contains("line 2"));
+        assert!(error("[https_cert]\nhttps_port = \"x\"").contains("in `https_cert.https_port`"));
+        assert!(error("[https_cert]\nmode = \"manual\"").contains("unknown variant `manual`"));
+
+        let e = error("server_port = 0\n[https_cert]\nmode = \"static\"\n[outbound]\ntls = \"ssl\"");
+        assert!(e.contains("server_port : should be between 1 and 65535"), "{}", e);
+        assert!(e.contains("https_cert.cert_path : required for the static mode"), "{}", e);
+        assert!(e.contains("outbound.tls :"), "{}", e);
+        assert!(error("[mail_server]\npop3_bind = \"any\"").contains("mail_server.pop3_bind : should be an ip"));
+        // acme without domains only matters when there's an https server to get a certificate for.
+        assert_eq!(build_config(Some("[https_cert]\ndomains = []"), vec![], vec![]).is_err(), cfg!(feature = "https"));
+
+        let e = error("trusted_proxies = [\"10.0.0.0/8\", \"::1/128\", \"10.0.0.0/33\", \"fd00::/129\"]");
+        assert!(!e.contains("trusted_proxies[0]") && !e.contains("trusted_proxies[1]"), "{}", e);
+        assert!(e.contains("trusted_proxies[2] : should be an ip or a cidr"), "{}", e);
+        assert!(e.contains("trusted_proxies[3] : should be an ip or a cidr"), "{}", e);
+
+        // what isn't `--key=value` is left to whoever else reads the args.
+        let config = build_config(None, vec![], args(&["server_port=1", "--nocapture", "--test-threads=1", "--server_port=3001"])).unwrap();
+        assert_eq!(config.server_port, 3001);
+    }
+}
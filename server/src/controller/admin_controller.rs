@@ -14,14 +14,23 @@ use serde_json::json;
 use tracing::info;
 use shared::constants::DATA_DIR;
 
-use crate::{check_if, HTML, method_router, S, template};
-use crate::config::{get_config_path, read_config_file, save_config_file};
+use crate::{AppState, check_if, HTML, method_router, S, template};
+use crate::config::{check_config, get_config_path, read_config_file, save_config_file};
+use crate::service::backplane::emit;
+use crate::service::cert_status;
+use crate::service::event_bus::AppEvent;
+use crate::service::html::escape_html;
+use crate::service::lock;
+
+/// the self-upgrade replaces the binary, one instance at a time.
+const UPGRADE_LOCK: &str = "admin-upgrade";
 
 method_router!(
     get : "/admin/upgrade" -> upgrade,
     get : "/admin/shutdown" -> shutdown,
     get : "/admin/index" -> enter_admin_page,
     post : "/admin/save-config" -> save_config,
+    post : "/admin/validate-config" -> validate_config,
     get : "/admin/logs" -> display_logs,
 );
 
@@ -57,10 +66,21 @@ async fn display_logs(s: S) -> HTML {
     Ok(Html(converted))
 }
 async fn save_config(s: S, Form(req): Form<SaveConfigReq>) -> HTML {
+    if let Err(e) = check_config(&req.new_content) {
+        return Ok(Html(format!("not saved.<pre>{}</pre>", escape_html(&e.to_string()))));
+    }
     save_config_file(&req.new_content)?;
     Ok(Html("save ok.".to_string()))
 }
 
+/// the same checks as saving, without writing.
+async fn validate_config(s: S, Form(req): Form<SaveConfigReq>) -> HTML {
+    match check_config(&req.new_content) {
+        Ok(_) => Ok(Html("config ok.".to_string())),
+        Err(e) => Ok(Html(format!("<pre>{}</pre>", escape_html(&e.to_string())))),
+    }
+}
+
 
 async fn enter_admin_page(s: S) -> HTML {
     // let config = &CONFIG;
@@ -73,11 +93,13 @@ async fn enter_admin_page(s: S) -> HTML {
         "upgrade_url" : &s.config.upgrade_url,
         "config_content" : config_content,
         "config_path" : config_path,
+        "cert_status" : cert_status::load()?,
     }))
 }
 
-async fn upgrade_in_background(url: Url) ->anyhow::Result<()>{
+async fn upgrade_in_background(s: &AppState, url: Url) ->anyhow::Result<()>{
     info!("begin to download from url in background  : {}", url);
+    emit(s, AppEvent::UpgradeProgress { message: format!("downloading {}", url) }).await;
 
     // download file
     let new_binary = temp_dir().join("new_play_bin");
@@ -85,9 +107,10 @@ async fn upgrade_in_background(url: Url) ->anyhow::Result<()>{
     let client = ClientBuilder::new().timeout(Duration::from_secs(30)).build()?;
     let response = client.get(url).send().await?;
     let mut content = Cursor::new(response.bytes().await?);
-    std::io::copy(&mut content, &mut file)?;
+    let size = std::io::copy(&mut content, &mut file)?;
 
     info!("downloaded and saved at : {:?}", new_binary);
+    emit(s, AppEvent::UpgradeProgress { message: format!("downloaded {} bytes, replacing binary", size) }).await;
 
     self_replace::self_replace(&new_binary)?;
     std::fs::remove_file(&new_binary)?;
@@ -102,9 +125,17 @@ async fn upgrade(s: S, Query(upgrade): Query<UpgradeRequest>) -> HTML {
     let url = Url::parse(&upgrade.url)?;
 
 
+    let state = s.0.clone();
     tokio::spawn(async move{
-        let r = upgrade_in_background(url).await;
+        // instances sharing a redis take turns, the loser just reports it.
+        let r = lock::with_lock(state.redis_service.as_ref(), UPGRADE_LOCK, 60, upgrade_in_background(&state, url)).await;
         info!("upgrade_in_background result >> {:?}", r);
+        let message = match r {
+            Ok(Some(Ok(_))) => "upgrade done, pls restart manually.".to_string(),
+            Ok(None) => "another instance is upgrading already, skipped.".to_string(),
+            Ok(Some(Err(e))) | Err(e) => format!("upgrade failed : {}", e),
+        };
+        emit(&state, AppEvent::UpgradeProgress { message }).await;
     });
 
 
@@ -1,22 +1,26 @@
-use std::sync::Arc;
-
-use axum::{Form, Json, Router};
+use axum::{Form, Json};
 use axum::extract::Query;
-use axum::routing::{get, post};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::{AppState, check_if, template};
+use crate::{check_if, method_router, template};
 use crate::{HTML, JSON, R, S};
+use crate::service::api_entry_io;
+use crate::service::api_entry_io::{ExportFormat, ImportFormat};
+use crate::service::api_mock::MAX_MOCK_DELAY_MS;
+use crate::service::response_cache::{CacheOptions, invalidate, TAG_API_ENTRY};
 use crate::tables::api_entry::{ApiEntry, UpdateApiEntry};
 
-pub fn init() -> Router<Arc<AppState>> {
-    Router::new()
-        .route("/api-entry/list", get(list))
-        .route("/api-entry/get", get(get_by_id))
-        .route("/api-entry/save", post(save))
-        .route("/api-entry/delete", get(delete))
-}
+method_router!(
+    get : "/api-entry/list" -> list with CacheOptions::ttl(60).tag(TAG_API_ENTRY),
+    get : "/api-entry/get" -> get_by_id with CacheOptions::ttl(60).tag(TAG_API_ENTRY),
+    post : "/api-entry/save" -> save,
+    get : "/api-entry/delete" -> delete,
+    post : "/api-entry/import" -> import,
+    get : "/api-entry/export" -> export with CacheOptions::ttl(60).tag(TAG_API_ENTRY),
+);
 
 async fn list(s: S) -> HTML {
     let items = ApiEntry::query_all(&s.db).await?;
@@ -38,6 +42,7 @@ async fn get_by_id(s: S, Query(id): Query<Id>) -> JSON<Vec<ApiEntry>> {
 
 // #[axum::debug_handler]
 async fn save(s: S,Form(entry): Form<UpdateApiEntry>) -> R<String> {
+    check_if!((0..=MAX_MOCK_DELAY_MS).contains(&entry.mock_delay_ms), "mock delay should be between 0 and {} ms!", MAX_MOCK_DELAY_MS);
     let r = match entry.id {
         None => {
             //insert
@@ -49,10 +54,64 @@ async fn save(s: S,Form(entry): Form<UpdateApiEntry>) -> R<String> {
         }
     };
     check_if!(r.rows_affected()==1, "update api entry error!");
+    invalidate(&s, TAG_API_ENTRY).await;
     Ok(r.rows_affected().to_string())
 }
 async fn delete(s: S ,Query(id): Query<Id>) -> R<String> {
     let r = ApiEntry::delete(id.id as i64, &s.db).await?;
     check_if!(r.rows_affected()==1, "delete api entry error!");
+    invalidate(&s, TAG_API_ENTRY).await;
     Ok(r.rows_affected().to_string())
-}
\ No newline at end of file
+}
+
+
+#[derive(Deserialize)]
+struct ImportReq {
+    format: ImportFormat,
+    content: String,
+    #[serde(default)]
+    collection: String,
+}
+
+async fn import(s: S, Form(req): Form<ImportReq>) -> R<String> {
+    let entries = api_entry_io::import(req.format, &req.content, &req.collection)?;
+    check_if!(!entries.is_empty(), "nothing to import!");
+
+    let mut count = 0;
+    for entry in entries {
+        let r = ApiEntry::insert(entry, &s.db).await?;
+        count += r.rows_affected();
+    }
+    invalidate(&s, TAG_API_ENTRY).await;
+    Ok(count.to_string())
+}
+
+#[derive(Deserialize)]
+struct ExportReq {
+    format: ExportFormat,
+    #[serde(default)]
+    collection: String,
+}
+
+async fn export(s: S, Query(req): Query<ExportReq>) -> R<Response> {
+    let items = if req.collection.is_empty() {
+        ApiEntry::query_all(&s.db).await?
+    } else {
+        ApiEntry::query_by_collection(&req.collection, &s.db).await?
+    };
+    let name = if req.collection.is_empty() { "play" } else { req.collection.as_str() };
+
+    let (content_type, file_name, body) = match req.format {
+        ExportFormat::Curl => ("text/plain; charset=utf-8", format!("{}.curl.txt", name), api_entry_io::export_curl(&items)),
+        ExportFormat::Postman => ("application/json", format!("{}.postman_collection.json", name),
+                                  serde_json::to_string_pretty(&api_entry_io::export_postman(name, &items))?),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name.replace('/', "_"))),
+        ],
+        body,
+    ).into_response())
+}
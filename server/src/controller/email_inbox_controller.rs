@@ -1,21 +1,165 @@
-use axum::response::Html;
+use axum::extract::Query;
+use axum::http::header;
+use axum::response::{Html, IntoResponse, Response};
+use serde::Deserialize;
 use serde_json::json;
-use crate::{check_if, HTML, method_router, S, template};
-use crate::tables::email_inbox::EmailInbox;
+
+use crate::{check_if, HTML, method_router, R, S, template};
+use crate::service::{mail_retention, mail_store};
+use crate::tables::email_attachment::EmailAttachment;
+use crate::tables::email_inbox::{EmailInbox, InboxFilter};
 
 method_router!(
     get : "/email-inbox/list"-> list,
+    get : "/email-inbox/detail"-> detail,
+    get : "/email-inbox/mark-read"-> mark_read,
+    get : "/email-inbox/delete"-> delete,
     get : "/email-inbox/delete-all"-> delete_all,
+    get : "/email-inbox/raw"-> download_raw,
+    get : "/email-inbox/attachment"-> download_attachment,
 );
 
-async fn list(s: S) ->HTML{
-    let items = EmailInbox::query_all(&s.db).await?;
-    template!(s, "email_inbox/list.html", json!({
-        "items": items
+const PAGE_SIZE: i64 = 20;
+
+#[derive(Deserialize)]
+struct ListReq {
+    #[serde(default)]
+    q: String,
+    /// a recipient address, every mailbox when empty.
+    #[serde(default)]
+    mailbox: String,
+    #[serde(default = "first_page")]
+    page: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+async fn list(s: S, Query(req): Query<ListReq>) ->HTML{
+    let filter = InboxFilter { q: req.q, mailbox: req.mailbox };
+    let total = EmailInbox::count(&filter, &s.db).await?;
+    let pages = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page = req.page.clamp(1, pages);
+    let items = EmailInbox::search(&filter, (page - 1) * PAGE_SIZE, PAGE_SIZE, &s.db).await?;
+    let (all_count, all_bytes) = EmailInbox::totals(&s.db).await?;
+    let retention = mail_retention::policy_for(&s.config.retention, &filter.mailbox).describe();
+    template!(s, "frame.html"+"email_inbox/list.html", json!({
+        "title": "My Email Inbox",
+        "items": items,
+        "mailboxes": EmailInbox::mailboxes(&s.db).await?,
+        "all_count": all_count,
+        "all_bytes": all_bytes,
+        "retention": retention,
+        "q": filter.q,
+        "mailbox": filter.mailbox,
+        "page": page,
+        "pages": pages,
+        "total": total,
+    }))
+}
+
+#[derive(Deserialize)]
+struct DetailReq {
+    id: i64,
+    /// `html`, `plain` or `source`, html when the message has one.
+    #[serde(default)]
+    view: String,
+}
+
+async fn detail(s: S, Query(req): Query<DetailReq>) -> HTML {
+    let items = EmailInbox::get_by_id(req.id, &s.db).await?;
+    check_if!(items.len()==1, format!("email {} not found.", req.id));
+    let mut mail = items[0].clone();
+    if !mail.is_read {
+        EmailInbox::mark_read(mail.id, true, &s.db).await?;
+        mail.is_read = true;
+    }
+    let files = EmailAttachment::query_by_email(mail.id, &s.db).await?;
+
+    let view = match req.view.as_str() {
+        "" if mail.html_content.is_empty() => "plain",
+        "" => "html",
+        v => v,
+    };
+    check_if!(matches!(view, "html" | "plain" | "source"), format!("unknown view : {}", view));
+    let content = match view {
+        "html" => mail_store::sanitize_html(&mail.html_content, &files),
+        "plain" => mail.plain_content.to_string(),
+        _ if mail.full_body.is_empty() => "".to_string(),
+        _ => String::from_utf8_lossy(&mail_store::read_file(&mail.full_body).await?).to_string(),
+    };
+    template!(s, "email_inbox/detail.html", json!({
+        "bcc": mail.bcc(),
+        "mail": mail,
+        "files": files,
+        "view": view,
+        "content": content,
+    }))
+}
+
+#[derive(Deserialize)]
+struct MarkReadReq {
+    id: i64,
+    #[serde(default = "read_by_default")]
+    read: bool,
+}
+
+fn read_by_default() -> bool {
+    true
+}
+
+async fn mark_read(s: S, Query(req): Query<MarkReadReq>) -> HTML {
+    let r = EmailInbox::mark_read(req.id, req.read, &s.db).await?;
+    check_if!(r.rows_affected()==1, format!("email {} not found.", req.id));
+    let items = EmailInbox::get_by_id(req.id, &s.db).await?;
+    check_if!(items.len()==1, format!("email {} not found.", req.id));
+    template!(s, "email_inbox/row.html", json!({
+        "item": items[0]
     }))
 }
+
+async fn delete(s: S, Query(id): Query<Id>) -> HTML {
+    let deleted = mail_store::delete_email(&s, id.id).await?;
+    check_if!(deleted, format!("email {} delete failed.", id.id));
+    Ok(Html("".to_string()))
+}
 async fn delete_all(s: S) ->HTML{
     let r = EmailInbox::delete_all(&s.db).await?;
+    EmailAttachment::delete_all(&s.db).await?;
+    mail_store::remove_all_files().await?;
 
     Ok(Html(format!("delete count : {}", r.rows_affected())))
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+struct Id {
+    id: i64,
+}
+
+fn download(content_type: &str, file_name: &str, body: Vec<u8>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name.replace(['"', '\\', '/'], "_"))),
+        ],
+        body,
+    ).into_response()
+}
+
+/// the message exactly as it was received.
+async fn download_raw(s: S, Query(id): Query<Id>) -> R<Response> {
+    let items = EmailInbox::get_by_id(id.id, &s.db).await?;
+    check_if!(items.len()==1, format!("email {} not found.", id.id));
+    check_if!(!items[0].full_body.is_empty(), format!("email {} has no raw source.", id.id));
+    let body = mail_store::read_file(&items[0].full_body).await?;
+    Ok(download("message/rfc822", &format!("{}.eml", id.id), body))
+}
+
+async fn download_attachment(s: S, Query(id): Query<Id>) -> R<Response> {
+    let items = EmailAttachment::get_by_id(id.id, &s.db).await?;
+    check_if!(items.len()==1, format!("attachment {} not found.", id.id));
+    let body = mail_store::read_file(&items[0].file_path).await?;
+    let content_type = if items[0].content_type.is_empty() { "application/octet-stream" } else { items[0].content_type.as_str() };
+    Ok(download(content_type, &items[0].filename, body))
+}
@@ -20,6 +20,8 @@ use sqlx::mysql::{MySqlPoolOptions, MySqlQueryResult, MySqlRow};
 
 use crate::{check_if, method_router, template};
 use crate::{HTML, JSON, render_fragment, S, Template};
+use crate::service::text_diff;
+use crate::service::text_diff::DiffMode;
 
 method_router!(
     post : "/functions/str-joiner" -> str_joiner,
@@ -130,28 +132,16 @@ async fn run_sql(s: S, Form(data): Form<RunSqlRequest>) -> HTML {
     }))
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize)]
 struct TextCompareReq {
     text1: String,
     text2: String,
-    #[serde(default = "default_with_ajax")]
-    with_ajax: i32,
-}
-
-#[allow(non_snake_case)]
-#[derive(Deserialize)]
-struct TextCompareRes {
-    comparison: Option<String>,
-    messageForUser: Option<String>,
-}
-
-fn default_with_ajax() -> i32 {
-    1
+    /// `side` (default) or `unified`
+    #[serde(default)]
+    mode: String,
 }
 
 async fn text_compare(s: S, Form(mut data): Form<TextCompareReq>) -> HTML {
-    let client = ClientBuilder::new().timeout(Duration::from_secs(3)).build()?;
-
     data.text1 = str_joiner(s.clone(), Form(Data { s: data.text1.to_string() })).await?.0;
     data.text2 = str_joiner(s, Form(Data { s: data.text2.to_string() })).await?.0;
 
@@ -167,12 +157,9 @@ async fn text_compare(s: S, Form(mut data): Form<TextCompareReq>) -> HTML {
         }
     }
 
-    let resp = client.post("https://text-compare.com/").form(&data).send().await?;
-    check_if!(resp.status().is_success(), "call https://text-compare.com/ failed.");
-    // info!("resp >> {}", resp.text().await?);
-    let res_body = resp.json::<TextCompareRes>().await?;
-    Ok(Html(res_body.comparison.unwrap_or("<h2>No Diff!</h2>".to_string())))
-    // Ok(Html("sfd".to_string()))
+    // after the templates ran, they can make the texts a lot longer.
+    check_if!(data.text1.len() + data.text2.len() <= text_diff::MAX_INPUT, "texts too large to compare, {} KB at most!", text_diff::MAX_INPUT / 1024);
+    Ok(Html(text_diff::render_html(&data.text1, &data.text2, DiffMode::from_name(&data.mode))))
 }
 
 async fn query_mysql(url: &str, sql: &str, is_query: bool) -> anyhow::Result<Vec<Vec<String>>> {
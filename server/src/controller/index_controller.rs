@@ -1,12 +1,17 @@
+use axum::http::StatusCode;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
 use serde_json::json;
 
 use crate::{method_router, template};
 use crate::{HTML, R, S};
+use crate::service::cert_status;
 
 method_router!(
     get : "/"-> root,
     get : "/test-redis"-> redis_test,
     get : "/test"-> test,
+    get : "/health"-> health,
 );
 
 // #[axum::debug_handler]
@@ -33,3 +38,13 @@ async fn test(s: S) -> HTML {
     template!(s, "test.html", json!({"name":"zzp"}))
 }
 
+/// 503 once the https certificate expired or getting one failed, for uptime checks.
+async fn health() -> R<Response> {
+    let https = cert_status::load()?;
+    let healthy = https.as_ref().map(|s| s["healthy"] == true).unwrap_or(true);
+    let code = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((code, Json(json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "https": https,
+    }))).into_response())
+}
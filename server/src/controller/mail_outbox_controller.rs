@@ -0,0 +1,72 @@
+use axum::extract::Query;
+use axum::Json;
+use axum::response::Html;
+use mail_server::outbound::OutgoingMail;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use shared::current_timestamp;
+
+use crate::{check_if, HTML, JSON, method_router, S, template};
+use crate::service::mail_outbox;
+use crate::tables::mail_outbox::{MailOutbox, STATUS_FAILED, STATUS_QUEUED, STATUS_SENT};
+
+method_router!(
+    get : "/admin/mail-outbox"-> list,
+    post : "/mail-outbox/send"-> send,
+    get : "/mail-outbox/retry"-> retry,
+    get : "/mail-outbox/delete"-> delete,
+);
+
+#[derive(Deserialize)]
+struct ListReq {
+    /// `queued`, `sent` or `failed`, everything when empty.
+    #[serde(default)]
+    status: String,
+}
+
+async fn list(s: S, Query(req): Query<ListReq>) -> HTML {
+    let mut counts = vec![];
+    for status in [STATUS_QUEUED, STATUS_SENT, STATUS_FAILED] {
+        counts.push(json!({"status": status, "count": MailOutbox::count_by_status(status, &s.db).await?}));
+    }
+    template!(s, "frame.html"+"mail_outbox/list.html", json!({
+        "title": "mail outbox",
+        "items": MailOutbox::query_by_status(&req.status, &s.db).await?,
+        "counts": counts,
+        "status": req.status,
+        "smarthost": s.config.outbound.smarthost,
+    }))
+}
+
+#[derive(Serialize)]
+struct SendResp {
+    id: i64,
+}
+
+/// queue a mail, the worker delivers it.
+async fn send(s: S, Json(mail): Json<OutgoingMail>) -> JSON<SendResp> {
+    let id = mail_outbox::enqueue(&s, &mail).await?;
+    Ok(Json(SendResp { id }))
+}
+
+#[derive(Deserialize)]
+struct Id {
+    id: i64,
+}
+
+async fn retry(s: S, Query(id): Query<Id>) -> HTML {
+    let r = MailOutbox::retry(id.id, current_timestamp!(), &s.db).await?;
+    check_if!(r.rows_affected()==1, format!("no failed mail {}.", id.id));
+    let items = MailOutbox::get_by_id(id.id, &s.db).await?;
+    check_if!(items.len()==1, format!("mail {} not found.", id.id));
+    template!(s, "mail_outbox/row.html", json!({
+        "item": items[0]
+    }))
+}
+
+async fn delete(s: S, Query(id): Query<Id>) -> HTML {
+    let r = MailOutbox::delete(id.id, &s.db).await?;
+    check_if!(r.rows_affected()==1, format!("mail {} delete failed.", id.id));
+    Ok(Html("".to_string()))
+}
@@ -0,0 +1,99 @@
+use axum::extract::Query;
+use axum::Form;
+use axum::response::Html;
+use serde::Deserialize;
+use serde_json::json;
+
+use shared::current_timestamp;
+
+use crate::{check_if, get_last_insert_id, HTML, method_router, S, template};
+use crate::service::mail_rules;
+use crate::tables::mail_rule::{MailRule, MailRuleHit};
+
+method_router!(
+    get : "/mail-rule/list"-> list,
+    post : "/mail-rule/add"-> add,
+    get : "/mail-rule/toggle"-> toggle,
+    get : "/mail-rule/delete"-> delete,
+    get : "/mail-rule/hits"-> hits,
+);
+
+/// hits shown per rule.
+const HITS_LIMIT: i64 = 50;
+
+async fn list(s: S) -> HTML {
+    template!(s, "frame.html"+"mail_rule/list.html", json!({
+        "title": "mail rules",
+        "items": MailRule::query_all(&s.db).await?,
+        "actions": mail_rules::ACTIONS,
+    }))
+}
+
+#[derive(Deserialize)]
+struct AddRuleReq {
+    name: String,
+    priority: i64,
+    #[serde(default)]
+    match_from: String,
+    #[serde(default)]
+    match_to: String,
+    #[serde(default)]
+    match_subject: String,
+    #[serde(default)]
+    match_attachment_type: String,
+    action: String,
+    #[serde(default)]
+    action_arg: String,
+}
+
+async fn add(s: S, Form(req): Form<AddRuleReq>) -> HTML {
+    let rule = MailRule {
+        name: req.name,
+        enabled: true,
+        priority: req.priority,
+        match_from: req.match_from.trim().to_string(),
+        match_to: req.match_to.trim().to_string(),
+        match_subject: req.match_subject,
+        match_attachment_type: req.match_attachment_type.trim().to_string(),
+        action: req.action,
+        action_arg: req.action_arg,
+        create_time: current_timestamp!(),
+        ..Default::default()
+    };
+    mail_rules::validate(&rule)?;
+    let r = MailRule::insert(&rule, &s.db).await?;
+    let items = MailRule::get_by_id(get_last_insert_id!(r), &s.db).await?;
+    check_if!(items.len()==1, "insert error!");
+    template!(s, "mail_rule/row.html", json!({
+        "item": items[0]
+    }))
+}
+
+#[derive(Deserialize)]
+struct Id {
+    id: i64,
+}
+
+async fn toggle(s: S, Query(id): Query<Id>) -> HTML {
+    let items = MailRule::get_by_id(id.id, &s.db).await?;
+    check_if!(items.len()==1, format!("rule {} not found.", id.id));
+    MailRule::set_enabled(id.id, !items[0].enabled, &s.db).await?;
+    let items = MailRule::get_by_id(id.id, &s.db).await?;
+    template!(s, "mail_rule/row.html", json!({
+        "item": items[0]
+    }))
+}
+
+async fn delete(s: S, Query(id): Query<Id>) -> HTML {
+    let r = MailRule::delete(id.id, &s.db).await?;
+    check_if!(r.rows_affected()==1, format!("rule {} delete failed.", id.id));
+    MailRuleHit::delete_by_rule(id.id, &s.db).await?;
+    Ok(Html("".to_string()))
+}
+
+async fn hits(s: S, Query(id): Query<Id>) -> HTML {
+    template!(s, "mail_rule/hits.html", json!({
+        "total": MailRuleHit::count_by_rule(id.id, &s.db).await?,
+        "hits": MailRuleHit::query_by_rule(id.id, HITS_LIMIT, &s.db).await?,
+    }))
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use axum::extract::{Path, RawQuery};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use serde_json::json;
+use tracing::info;
+
+use shared::current_timestamp;
+
+use crate::{HTML, method_router, R, render_fragment, S, template, Template};
+use crate::service::api_mock::{find_mock, hit_body, hit_headers};
+use crate::tables::api_entry::ApiEntry;
+use crate::tables::api_mock_hit::ApiMockHit;
+
+method_router!(
+    any : "/mock/*path" -> serve_mock,
+    get : "/api-entry/mock-hits" -> mock_hits,
+    get : "/api-entry/mock-hits/clear" -> clear_mock_hits,
+);
+
+async fn serve_mock(s: S, method: Method, Path(path): Path<String>, RawQuery(query): RawQuery, headers: HeaderMap, body: String) -> R<Response> {
+    let path = format!("/{}", path.trim_start_matches('/'));
+    let query = query.unwrap_or_default();
+
+    let entries = ApiEntry::query_mocks(&s.db).await?;
+    let found = find_mock(&entries, method.as_str(), &path, &query, &body);
+
+    let (entry_id, response) = match found {
+        None => (0, (StatusCode::NOT_FOUND, format!("no mock matches {} {}", method, path)).into_response()),
+        Some(m) => {
+            let entry = m.entry;
+            if entry.mock_delay_ms > 0 {
+                tokio::time::sleep(Duration::from_millis(entry.mock_delay_ms as u64)).await;
+            }
+
+            // the body is a template when it contains template tags.
+            let content = if entry.mock_body.contains("{{") {
+                render_fragment(&s, Template::DynamicTemplate {
+                    name: format!("<mock_{}>", entry.id),
+                    content: entry.mock_body.to_string(),
+                }, json!({
+                    "method": method.as_str(),
+                    "path": path,
+                    "path_params": m.path_params,
+                    "query": query,
+                    "body": body,
+                })).await?.0
+            } else {
+                entry.mock_body.to_string()
+            };
+
+            let status = StatusCode::from_u16(entry.mock_status as u16).unwrap_or(StatusCode::OK);
+            let mut response = (status, content).into_response();
+            for line in entry.mock_headers.lines() {
+                if let Some((k, v)) = line.split_once(':') {
+                    if let (Ok(k), Ok(v)) = (HeaderName::try_from(k.trim()), HeaderValue::from_str(v.trim())) {
+                        response.headers_mut().insert(k, v);
+                    }
+                }
+            }
+            (entry.id, response)
+        }
+    };
+
+    let hit = ApiMockHit {
+        api_entry_id: entry_id,
+        method: method.to_string(),
+        path,
+        query,
+        headers: hit_headers(&headers),
+        body: hit_body(&body),
+        status: response.status().as_u16() as i32,
+        create_time: current_timestamp!(),
+        ..Default::default()
+    };
+    info!("mock hit : {} {} -> {}", hit.method, hit.path, hit.status);
+    ApiMockHit::insert(&hit, &s.db).await?;
+
+    Ok(response)
+}
+
+async fn mock_hits(s: S) -> HTML {
+    let items = ApiMockHit::query_recent(100, &s.db).await?;
+    template!(s, "api_entry/mock-hits.html", json!({
+        "items": items
+    }))
+}
+
+async fn clear_mock_hits(s: S) -> HTML {
+    let r = ApiMockHit::delete_all(&s.db).await?;
+    Ok(Html(format!("delete count : {}", r.rows_affected())))
+}
@@ -8,6 +8,11 @@ mod api_entry_controller;
 mod admin_controller;
 mod english_card_controller;
 mod email_inbox_controller;
+mod mock_controller;
+mod sse_controller;
+mod mail_rule_controller;
+#[cfg(feature = "mail_server")]
+mod mail_outbox_controller;
 
 
 ///
@@ -23,5 +28,10 @@ crate::register_routers!(
         admin_controller,
         english_card_controller,
         email_inbox_controller,
+        mock_controller,
+        sse_controller,
+        mail_rule_controller,
+        #[cfg(feature = "mail_server")]
+        mail_outbox_controller,
     );
 
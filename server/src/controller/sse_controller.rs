@@ -0,0 +1,54 @@
+use std::convert::Infallible;
+
+use axum::extract::Query;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{method_router, S};
+use crate::service::event_bus::StoredEvent;
+
+method_router!(
+    get : "/events" -> events,
+);
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// comma separated event names to receive, everything when empty, `/events?types=new_mail,todo_changed`
+    #[serde(default)]
+    types: String,
+}
+
+///
+/// live app events as server sent events, bind them with htmx `sse-swap="<event name>"`.
+/// a reconnecting browser sends `Last-Event-ID` and gets what it missed replayed first.
+async fn events(s: S, headers: HeaderMap, Query(q): Query<EventsQuery>) -> Sse<impl Stream<Item=Result<Event, Infallible>>> {
+    let last_event_id = headers.get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let types: Vec<String> = q.types.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+
+    let (replay, receiver) = s.event_bus.subscribe(last_event_id);
+    let live = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(e) => return Some((e, receiver)),
+                // a slow client skips what it missed rather than blocking everyone.
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let stream = futures::stream::iter(replay).chain(live)
+        .filter(move |e: &StoredEvent| std::future::ready(types.is_empty() || types.iter().any(|t| t == e.event.name())))
+        .map(|e| Ok(Event::default()
+            .id(e.id.to_string())
+            .event(e.event.name())
+            // a bare `\r` would make axum panic, data lines are split on `\n` only.
+            .data(e.event.html().replace('\r', ""))));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
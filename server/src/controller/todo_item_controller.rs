@@ -1,24 +1,24 @@
-use std::sync::Arc;
 use anyhow::{bail, ensure};
 use axum::extract::Query;
 use axum::response::Html;
 
-use axum::{Form, Router};
-use axum::routing::{get, post};
+use axum::Form;
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::{AppState, check_if, get_last_insert_id, template};
+use crate::{check_if, get_last_insert_id, method_router, template};
 use crate::{HTML, S};
+use crate::service::backplane::emit;
+use crate::service::event_bus::AppEvent;
+use crate::service::response_cache::{CacheOptions, invalidate, TAG_TODO_ITEM};
 use crate::tables::todo_item::{AddTodoItem, TodoItem, UpdateTodoItem};
 
-pub fn init() -> Router<Arc<AppState>> {
-    Router::new()
-        .route("/todo/list", get(todo_list))
-        .route("/todo/mark-done", get(mark_done))
-        .route("/todo/add-todo", post(add_todo))
-        .route("/todo/delete", get(delete))
-}
+method_router!(
+    get : "/todo/list" -> todo_list with CacheOptions::ttl(60).tag(TAG_TODO_ITEM),
+    get : "/todo/mark-done" -> mark_done,
+    post : "/todo/add-todo" -> add_todo,
+    get : "/todo/delete" -> delete,
+);
 
 
 
@@ -38,6 +38,8 @@ struct TodoId {
 async fn delete(Query(todoId): Query<TodoId>, s: S) -> HTML {
     let items = TodoItem::delete(todoId.id as i64, &s.db).await?;
     check_if!(items.rows_affected()==1, format!("item {} delete failed.", todoId.id));
+    invalidate(&s, TAG_TODO_ITEM).await;
+    emit(&s, AppEvent::TodoChanged { id: todoId.id as i64, action: "deleted".to_string(), title: "".to_string() }).await;
     Ok(Html("".to_string()))
 }
 async fn mark_done(Query(todoId): Query<TodoId>, s: S) -> HTML {
@@ -46,9 +48,11 @@ async fn mark_done(Query(todoId): Query<TodoId>, s: S) -> HTML {
 
     let update_result = TodoItem::update(todoId.id, UpdateTodoItem { title: (&items[0].title).to_string(), status: "DONE".to_string() }, &s.db).await?;
     check_if!(update_result.rows_affected()==1, format!("todo item : {} update failed!", todoId.id));
+    invalidate(&s, TAG_TODO_ITEM).await;
 
     let items = TodoItem::get_by_id(todoId.id, &s.db).await?;
     check_if!(items.len()==1, format!("item {} not found.", todoId.id));
+    emit(&s, AppEvent::TodoChanged { id: items[0].id, action: "done".to_string(), title: items[0].title.to_string() }).await;
 
 
     template!(s, "todo_item/todo_item.html", json!({
@@ -69,10 +73,12 @@ async fn add_todo(s: S , Form(add_todo): Form<AddTodoReq>) -> HTML {
     }, &s.db).await?;
 
     check_if!(r.rows_affected()==1 , "insert error!");
+    invalidate(&s, TAG_TODO_ITEM).await;
 
 
     let items = TodoItem::get_by_id(get_last_insert_id!(r) as u32, &s.db).await?;
     check_if!(items.len()==1 , "get_by_id error!");
+    emit(&s, AppEvent::TodoChanged { id: items[0].id, action: "added".to_string(), title: items[0].title.to_string() }).await;
 
     template!(s, "todo_item/todo_item.html", json!({
         "item": items[0]
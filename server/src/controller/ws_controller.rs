@@ -1,24 +1,42 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::{headers, Router, TypedHeader};
 use axum::{
     extract::ws::{Message, WebSocket, WebSocketUpgrade},
     response::IntoResponse,
 };
+use axum::extract::{Query, State};
 use axum::routing::get;
-//allows to split the websocket stream into separate TX and RX branches
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::time::{Instant, interval};
 use tracing::info;
 
 use crate::AppState;
+use crate::service::backplane::publish_ws;
+use crate::service::html::escape_html;
+use crate::service::ws_hub::oob;
 
 pub fn init() -> Router<Arc<AppState>> {
     Router::new()
         .route("/ws", get(ws_handler))
 }
 
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// no frame from the client for this long (pongs included) and we hang up.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
 
+#[derive(Deserialize)]
+struct WsParams {
+    /// comma separated topics to subscribe right away, `/ws?topics=chat,todo`
+    #[serde(default)]
+    topics: String,
+}
 
 async fn ws_handler(
+    State(s): State<Arc<AppState>>,
+    Query(params): Query<WsParams>,
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
 ) -> impl IntoResponse {
@@ -26,41 +44,70 @@ async fn ws_handler(
         info!("`{}` connected", user_agent.as_str());
     }
 
-    ws.on_upgrade(handle_socket)
+    let topics: Vec<String> = params.topics.split(',').map(|t| t.trim().to_string()).collect();
+    ws.on_upgrade(move |socket| handle_socket(s, socket, topics))
 }
 
-async fn handle_socket(mut socket: WebSocket) {
-    loop{
-        if let Some(msg) = socket.recv().await {
-            if let Ok(msg) = msg {
-                match msg{
-                    Message::Text(msg) => {
-                        info!("Client says: {:?}", msg);
-                        //客户端发什么，服务端就回什么（只是演示而已）
-                        if let Err(e) = socket
-                            .send(Message::Text(format!("{:?}", r#"
-                             <div id="notifications" hx-swap-oob="beforeend">
-  <p>New messages</p>
- </div>
-                            "#)))
-                            .await
-                        {
-                            info!("send msg error  : {:?}", e);
-                            return;
-                        }
-                    }
+async fn handle_socket(s: Arc<AppState>, mut socket: WebSocket, topics: Vec<String>) {
+    let (id, mut receiver) = s.ws_hub.connect(topics);
+    info!("ws client {} connected", id);
+
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => {
+                let Some(Ok(msg)) = msg else { break; };
+                last_seen = Instant::now();
+                match msg {
+                    Message::Text(text) => handle_client_message(&s, id, &text).await,
                     Message::Close(e) => {
                         info!("client disconnected : {:?}", e);
-                        return;
+                        break;
                     }
-                    _=>{}
+                    _ => {}
+                }
+            }
+            out = receiver.recv() => {
+                // `None` means the hub dropped us, we were too slow.
+                let Some(out) = out else { break; };
+                if let Err(e) = socket.send(Message::Text(out)).await {
+                    info!("send msg error  : {:?}", e);
+                    break;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    info!("ws client {} timed out", id);
+                    break;
+                }
+                if socket.send(Message::Ping(vec![])).await.is_err() {
+                    break;
                 }
-
-            } else {
-                info!("client disconnected");
-                return;
             }
         }
     }
 
-}
\ No newline at end of file
+    s.ws_hub.disconnect(id);
+    info!("ws client {} disconnected", id);
+}
+
+///
+/// messages from the htmx ws extension are the form fields as json.
+/// `subscribe` / `unsubscribe` manage topics, `chat_message` is relayed to the `chat` topic.
+async fn handle_client_message(s: &Arc<AppState>, id: u64, text: &str) {
+    let Ok(msg) = serde_json::from_str::<Value>(text) else {
+        info!("Client says: {:?}", text);
+        return;
+    };
+    if let Some(topic) = msg["subscribe"].as_str() {
+        s.ws_hub.subscribe(id, topic);
+    }
+    if let Some(topic) = msg["unsubscribe"].as_str() {
+        s.ws_hub.unsubscribe(id, topic);
+    }
+    if let Some(chat) = msg["chat_message"].as_str().filter(|m| !m.trim().is_empty()) {
+        publish_ws(s, "chat", oob("notifications", "beforeend", &format!("<p>{}</p>", escape_html(chat)))).await;
+    }
+}
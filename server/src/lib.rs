@@ -8,7 +8,7 @@ use async_channel::Receiver;
 
 use axum::extract::State;
 use axum::http::{Method, StatusCode};
-use axum::Json;
+use axum::{Extension, Json};
 use axum::response::{Html, IntoResponse, Response};
 use axum::Router;
 use axum_server::Handle;
@@ -33,6 +33,10 @@ use crate::config::init_config;
 use crate::controller::app_routers;
 use crate::service::template_service;
 use crate::service::template_service::{TemplateService};
+use crate::service::backplane::Backplane;
+use crate::service::event_bus::EventBus;
+use crate::service::rate_limit::RateLimitLayer;
+use crate::service::ws_hub::WsHub;
 use crate::tables::DBPool;
 use crate::tables::email_inbox::EmailInbox;
 
@@ -83,6 +87,9 @@ pub struct AppState {
     pub template_service: TemplateService,
     pub db: DBPool,
     pub redis_service: Box<dyn RedisAPI + Send + Sync>,
+    pub ws_hub: WsHub,
+    pub backplane: Backplane,
+    pub event_bus: EventBus,
     pub config: Config,
 }
 
@@ -100,13 +107,18 @@ pub async fn init_app_state(config: &Config, use_test_pool: bool) -> Arc<AppStat
         template_service: TemplateService::new(req_sender),
         db: if final_test_pool { tables::init_test_pool().await } else { tables::init_pool(&config).await },
         #[cfg(feature = "redis")]
-        redis_service: Box::new(redis::RedisService::new(config.redis_uri.clone(), final_test_pool).await.unwrap()),
+        redis_service: redis::connect(config.redis_uri.clone(), &config.redis_mode, final_test_pool).await.unwrap(),
         #[cfg(not(feature = "redis"))]
-        redis_service: Box::new(crate::service::redis_fake_service::RedisFakeService::new(config.redis_uri.clone(), final_test_pool).await.unwrap()),
+        redis_service: Box::new(crate::service::redis_memory_service::RedisMemoryService::from_config(config, final_test_pool).unwrap()),
+        ws_hub: WsHub::default(),
+        backplane: Backplane::default(),
+        event_bus: EventBus::default(),
         config: config.clone(),
     });
 
 
+    service::backplane::start(app_state.clone());
+
     #[cfg(feature = "tpl")]
     start_template_backend_thread(Box::new(tpl::TplEngine {}), req_receiver);
     #[cfg(not(feature = "tpl"))]
@@ -134,7 +146,7 @@ pub async fn start_server(router: Router, app_state: Arc<AppState>) -> anyhow::R
     #[cfg(not(feature = "https"))]
     // run it with hyper on localhost:3000
     axum_server::bind(addr)
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
 
@@ -143,13 +155,28 @@ pub async fn start_server(router: Router, app_state: Arc<AppState>) -> anyhow::R
 
     #[cfg(feature = "https")]
     https::start_https_server(&https::HttpsConfig {
+        mode: match app_state.config.https_cert.mode {
+            crate::config::CertMode::Acme => https::CertMode::Acme {
+                directory: app_state.config.https_cert.directory.clone(),
+                challenge: match app_state.config.https_cert.challenge {
+                    crate::config::AcmeChallenge::TlsAlpn01 => https::Challenge::TlsAlpn01,
+                    crate::config::AcmeChallenge::Http01 => https::Challenge::Http01,
+                },
+            },
+            crate::config::CertMode::Static => https::CertMode::Static {
+                cert_path: app_state.config.https_cert.cert_path.clone(),
+                key_path: app_state.config.https_cert.key_path.clone(),
+            },
+            crate::config::CertMode::SelfSigned => https::CertMode::SelfSigned,
+        },
         domains: app_state.config.https_cert.domains.clone(),
         email: app_state.config.https_cert.emails.clone(),
         cache_dir: certs_path.to_str().unwrap().to_string(),
-        prod: true,
         http_port: server_port as u16,
         https_port: app_state.config.https_cert.https_port,
-    }, router).await;
+        hsts_max_age: app_state.config.https_cert.hsts_max_age,
+        hsts_include_subdomains: app_state.config.https_cert.hsts_include_subdomains,
+    }, crate::service::vhost::router(&app_state.config.https_cert, router)?).await?;
 
 
     Ok(())
@@ -175,13 +202,14 @@ pub struct Success {}
 
 #[macro_export]
 macro_rules! register_routers {
-    ($($c: ident),*$(,)?) => {
+    ($($(#[$m: meta])* $c: ident),*$(,)?) => {
             use axum::Router;
             pub fn app_routers() -> Router<std::sync::Arc<crate::AppState>> {
 
             let mut router = Router::new();
             $(
 
+                $(#[$m])*
                 #[allow(unused_assignments)]
                 {
                     router= router.merge($c::init());
@@ -194,13 +222,16 @@ macro_rules! register_routers {
     };
 }
 
+///
+/// `get : "/x" -> handler with CacheOptions::ttl(60)` puts the route behind the response cache.
 #[macro_export]
 macro_rules! method_router {
-    ($($m: ident : $u :literal-> $f: ident),*$(,)?) => {
+    ($($m: ident : $u :literal-> $f: ident $(with $c: expr)?),*$(,)?) => {
         pub fn init() -> axum::Router<std::sync::Arc<crate::AppState>> {
             let mut router = axum::Router::new();
             $(
-                router = router.route($u, axum::routing::$m($f));
+                router = router.route($u, axum::routing::$m($f)
+                    $(.route_layer(axum::middleware::from_fn_with_state($c, crate::service::response_cache::middleware)))?);
             )*
             router
         }
@@ -218,7 +249,10 @@ pub fn routers(app_state: Arc<AppState>) -> Router {
 
     Router::new()
         .merge(app_routers())
-        .with_state(app_state)
+        .with_state(app_state.clone())
+        .layer(RateLimitLayer::new(app_state.clone()))
+        // the response cache middleware reaches redis through this.
+        .layer(Extension(app_state))
         // logging so we can see whats going on
         .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::default().include_headers(true)))
         .layer(TimeoutLayer::new(Duration::from_secs(3)))
@@ -352,18 +386,48 @@ async fn render_fragment(s: &S, fragment: Template, data: Value) -> R<Html<Strin
 
 
 #[cfg(feature = "mail_server")]
-pub async fn handle_email_message(copy_appstate: &Arc<AppState>, msg: &mail_server::models::message::Message) {
-    let r = EmailInbox::insert(&EmailInbox {
+pub async fn handle_email_message(copy_appstate: &Arc<AppState>, msg: &mail_server::models::message::Message, resolver: &dyn mail_server::auth::dns::DnsResolver) {
+    let attachments: Vec<service::mail_store::NewAttachment> = msg.attachments.iter().map(|a| service::mail_store::NewAttachment {
+        filename: a.filename.to_string(),
+        content_type: a.file_type.to_string(),
+        cid: a.cid.to_string(),
+        body: a.body.clone(),
+    }).collect();
+    let rules = service::mail_rules::enabled_rules(copy_appstate).await;
+    let rules = service::mail_rules::select(&rules, &service::mail_rules::incoming(msg));
+    let mut mail = EmailInbox {
         from_mail: msg.sender.to_string(),
         to_mail: msg.recipients.join(","),
+        cc_mail: msg.cc.join(","),
+        envelope_from: msg.envelope_from.to_string(),
+        envelope_to: msg.envelope_recipients.join(","),
         send_date: msg.created_at.as_ref().unwrap_or(&String::from("")).to_string(),
         subject: msg.subject.to_string(),
         plain_content: msg.plain.as_ref().unwrap_or(&String::from("")).to_string(),
         html_content: msg.html.as_ref().unwrap_or(&String::from("")).to_string(),
-        full_body: "<TODO>".to_string(),
-        attachments: "<TODO>".to_string(),
         create_time: current_timestamp!(),
         ..Default::default()
-    }, &copy_appstate.db).await;
+    };
+    let auth = mail_server::auth::verify(resolver, msg).await;
+    info!("email from {} : {}", msg.sender, auth.details);
+    mail.spf_result = auth.spf.to_string();
+    mail.dkim_result = auth.dkim.to_string();
+    mail.dmarc_result = auth.dmarc.to_string();
+    mail.auth_details = auth.details.to_string();
+    if !service::mail_rules::apply_before_save(&rules, &mut mail) {
+        info!("email from {} dropped by a rule", msg.sender);
+        service::mail_rules::run_actions(copy_appstate, &rules, msg, 0, &auth).await;
+        return;
+    }
+
+    let r = service::mail_store::save_email(copy_appstate, mail, &msg.source, &attachments).await;
     info!("email insert result : {:?}", r);
+    if let Ok(id) = r {
+        service::backplane::emit(copy_appstate, service::event_bus::AppEvent::NewMail {
+            id,
+            from: msg.sender.to_string(),
+            subject: msg.subject.to_string(),
+        }).await;
+        service::mail_rules::run_actions(copy_appstate, &rules, msg, id, &auth).await;
+    }
 }
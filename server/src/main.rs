@@ -14,7 +14,7 @@ use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use play::{ init_app_state, shutdown_another_instance, start_server};
-use play::config::init_config;
+use play::config::load_config;
 use play::routers;
 use shared::constants::DATA_DIR;
 
@@ -67,9 +67,8 @@ async fn main()->anyhow::Result<()> {
 
     subscriber.with(filter).init();
 
-    //init config
-    // init config
-    let config = init_config(false);
+    // init config, a broken one stops the boot with the problems listed.
+    let config = load_config()?;
 
     let server_port = config.server_port;
 
@@ -127,14 +126,25 @@ async fn main()->anyhow::Result<()> {
     #[cfg(feature = "mail_server")]
     tokio::spawn(async move {
         info!("starting mail server...");
-        let addr = SocketAddr::from(([0, 0, 0, 0], 25));
-        let (sever, rx) = mail_server::smtp::Builder::new().bind(addr).build();
+        let addr = SocketAddr::from(([0, 0, 0, 0], copy_appstate.config.mail_server.smtp_port));
+        play::service::smtp_setup::watch(&copy_appstate.config).expect("smtp config error");
+        let (sever, rx) = play::service::smtp_setup::builder(&copy_appstate.config)
+            .expect("smtp config error")
+            .bind(addr)
+            .build();
+        let resolver: Box<dyn mail_server::auth::dns::DnsResolver> = match mail_server::auth::dns::SystemResolver::new() {
+            Ok(resolver) => Box::new(resolver),
+            Err(e) => {
+                error!("no dns resolver, sender checks will report none : {:?}", e);
+                Box::new(mail_server::auth::dns::StaticResolver::default())
+            }
+        };
         tokio::spawn(async move {
             loop{
                 //handle message
                 match rx.recv().await {
                     Ok(msg) => {
-                        play::handle_email_message(&copy_appstate, &msg).await;
+                        play::handle_email_message(&copy_appstate, &msg, resolver.as_ref()).await;
                     }
                     Err(e) => {
                         error!("recv mail message error : {:?}", e);
@@ -146,6 +156,14 @@ async fn main()->anyhow::Result<()> {
         sever.serve().expect("create mail server failed!");
     });
 
+    play::service::mail_retention::start(app_state.clone());
+    #[cfg(feature = "mail_server")]
+    play::service::mail_outbox::start(app_state.clone());
+    #[cfg(feature = "pop3")]
+    play::service::pop3_setup::start(app_state.clone());
+    #[cfg(all(feature = "mail_server", feature = "tpl"))]
+    play::service::mail_outbox::drain_template_mails(app_state.clone());
+
 
     #[cfg(not(feature = "ui"))]
     start_server( router, app_state).await;
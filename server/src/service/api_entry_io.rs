@@ -0,0 +1,647 @@
+use anyhow::{anyhow, bail, Context};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::tables::api_entry::{ApiEntry, UpdateApiEntry};
+
+const POSTMAN_SCHEMA: &str = "https://schema.getpostman.com/json/collection/v2.1.0/collection.json";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Curl,
+    Har,
+    Postman,
+    OpenApi,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Curl,
+    Postman,
+}
+
+///
+/// parse `content` into api entries, `collection` overrides the collection name found in the document.
+pub fn import(format: ImportFormat, content: &str, collection: &str) -> anyhow::Result<Vec<UpdateApiEntry>> {
+    let mut entries = match format {
+        ImportFormat::Curl => import_curl(content)?,
+        ImportFormat::Har => import_har(content)?,
+        ImportFormat::Postman => import_postman(content)?,
+        ImportFormat::OpenApi => import_openapi(content)?,
+    };
+    if !collection.trim().is_empty() {
+        for e in entries.iter_mut() {
+            e.collection = collection.trim().to_string();
+        }
+    }
+    Ok(entries)
+}
+
+
+pub fn export_curl(entries: &[ApiEntry]) -> String {
+    entries.iter().map(to_curl).collect::<Vec<String>>().join("\n\n")
+}
+
+pub fn export_postman(name: &str, entries: &[ApiEntry]) -> Value {
+    let items = entries.iter().map(|e| {
+        let (raw, query) = if e.url_params.is_empty() {
+            (e.url.to_string(), vec![])
+        } else {
+            (format!("{}?{}", e.url, e.url_params), split_query(&e.url_params))
+        };
+        let mut request = json!({
+            "method": e.method,
+            "header": header_lines(&e.headers).into_iter()
+                .map(|(k, v)| json!({"key": k, "value": v})).collect::<Vec<Value>>(),
+            "url": {
+                "raw": raw,
+                "query": query.into_iter().map(|(k, v)| json!({"key": k, "value": v})).collect::<Vec<Value>>(),
+            },
+        });
+        if !e.body.is_empty() {
+            request["body"] = json!({"mode": "raw", "raw": e.body});
+        }
+        json!({
+            "name": format!("{} {}", e.method, e.url),
+            "request": request,
+        })
+    }).collect::<Vec<Value>>();
+
+    json!({
+        "info": {"name": name, "schema": POSTMAN_SCHEMA},
+        "item": items,
+    })
+}
+
+
+fn to_curl(e: &ApiEntry) -> String {
+    let url = if e.url_params.is_empty() { e.url.to_string() } else { format!("{}?{}", e.url, e.url_params) };
+    let mut parts = vec![format!("curl -X {} {}", e.method, shell_quote(&url))];
+    for (k, v) in header_lines(&e.headers) {
+        parts.push(format!("-H {}", shell_quote(&format!("{}: {}", k, v))));
+    }
+    if !e.body.is_empty() {
+        parts.push(format!("--data-raw {}", shell_quote(&e.body)));
+    }
+    parts.join(" \\\n  ")
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// headers are stored as `Key: value` lines, the same way the api manager page edits them.
+fn header_lines(headers: &str) -> Vec<(String, String)> {
+    headers.lines().filter_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        if k.trim().is_empty() { None } else { Some((k.trim().to_string(), v.trim().to_string())) }
+    }).collect()
+}
+
+fn join_headers(headers: &[(String, String)]) -> String {
+    headers.iter().map(|(k, v)| format!("{}: {}", k, v)).collect::<Vec<String>>().join("\n")
+}
+
+fn split_query(query: &str) -> Vec<(String, String)> {
+    query.split('&').filter(|s| !s.is_empty()).map(|pair| {
+        match pair.split_once('=') {
+            None => (pair.to_string(), "".to_string()),
+            Some((k, v)) => (k.to_string(), v.to_string()),
+        }
+    }).collect()
+}
+
+fn join_query(pairs: &[(String, String)]) -> String {
+    pairs.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<String>>().join("&")
+}
+
+/// split a full url into the `url` and `url_params` columns.
+fn split_url(full: &str) -> (String, String) {
+    let full = full.split('#').next().unwrap_or("");
+    match full.split_once('?') {
+        None => (full.to_string(), "".to_string()),
+        Some((url, query)) => (url.to_string(), query.to_string()),
+    }
+}
+
+fn new_entry(method: &str, full_url: &str, headers: &[(String, String)], body: &str, collection: &str) -> UpdateApiEntry {
+    let (url, url_params) = split_url(full_url);
+    UpdateApiEntry {
+        id: None,
+        url,
+        method: method.to_uppercase(),
+        url_params,
+        headers: join_headers(headers),
+        body: body.to_string(),
+        collection: collection.to_string(),
+        ..Default::default()
+    }
+}
+
+
+/// split shell text into commands of words, honoring quotes, escapes and line continuations.
+/// an unquoted newline, `;`, `&&`, `||` or `|` ends a command, `#` starts a comment.
+fn shell_commands(s: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut commands = vec![];
+    let mut words = vec![];
+    let mut cur = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars().peekable();
+
+    fn end_word(cur: &mut String, in_word: &mut bool, words: &mut Vec<String>) {
+        if std::mem::take(in_word) {
+            words.push(std::mem::take(cur));
+        }
+    }
+    fn end_command(words: &mut Vec<String>, commands: &mut Vec<Vec<String>>) {
+        if !words.is_empty() {
+            commands.push(std::mem::take(words));
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        None => bail!("unterminated single quote"),
+                        Some('\'') => break,
+                        Some(c) => cur.push(c),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                // ANSI-C quoting : $'...'
+                chars.next();
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        None => bail!("unterminated $' quote"),
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => cur.push('\n'),
+                            Some('r') => cur.push('\r'),
+                            Some('t') => cur.push('\t'),
+                            Some(c) => cur.push(c),
+                            None => bail!("unterminated $' quote"),
+                        },
+                        Some(c) => cur.push(c),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        None => bail!("unterminated double quote"),
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => cur.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                cur.push('\\');
+                                cur.push(c);
+                            }
+                            None => bail!("unterminated double quote"),
+                        },
+                        Some(c) => cur.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => { chars.next(); }
+                Some(c) => {
+                    in_word = true;
+                    cur.push(c);
+                }
+                None => {}
+            },
+            '#' if !in_word => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+            }
+            // a lone `&` is kept, it's more likely an unquoted url than a background job.
+            '\n' | ';' | '|' | '&' if c != '&' || chars.peek() == Some(&'&') => {
+                if c != '\n' && chars.peek() == Some(&c) {
+                    chars.next();
+                }
+                end_word(&mut cur, &mut in_word, &mut words);
+                end_command(&mut words, &mut commands);
+            }
+            c if c.is_whitespace() => end_word(&mut cur, &mut in_word, &mut words),
+            c => {
+                in_word = true;
+                cur.push(c);
+            }
+        }
+    }
+    end_word(&mut cur, &mut in_word, &mut words);
+    end_command(&mut words, &mut commands);
+    Ok(commands)
+}
+
+/// curl options that take a value we don't care about.
+const CURL_IGNORED_WITH_ARG: &[&str] = &["-o", "--output", "-m", "--max-time", "--connect-timeout", "-x", "--proxy",
+    "-w", "--write-out", "--retry", "--cacert", "--cert", "--key", "-c", "--cookie-jar", "-r", "--range"];
+
+/// import the curl commands of a shell snippet, other commands (`| jq`, `echo`) are skipped.
+fn import_curl(content: &str) -> anyhow::Result<Vec<UpdateApiEntry>> {
+    let commands: Vec<Vec<String>> = shell_commands(content)?.into_iter()
+        .filter(|words| words[0] == "curl" || words[0].ends_with("/curl"))
+        .collect();
+    if commands.is_empty() {
+        bail!("no curl command found");
+    }
+    commands.iter().map(|words| parse_curl_args(&words[1..])).collect()
+}
+
+fn parse_curl_args(args: &[String]) -> anyhow::Result<UpdateApiEntry> {
+    let mut method: Option<String> = None;
+    let mut url: Option<String> = None;
+    let mut headers = vec![];
+    let mut data: Vec<String> = vec![];
+    let mut use_get = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| anyhow!("curl option {} requires a value", arg));
+        match arg.as_str() {
+            "-X" | "--request" => method = Some(value()?),
+            "-H" | "--header" => {
+                if let Some((k, v)) = value()?.split_once(':') {
+                    headers.push((k.trim().to_string(), v.trim().to_string()));
+                }
+            }
+            "-d" | "--data" | "--data-raw" | "--data-binary" | "--data-ascii" | "--data-urlencode" => data.push(value()?),
+            "--json" => {
+                data.push(value()?);
+                headers.push(("Content-Type".to_string(), "application/json".to_string()));
+                headers.push(("Accept".to_string(), "application/json".to_string()));
+            }
+            "-u" | "--user" => {
+                let token = base64::engine::general_purpose::STANDARD.encode(value()?);
+                headers.push(("Authorization".to_string(), format!("Basic {}", token)));
+            }
+            "-b" | "--cookie" => headers.push(("Cookie".to_string(), value()?)),
+            "-A" | "--user-agent" => headers.push(("User-Agent".to_string(), value()?)),
+            "-e" | "--referer" => headers.push(("Referer".to_string(), value()?)),
+            "-G" | "--get" => use_get = true,
+            "-I" | "--head" => method = Some("HEAD".to_string()),
+            "--url" => url = Some(value()?),
+            a if CURL_IGNORED_WITH_ARG.contains(&a) => { value()?; }
+            a if a.starts_with('-') => {}
+            a => {
+                if url.is_none() {
+                    url = Some(a.to_string());
+                }
+            }
+        }
+    }
+
+    let url = url.ok_or_else(|| anyhow!("no url found in curl command"))?;
+    let mut body = data.join("&");
+    let mut full_url = url;
+    if use_get && !body.is_empty() {
+        full_url = format!("{}{}{}", full_url, if full_url.contains('?') { "&" } else { "?" }, body);
+        body = "".to_string();
+    }
+    let method = method.unwrap_or_else(|| if body.is_empty() { "GET" } else { "POST" }.to_string());
+    Ok(new_entry(&method, &full_url, &headers, &body, ""))
+}
+
+
+fn import_har(content: &str) -> anyhow::Result<Vec<UpdateApiEntry>> {
+    let har: Value = serde_json::from_str(content).context("invalid HAR json")?;
+    let entries = har["log"]["entries"].as_array().ok_or_else(|| anyhow!("HAR file has no log.entries"))?;
+    let collection = har["log"]["pages"][0]["title"].as_str().unwrap_or("har");
+
+    entries.iter().map(|entry| {
+        let req = &entry["request"];
+        let method = req["method"].as_str().ok_or_else(|| anyhow!("HAR entry has no request.method"))?;
+        let url = req["url"].as_str().ok_or_else(|| anyhow!("HAR entry has no request.url"))?;
+        let headers = name_value_pairs(&req["headers"])
+            .into_iter()
+            //skip http2 pseudo headers like `:authority`
+            .filter(|(k, _)| !k.starts_with(':'))
+            .collect::<Vec<(String, String)>>();
+        let body = req["postData"]["text"].as_str().unwrap_or("");
+        Ok(new_entry(method, url, &headers, body, collection))
+    }).collect()
+}
+
+fn name_value_pairs(v: &Value) -> Vec<(String, String)> {
+    v.as_array().map(|list| list.iter().filter_map(|h| {
+        Some((h["name"].as_str()?.to_string(), h["value"].as_str().unwrap_or("").to_string()))
+    }).collect()).unwrap_or_default()
+}
+
+
+fn import_postman(content: &str) -> anyhow::Result<Vec<UpdateApiEntry>> {
+    let doc: Value = serde_json::from_str(content).context("invalid postman collection json")?;
+    let items = doc["item"].as_array().ok_or_else(|| anyhow!("postman collection has no item list"))?;
+    let name = doc["info"]["name"].as_str().unwrap_or("postman");
+    let mut entries = vec![];
+    collect_postman_items(items, name, &mut entries)?;
+    Ok(entries)
+}
+
+fn collect_postman_items(items: &[Value], collection: &str, entries: &mut Vec<UpdateApiEntry>) -> anyhow::Result<()> {
+    for item in items {
+        // folders nest items, we flatten them into `collection/folder`.
+        if let Some(children) = item["item"].as_array() {
+            let folder = format!("{}/{}", collection, item["name"].as_str().unwrap_or(""));
+            collect_postman_items(children, &folder, entries)?;
+            continue;
+        }
+        let req = &item["request"];
+        if let Some(url) = req.as_str() {
+            entries.push(new_entry("GET", url, &[], "", collection));
+            continue;
+        }
+
+        let method = req["method"].as_str().unwrap_or("GET");
+        let url = match &req["url"] {
+            Value::String(s) => s.to_string(),
+            url => url["raw"].as_str().map(|s| s.to_string()).unwrap_or_else(|| postman_url(url)),
+        };
+        let headers = enabled_key_values(&req["header"]);
+        let body = match req["body"]["mode"].as_str() {
+            Some("raw") => req["body"]["raw"].as_str().unwrap_or("").to_string(),
+            Some("urlencoded") => join_query(&enabled_key_values(&req["body"]["urlencoded"])),
+            Some("formdata") => join_query(&enabled_key_values(&req["body"]["formdata"])),
+            _ => "".to_string(),
+        };
+        entries.push(new_entry(method, &url, &headers, &body, collection));
+    }
+    Ok(())
+}
+
+/// rebuild a url from postman's structured form when `raw` is missing.
+fn postman_url(url: &Value) -> String {
+    let join = |v: &Value, sep: &str| v.as_array().map(|l| l.iter()
+        .filter_map(|s| s.as_str()).collect::<Vec<&str>>().join(sep)).unwrap_or_default();
+    let protocol = url["protocol"].as_str().unwrap_or("http");
+    let mut s = format!("{}://{}", protocol, join(&url["host"], "."));
+    if let Some(port) = url["port"].as_str() {
+        s = format!("{}:{}", s, port);
+    }
+    let path = join(&url["path"], "/");
+    if !path.is_empty() {
+        s = format!("{}/{}", s, path);
+    }
+    let query = join_query(&enabled_key_values(&url["query"]));
+    if !query.is_empty() {
+        s = format!("{}?{}", s, query);
+    }
+    s
+}
+
+fn enabled_key_values(v: &Value) -> Vec<(String, String)> {
+    v.as_array().map(|list| list.iter()
+        .filter(|h| !h["disabled"].as_bool().unwrap_or(false))
+        .filter_map(|h| Some((h["key"].as_str()?.to_string(), h["value"].as_str().unwrap_or("").to_string())))
+        .collect()).unwrap_or_default()
+}
+
+
+const OPENAPI_METHODS: &[&str] = &["get", "post", "put", "delete", "patch", "head", "options"];
+
+fn import_openapi(content: &str) -> anyhow::Result<Vec<UpdateApiEntry>> {
+    let doc: Value = if content.trim_start().starts_with('{') {
+        serde_json::from_str(content).context("invalid openapi json")?
+    } else {
+        serde_yaml::from_str(content).context("invalid openapi yaml")?
+    };
+    let version = doc["openapi"].as_str().unwrap_or("");
+    if !version.starts_with('3') {
+        bail!("only openapi 3.x documents are supported, got : {:?}", version);
+    }
+    let collection = doc["info"]["title"].as_str().unwrap_or("openapi");
+    let mut base = doc["servers"][0]["url"].as_str().unwrap_or("http://localhost").trim_end_matches('/').to_string();
+    if base.starts_with('/') {
+        base = format!("http://localhost{}", base);
+    }
+    let paths = doc["paths"].as_object().ok_or_else(|| anyhow!("openapi document has no paths"))?;
+
+    let mut entries = vec![];
+    for (path, item) in paths {
+        for method in OPENAPI_METHODS {
+            let op = &item[*method];
+            if op.is_null() {
+                continue;
+            }
+
+            let mut query = vec![];
+            let mut headers = vec![];
+            let params = item["parameters"].as_array().into_iter().flatten()
+                .chain(op["parameters"].as_array().into_iter().flatten());
+            for p in params {
+                let name = p["name"].as_str().unwrap_or("").to_string();
+                let value = example_of(p).map(|v| scalar_string(&v)).unwrap_or_default();
+                match p["in"].as_str() {
+                    Some("query") => query.push((name, value)),
+                    Some("header") => headers.push((name, value)),
+                    _ => {}
+                }
+            }
+
+            let mut body = "".to_string();
+            if let Some(content) = op["requestBody"]["content"].as_object() {
+                if let Some((content_type, media)) = content.iter().next() {
+                    headers.push(("Content-Type".to_string(), content_type.to_string()));
+                    if let Some(example) = example_of(media).or_else(|| skeleton_of(&media["schema"], &doc, 0)) {
+                        body = if content_type.contains("json") {
+                            serde_json::to_string_pretty(&example)?
+                        } else {
+                            scalar_string(&example)
+                        };
+                    }
+                }
+            }
+
+            let mut full_url = format!("{}{}", base, path);
+            if !query.is_empty() {
+                full_url = format!("{}?{}", full_url, join_query(&query));
+            }
+            entries.push(new_entry(method, &full_url, &headers, &body, collection));
+        }
+    }
+    Ok(entries)
+}
+
+fn example_of(v: &Value) -> Option<Value> {
+    if !v["example"].is_null() {
+        return Some(v["example"].clone());
+    }
+    if let Some(examples) = v["examples"].as_object() {
+        if let Some((_, e)) = examples.iter().next() {
+            return Some(e["value"].clone());
+        }
+    }
+    if !v["schema"]["example"].is_null() {
+        return Some(v["schema"]["example"].clone());
+    }
+    if !v["schema"]["default"].is_null() {
+        return Some(v["schema"]["default"].clone());
+    }
+    None
+}
+
+/// build a placeholder value from a json schema, following local `$ref`s a few levels deep.
+fn skeleton_of(schema: &Value, doc: &Value, depth: usize) -> Option<Value> {
+    if depth > 5 || schema.is_null() {
+        return None;
+    }
+    if let Some(r) = schema["$ref"].as_str() {
+        let target = r.trim_start_matches("#/").split('/').fold(doc, |v, k| &v[k]);
+        return skeleton_of(target, doc, depth + 1);
+    }
+    if !schema["example"].is_null() {
+        return Some(schema["example"].clone());
+    }
+    Some(match schema["type"].as_str() {
+        Some("object") | None if schema["properties"].is_object() => {
+            let mut obj = serde_json::Map::new();
+            for (k, v) in schema["properties"].as_object()? {
+                obj.insert(k.to_string(), skeleton_of(v, doc, depth + 1).unwrap_or(Value::Null));
+            }
+            Value::Object(obj)
+        }
+        Some("array") => Value::Array(skeleton_of(&schema["items"], doc, depth + 1).into_iter().collect()),
+        Some("integer") | Some("number") => json!(0),
+        Some("boolean") => json!(false),
+        Some("string") => json!(""),
+        _ => return None,
+    })
+}
+
+fn scalar_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.to_string(),
+        Value::Null => "".to_string(),
+        v => v.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use shared::file_path;
+
+    use super::*;
+
+    fn as_rows(entries: &[UpdateApiEntry]) -> Vec<ApiEntry> {
+        entries.iter().enumerate().map(|(i, e)| ApiEntry {
+            id: i as i64 + 1,
+            url: e.url.to_string(),
+            method: e.method.to_string(),
+            url_params: e.url_params.to_string(),
+            headers: e.headers.to_string(),
+            body: e.body.to_string(),
+            collection: e.collection.to_string(),
+            ..Default::default()
+        }).collect()
+    }
+
+    #[test]
+    fn test_import_curl() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::Curl, include_str!(file_path!("/tests/fixtures/api_entry/requests.curl.txt")), "")?;
+        assert_eq!(entries.len(), 3);
+
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[0].url, "https://httpbin.org/get");
+        assert_eq!(entries[0].url_params, "a=1&b=2");
+        assert_eq!(entries[0].headers, "Accept: application/json");
+
+        assert_eq!(entries[1].method, "POST");
+        assert_eq!(entries[1].headers, "Content-Type: application/json\nAuthorization: Basic dXNlcjpwYXNz");
+        assert_eq!(entries[1].body, r#"{"name": "it's me"}"#);
+
+        assert_eq!(entries[2].method, "DELETE");
+        assert_eq!(entries[2].url, "https://httpbin.org/delete");
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_curl_commands() -> anyhow::Result<()> {
+        let content = "echo curl is next; curl -d curl https://a.com/x && curl -X POST https://a.com/y | jq .\n\
+            # curl https://a.com/commented\n\
+            /usr/bin/curl 'https://a.com/z?a=1&b=2' -H 'X-List: a; b' # trailing comment\n";
+        let entries = import(ImportFormat::Curl, content, "")?;
+        assert_eq!(entries.iter().map(|e| e.url.as_str()).collect::<Vec<_>>(), vec!["https://a.com/x", "https://a.com/y", "https://a.com/z"]);
+        assert_eq!(entries[0].body, "curl");
+        assert_eq!(entries[1].method, "POST");
+        assert_eq!(entries[2].url_params, "a=1&b=2");
+        assert_eq!(entries[2].headers, "X-List: a; b");
+
+        assert!(import(ImportFormat::Curl, "echo curl", "").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_har() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::Har, include_str!(file_path!("/tests/fixtures/api_entry/requests.har")), "")?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].collection, "httpbin");
+        assert_eq!(entries[0].url_params, "q=rust");
+        assert_eq!(entries[0].headers, "Accept: */*");
+        assert_eq!(entries[1].method, "POST");
+        assert_eq!(entries[1].body, "name=play");
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_postman() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::Postman, include_str!(file_path!("/tests/fixtures/api_entry/requests.postman_collection.json")), "")?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].collection, "httpbin");
+        assert_eq!(entries[0].url, "https://httpbin.org/get");
+        assert_eq!(entries[0].url_params, "page=1");
+        // disabled headers are skipped
+        assert_eq!(entries[0].headers, "Accept: application/json");
+        assert_eq!(entries[1].collection, "httpbin/forms");
+        assert_eq!(entries[1].body, "name=play&lang=rust");
+        assert_eq!(entries[2].url, "https://httpbin.org/anything/1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_openapi() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::OpenApi, include_str!(file_path!("/tests/fixtures/api_entry/petstore.openapi.json")), "pets")?;
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|e| e.collection == "pets"));
+
+        let list = entries.iter().find(|e| e.method == "GET" && e.url.ends_with("/pets")).unwrap();
+        assert_eq!(list.url, "https://petstore.example.com/v1/pets");
+        assert_eq!(list.url_params, "limit=20");
+
+        let create = entries.iter().find(|e| e.method == "POST").unwrap();
+        assert_eq!(create.headers, "Content-Type: application/json");
+        let body: Value = serde_json::from_str(&create.body)?;
+        assert_eq!(body, json!({"id": 0, "name": "", "tag": ""}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_curl_round_trip() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::Curl, include_str!(file_path!("/tests/fixtures/api_entry/requests.curl.txt")), "")?;
+        let exported = export_curl(&as_rows(&entries));
+        let imported = import(ImportFormat::Curl, &exported, "")?;
+        assert_eq!(imported, entries);
+        Ok(())
+    }
+
+    #[test]
+    fn test_postman_round_trip() -> anyhow::Result<()> {
+        let entries = import(ImportFormat::Har, include_str!(file_path!("/tests/fixtures/api_entry/requests.har")), "")?;
+        let exported = export_postman("httpbin", &as_rows(&entries));
+        let imported = import(ImportFormat::Postman, &exported.to_string(), "")?;
+        assert_eq!(imported, entries);
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
+use regex::Regex;
+
+use crate::tables::api_entry::ApiEntry;
+
+/// longest delay a mock may ask for, the server wide timeout would cut it anyway.
+pub const MAX_MOCK_DELAY_MS: i64 = 2500;
+/// how much of a request body a mock hit keeps.
+pub const MAX_HIT_BODY: usize = 16 * 1024;
+/// credentials sent to a mock stay out of the hit log.
+const REDACTED_HEADERS: [&str; 6] = ["authorization", "proxy-authorization", "cookie", "x-api-key", "x-auth-token", "x-csrf-token"];
+
+/// a mock entry picked for one incoming request, along with the captured path params.
+pub struct MockMatch<'a> {
+    pub entry: &'a ApiEntry,
+    pub path_params: HashMap<String, String>,
+}
+
+///
+/// find the mock entry serving `method` + `path`.
+/// when several entries match, the one with the most literal path segments wins.
+pub fn find_mock<'a>(entries: &'a [ApiEntry], method: &str, path: &str, query: &str, body: &str) -> Option<MockMatch<'a>> {
+    entries.iter()
+        .filter(|e| e.mock_enabled == 1 && e.method.eq_ignore_ascii_case(method))
+        .filter_map(|e| {
+            let path_params = match_path(&url_path(&e.url), path)?;
+            if !match_query(&e.mock_query_match, query) || !match_body(&e.mock_body_match, body) {
+                return None;
+            }
+            Some((literal_segments(&url_path(&e.url)), MockMatch { entry: e, path_params }))
+        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, m)| m)
+}
+
+/// the path part of an entry url, `https://host/a/b?x=1` -> `/a/b`
+pub fn url_path(url: &str) -> String {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let path = match without_scheme.find('/') {
+        None => "/",
+        Some(i) if url.contains("://") => &without_scheme[i..],
+        Some(_) => without_scheme,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or("/");
+    format!("/{}", path.trim_matches('/'))
+}
+
+/// match a path pattern supporting `{name}` / `:name` captures and a trailing `*`.
+pub fn match_path(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let pattern_segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let mut params = HashMap::new();
+
+    for (i, p) in pattern_segments.iter().enumerate() {
+        if *p == "*" && i == pattern_segments.len() - 1 {
+            params.insert("*".to_string(), path_segments.get(i..).unwrap_or_default().join("/"));
+            return Some(params);
+        }
+        let segment = path_segments.get(i)?;
+        if let Some(name) = capture_name(p) {
+            params.insert(name.to_string(), segment.to_string());
+        } else if p != segment {
+            return None;
+        }
+    }
+
+    if pattern_segments.len() == path_segments.len() { Some(params) } else { None }
+}
+
+fn capture_name(segment: &str) -> Option<&str> {
+    if segment.starts_with('{') && segment.ends_with('}') && segment.len() > 2 {
+        Some(&segment[1..segment.len() - 1])
+    } else if segment.starts_with(':') && segment.len() > 1 {
+        Some(&segment[1..])
+    } else {
+        None
+    }
+}
+
+fn literal_segments(pattern: &str) -> usize {
+    pattern.split('/').filter(|s| !s.is_empty() && *s != "*" && capture_name(s).is_none()).count()
+}
+
+/// every `k=v` in `expected` must be present in `query`, a bare `k` only requires the key.
+fn match_query(expected: &str, query: &str) -> bool {
+    let actual: Vec<(&str, &str)> = query.split('&').filter(|s| !s.is_empty())
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .collect();
+    expected.split('&').filter(|s| !s.trim().is_empty()).all(|pair| {
+        match pair.trim().split_once('=') {
+            None => actual.iter().any(|(k, _)| *k == pair.trim()),
+            Some((k, v)) => actual.iter().any(|(ak, av)| *ak == k && *av == v),
+        }
+    })
+}
+
+fn match_body(expected: &str, body: &str) -> bool {
+    if expected.trim().is_empty() {
+        return true;
+    }
+    match Regex::new(expected.trim()) {
+        Ok(re) => re.is_match(body),
+        Err(_) => body.contains(expected.trim()),
+    }
+}
+
+/// the request headers of a mock hit, one `name: value` per line, credentials masked.
+pub fn hit_headers(headers: &HeaderMap) -> String {
+    headers.iter().map(|(k, v)| {
+        let value = if REDACTED_HEADERS.contains(&k.as_str()) { "***" } else { v.to_str().unwrap_or("") };
+        format!("{}: {}", k, value)
+    }).collect::<Vec<String>>().join("\n")
+}
+
+/// the request body of a mock hit, cut at `MAX_HIT_BODY` bytes.
+pub fn hit_body(body: &str) -> String {
+    if body.len() <= MAX_HIT_BODY {
+        return body.to_string();
+    }
+    let mut end = MAX_HIT_BODY;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes)", &body[..end], body.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock(id: i64, method: &str, url: &str) -> ApiEntry {
+        ApiEntry {
+            id,
+            url: url.to_string(),
+            method: method.to_string(),
+            mock_enabled: 1,
+            mock_status: 200,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_url_path() {
+        assert_eq!(url_path("https://a.com/users/{id}?x=1"), "/users/{id}");
+        assert_eq!(url_path("https://a.com"), "/");
+        assert_eq!(url_path("/users/"), "/users");
+    }
+
+    #[test]
+    fn test_match_path() {
+        let params = match_path("/users/{id}/books/:book", "/users/42/books/7").unwrap();
+        assert_eq!(params["id"], "42");
+        assert_eq!(params["book"], "7");
+
+        assert!(match_path("/users/{id}", "/users/42/books").is_none());
+        assert!(match_path("/users", "/orders").is_none());
+        assert_eq!(match_path("/files/*", "/files/a/b.txt").unwrap()["*"], "a/b.txt");
+    }
+
+    #[test]
+    fn test_find_mock() {
+        let mut with_query = mock(3, "GET", "http://localhost/users/{id}");
+        with_query.mock_query_match = "verbose=1".to_string();
+        let mut with_body = mock(4, "POST", "http://localhost/users");
+        with_body.mock_body_match = r#""name":\s*"admin""#.to_string();
+        let mut disabled = mock(5, "GET", "http://localhost/users/me");
+        disabled.mock_enabled = 0;
+
+        let entries = vec![
+            mock(1, "GET", "http://localhost/users/{id}"),
+            mock(2, "GET", "http://localhost/users/me"),
+            with_query,
+            with_body,
+            disabled,
+        ];
+
+        assert_eq!(find_mock(&entries, "GET", "/users/me", "", "").unwrap().entry.id, 2);
+        let m = find_mock(&entries, "get", "/users/9", "", "").unwrap();
+        assert_eq!(m.entry.id, 1);
+        assert_eq!(m.path_params["id"], "9");
+
+        assert_eq!(find_mock(&entries, "POST", "/users", "", r#"{"name": "admin"}"#).unwrap().entry.id, 4);
+        assert!(find_mock(&entries, "POST", "/users", "", r#"{"name": "bob"}"#).is_none());
+        assert!(find_mock(&entries, "DELETE", "/users/9", "", "").is_none());
+    }
+
+    #[test]
+    fn test_match_query() {
+        assert!(match_query("", "a=1"));
+        assert!(match_query("a=1", "b=2&a=1"));
+        assert!(match_query("a", "a=3"));
+        assert!(!match_query("a=1", "a=2"));
+    }
+
+    #[test]
+    fn test_hit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        headers.insert("cookie", "session=secret".parse().unwrap());
+        headers.insert("accept", "*/*".parse().unwrap());
+        let text = hit_headers(&headers);
+        assert!(!text.contains("secret"));
+        assert!(text.contains("authorization: ***"));
+        assert!(text.contains("accept: */*"));
+    }
+
+    #[test]
+    fn test_hit_body() {
+        assert_eq!(hit_body("{}"), "{}");
+        let big = "é".repeat(MAX_HIT_BODY);
+        let kept = hit_body(&big);
+        assert!(kept.len() < MAX_HIT_BODY + 32);
+        assert!(kept.ends_with(&format!("... ({} bytes)", big.len())));
+    }
+}
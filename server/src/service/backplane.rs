@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use shared::current_timestamp;
+use shared::redis_api::RedisAPI;
+
+use crate::AppState;
+use crate::service::event_bus::{AppEvent, StoredEvent};
+
+/// every instance publishes and listens on this one redis channel.
+pub const BACKPLANE_CHANNEL: &str = "play:backplane";
+/// an envelope for websocket subscribers of `topic`.
+pub const KIND_WS: &str = "ws";
+/// a `StoredEvent` as json, for the sse streams.
+pub const KIND_EVENT: &str = "event";
+/// the redis counter numbering events across instances.
+pub const EVENT_ID_KEY: &str = "play:event-id";
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+static INSTANCE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope {
+    /// the instance that published it, it already delivered locally so it skips its own envelopes.
+    pub origin: String,
+    pub kind: String,
+    pub topic: String,
+    pub payload: String,
+}
+
+///
+/// fans local events out to the other `play` instances through redis pub/sub.
+pub struct Backplane {
+    pub origin: String,
+}
+
+impl Default for Backplane {
+    fn default() -> Self {
+        Backplane {
+            origin: format!("{}-{}-{}", std::process::id(), current_timestamp!(), INSTANCE_SEQ.fetch_add(1, Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Backplane {
+    pub async fn forward(&self, redis: &(dyn RedisAPI + Send + Sync), kind: &str, topic: &str, payload: &str) {
+        let envelope = Envelope {
+            origin: self.origin.to_string(),
+            kind: kind.to_string(),
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+        };
+        let Ok(msg) = serde_json::to_string(&envelope) else { return; };
+        if let Err(e) = redis.publish(BACKPLANE_CHANNEL, &msg).await {
+            warn!("backplane publish failed : {:?}", e);
+        }
+    }
+}
+
+/// publish to websocket subscribers of `topic` on this instance and every other one.
+pub async fn publish_ws(s: &AppState, topic: &str, payload: impl Into<String>) -> usize {
+    let payload = payload.into();
+    let delivered = s.ws_hub.publish(topic, payload.to_string());
+    s.backplane.forward(s.redis_service.as_ref(), KIND_WS, topic, &payload).await;
+    delivered
+}
+
+///
+/// emit an event to the sse streams of this instance and every other one.
+/// its id comes from `EVENT_ID_KEY` and travels with it, so every instance replays the same ids.
+pub async fn emit(s: &AppState, event: AppEvent) {
+    let stored = match s.redis_service.incr_by(EVENT_ID_KEY, 1).await {
+        Ok(id) => s.event_bus.emit_as(id as u64, event),
+        Err(e) => {
+            warn!("no event id from redis, using a local one : {:?}", e);
+            s.event_bus.emit(event)
+        }
+    };
+    if let Ok(payload) = serde_json::to_string(&stored) {
+        s.backplane.forward(s.redis_service.as_ref(), KIND_EVENT, stored.event.name(), &payload).await;
+    }
+}
+
+/// listen to the other instances, resubscribing whenever the subscription drops.
+pub fn start(s: Arc<AppState>) {
+    tokio::spawn(async move {
+        loop {
+            match s.redis_service.subscribe(BACKPLANE_CHANNEL).await {
+                Ok(mut stream) => {
+                    info!("backplane subscribed, origin : {}", s.backplane.origin);
+                    while let Some(msg) = stream.next().await {
+                        match serde_json::from_str::<Envelope>(&msg) {
+                            Ok(envelope) => dispatch(&s, envelope),
+                            Err(e) => warn!("bad backplane message : {:?}", e),
+                        }
+                    }
+                    warn!("backplane subscription ended");
+                }
+                Err(e) => warn!("backplane subscribe failed : {:?}", e),
+            }
+            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+        }
+    });
+}
+
+fn dispatch(s: &AppState, envelope: Envelope) {
+    if envelope.origin == s.backplane.origin {
+        return;
+    }
+    match envelope.kind.as_str() {
+        KIND_WS => {
+            s.ws_hub.publish(&envelope.topic, envelope.payload);
+        }
+        KIND_EVENT => match serde_json::from_str::<StoredEvent>(&envelope.payload) {
+            Ok(stored) => {
+                s.event_bus.emit_as(stored.id, stored.event);
+            }
+            Err(e) => warn!("bad backplane event : {:?}", e),
+        },
+        kind => warn!("unknown backplane kind : {}", kind),
+    }
+}
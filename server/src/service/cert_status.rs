@@ -0,0 +1,21 @@
+use serde_json::Value;
+
+///
+/// what the https server last wrote to DATA_DIR/certs/status.json, plus a `healthy` flag.
+/// `None` without the https feature or before the https server ever started.
+pub fn load() -> anyhow::Result<Option<Value>> {
+    #[cfg(feature = "https")]
+    {
+        use std::{env, path::Path};
+        use shared::constants::DATA_DIR;
+        use shared::current_timestamp;
+
+        if let Some(status) = https::load_status(Path::new(&env::var(DATA_DIR)?).join("certs"))? {
+            let healthy = status.is_healthy(current_timestamp!() / 1000);
+            let mut value = serde_json::to_value(status)?;
+            value["healthy"] = Value::Bool(healthy);
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
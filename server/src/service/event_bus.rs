@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::service::html::escape_html;
+
+/// events kept around so reconnecting clients can catch up via `Last-Event-ID`.
+const REPLAY_CAPACITY: usize = 256;
+
+///
+/// things happening in the app that live views care about.
+/// the sse event name is the snake case variant name, e.g. `new_mail`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    NewMail { id: i64, from: String, subject: String },
+    TodoChanged { id: i64, action: String, title: String },
+    UpgradeProgress { message: String },
+}
+
+impl AppEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::NewMail { .. } => "new_mail",
+            AppEvent::TodoChanged { .. } => "todo_changed",
+            AppEvent::UpgradeProgress { .. } => "upgrade_progress",
+        }
+    }
+
+    /// the fragment htmx swaps in with `sse-swap="<name>"`.
+    pub fn html(&self) -> String {
+        match self {
+            AppEvent::NewMail { id, from, subject } =>
+                format!("<p><a href=\"/email-inbox/detail?id={}\">new mail from {} : {}</a></p>", id, escape_html(from), escape_html(subject)),
+            AppEvent::TodoChanged { id, action, title } =>
+                format!("<p>todo #{} {} : {}</p>", id, escape_html(action), escape_html(title)),
+            AppEvent::UpgradeProgress { message } =>
+                format!("<p>{}</p>", escape_html(message)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredEvent {
+    pub id: u64,
+    pub event: AppEvent,
+}
+
+struct Inner {
+    /// the highest id emitted so far.
+    last_id: u64,
+    recent: VecDeque<StoredEvent>,
+}
+
+///
+/// in-process event bus. `backplane::emit` numbers events with a redis counter and the
+/// other instances keep that id, so a `Last-Event-ID` means the same on every instance.
+pub struct EventBus {
+    inner: Mutex<Inner>,
+    sender: broadcast::Sender<StoredEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus {
+            inner: Mutex::new(Inner { last_id: 0, recent: VecDeque::with_capacity(REPLAY_CAPACITY) }),
+            sender: broadcast::channel(REPLAY_CAPACITY).0,
+        }
+    }
+}
+
+impl EventBus {
+    /// with the next id of this instance.
+    pub fn emit(&self, event: AppEvent) -> StoredEvent {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.last_id + 1;
+        Self::push(&mut inner, &self.sender, StoredEvent { id, event })
+    }
+
+    /// with an id given out elsewhere, e.g. by the redis counter.
+    pub fn emit_as(&self, id: u64, event: AppEvent) -> StoredEvent {
+        let mut inner = self.inner.lock().unwrap();
+        Self::push(&mut inner, &self.sender, StoredEvent { id, event })
+    }
+
+    fn push(inner: &mut Inner, sender: &broadcast::Sender<StoredEvent>, stored: StoredEvent) -> StoredEvent {
+        inner.last_id = inner.last_id.max(stored.id);
+        if inner.recent.len() == REPLAY_CAPACITY {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(stored.clone());
+        // sent under the lock, so a subscriber never sees an event both replayed and live.
+        let _ = sender.send(stored.clone());
+        stored
+    }
+
+    ///
+    /// the events after `last_event_id` still in the buffer, plus a receiver for everything newer.
+    /// an id from the future (say the server restarted) replays nothing.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> (Vec<StoredEvent>, broadcast::Receiver<StoredEvent>) {
+        let inner = self.inner.lock().unwrap();
+        let replay = match last_event_id {
+            Some(last) if last <= inner.last_id => inner.recent.iter().filter(|e| e.id > last).cloned().collect(),
+            _ => vec![],
+        };
+        (replay, self.sender.subscribe())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(message: &str) -> AppEvent {
+        AppEvent::UpgradeProgress { message: message.to_string() }
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let bus = EventBus::default();
+        for i in 0..3 {
+            bus.emit(progress(&i.to_string()));
+        }
+
+        let (replay, mut receiver) = bus.subscribe(Some(1));
+        assert_eq!(replay.iter().map(|e| e.id).collect::<Vec<u64>>(), vec![2, 3]);
+        assert!(bus.subscribe(None).0.is_empty());
+        assert!(bus.subscribe(Some(100)).0.is_empty());
+
+        bus.emit(progress("live"));
+        let live = receiver.recv().await.unwrap();
+        assert_eq!(live.id, 4);
+        assert_eq!(live.event.html(), "<p>live</p>");
+    }
+
+    #[test]
+    fn test_given_ids() {
+        let bus = EventBus::default();
+        bus.emit_as(7, progress("a"));
+        // from another instance, it got its id a little earlier.
+        bus.emit_as(5, progress("b"));
+        assert_eq!(bus.emit(progress("c")).id, 8);
+
+        let (replay, _) = bus.subscribe(Some(4));
+        assert_eq!(replay.iter().map(|e| e.id).collect::<Vec<u64>>(), vec![7, 5, 8]);
+        assert_eq!(bus.subscribe(Some(6)).0.len(), 2);
+        assert!(bus.subscribe(Some(9)).0.is_empty());
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let bus = EventBus::default();
+        for i in 0..REPLAY_CAPACITY + 10 {
+            bus.emit(progress(&i.to_string()));
+        }
+        let (replay, _) = bus.subscribe(Some(0));
+        assert_eq!(replay.len(), REPLAY_CAPACITY);
+        assert_eq!(replay[0].id, 11);
+    }
+
+    #[test]
+    fn test_event_json() {
+        let e = AppEvent::TodoChanged { id: 1, action: "added".to_string(), title: "<b>".to_string() };
+        assert_eq!(e.name(), "todo_changed");
+        assert_eq!(serde_json::from_str::<AppEvent>(&serde_json::to_string(&e).unwrap()).unwrap(), e);
+        assert_eq!(e.html(), "<p>todo #1 added : &lt;b&gt;</p>");
+    }
+}
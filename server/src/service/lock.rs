@@ -0,0 +1,160 @@
+//!
+//! distributed locks on `RedisAPI`, so `play` instances sharing a redis don't run the same job twice.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::bail;
+use tracing::warn;
+
+use shared::redis_api::RedisAPI;
+
+const LOCK_PREFIX: &str = "lock:";
+const FENCE_PREFIX: &str = "lock-fence:";
+/// renewals in a row that may fail on redis errors, with renewals every `ttl / 3` the lock outlives one miss.
+const RENEW_ATTEMPTS: u32 = 2;
+
+///
+/// a held lock. `token` grows with every acquire of the same name,
+/// hand it along with writes so a holder whose lock expired can be told apart from the current one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lock {
+    pub name: String,
+    pub token: i64,
+}
+
+impl Lock {
+    /// `None` while someone else holds it, the lock is gone after `ttl` seconds unless renewed.
+    pub async fn acquire(redis: &(dyn RedisAPI + Send + Sync), name: &str, ttl: u64) -> anyhow::Result<Option<Lock>> {
+        let token = redis.incr_by(&format!("{}{}", FENCE_PREFIX, name), 1).await?;
+        if !redis.set_nx_ex(&format!("{}{}", LOCK_PREFIX, name), &token.to_string(), ttl).await? {
+            return Ok(None);
+        }
+        Ok(Some(Lock { name: name.to_string(), token }))
+    }
+
+    /// false when the lock already expired and may belong to someone else now.
+    pub async fn renew(&self, redis: &(dyn RedisAPI + Send + Sync), ttl: u64) -> anyhow::Result<bool> {
+        redis.expire_if_eq(&self.key(), &self.token.to_string(), ttl).await
+    }
+
+    /// false when the lock had expired already.
+    pub async fn release(self, redis: &(dyn RedisAPI + Send + Sync)) -> anyhow::Result<bool> {
+        redis.del_if_eq(&self.key(), &self.token.to_string()).await
+    }
+
+    fn key(&self) -> String {
+        format!("{}{}", LOCK_PREFIX, self.name)
+    }
+}
+
+///
+/// run `f` holding the lock `name`, it is renewed every `ttl / 3` seconds until `f` is done.
+/// `None` when another holder has it. a lost lock, or redis failing `RENEW_ATTEMPTS` renewals in a row,
+/// drops `f` at its next await and errors, someone else may hold the lock by then.
+pub async fn with_lock<T>(redis: &(dyn RedisAPI + Send + Sync), name: &str, ttl: u64, f: impl Future<Output=T>) -> anyhow::Result<Option<T>> {
+    let Some(lock) = Lock::acquire(redis, name, ttl).await? else {
+        return Ok(None);
+    };
+
+    let renew = keep_renewed(Duration::from_secs((ttl / 3).max(1)), || lock.renew(redis, ttl));
+    let r = tokio::select! {
+        r = f => r,
+        _ = renew => bail!("lost lock {} (token {}) before the work was done.", lock.name, lock.token),
+    };
+
+    lock.release(redis).await?;
+    Ok(Some(r))
+}
+
+/// renews every `period`, returns once the lock is gone or can't be renewed anymore.
+async fn keep_renewed<F, Fut>(period: Duration, mut renew: F)
+    where F: FnMut() -> Fut, Fut: Future<Output=anyhow::Result<bool>> {
+    let mut interval = tokio::time::interval(period);
+    interval.tick().await;
+    let mut failures = 0;
+    loop {
+        interval.tick().await;
+        match renew().await {
+            Ok(true) => failures = 0,
+            Ok(false) => return,
+            Err(e) => {
+                failures += 1;
+                warn!("renewing a lock failed ({}/{}) : {:?}", failures, RENEW_ATTEMPTS, e);
+                if failures >= RENEW_ATTEMPTS {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(all(test, not(feature = "redis")))]
+mod tests {
+    use crate::service::redis_memory_service::RedisMemoryService;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock() -> anyhow::Result<()> {
+        let redis = RedisMemoryService::open(None)?;
+
+        let a = Lock::acquire(&redis, "job", 10).await?.unwrap();
+        assert!(Lock::acquire(&redis, "job", 10).await?.is_none());
+        assert!(a.renew(&redis, 10).await?);
+
+        // a stale holder can neither renew nor release the next one's lock.
+        redis.del("lock:job").await?;
+        let b = Lock::acquire(&redis, "job", 10).await?.unwrap();
+        assert!(b.token > a.token);
+        assert!(!a.renew(&redis, 10).await?);
+        assert!(!a.release(&redis).await?);
+        assert!(b.release(&redis).await?);
+
+        assert_eq!(with_lock(&redis, "job", 10, async { 1 }).await?, Some(1));
+        let held = Lock::acquire(&redis, "job", 10).await?.unwrap();
+        assert_eq!(with_lock(&redis, "job", 10, async { 1 }).await?, None);
+        held.release(&redis).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_renew_retries_errors() {
+        use std::sync::Mutex;
+
+        let run = |answers: Vec<anyhow::Result<bool>>| async move {
+            let answers = Mutex::new(answers.into_iter());
+            let calls = Mutex::new(0);
+            keep_renewed(Duration::from_millis(5), || {
+                *calls.lock().unwrap() += 1;
+                let answer = answers.lock().unwrap().next().unwrap_or(Ok(false));
+                async move { answer }
+            }).await;
+            let calls = *calls.lock().unwrap();
+            calls
+        };
+
+        // a single error is retried, a second one in a row gives up.
+        assert_eq!(run(vec![Ok(true), Err(anyhow::anyhow!("timeout")), Ok(true), Err(anyhow::anyhow!("timeout")), Err(anyhow::anyhow!("timeout"))]).await, 5);
+        // a lock someone else took isn't retried.
+        assert_eq!(run(vec![Ok(true), Ok(false), Ok(true)]).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_lost_lock_stops_the_work() -> anyhow::Result<()> {
+        let redis = RedisMemoryService::open(None)?;
+        let done = std::sync::atomic::AtomicBool::new(false);
+
+        let r = with_lock(&redis, "job", 3, async {
+            // as if it expired while we were stuck, the renewal after a second notices.
+            redis.del("lock:job").await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            done.store(true, std::sync::atomic::Ordering::SeqCst);
+        }).await;
+
+        assert!(r.unwrap_err().to_string().starts_with("lost lock job"));
+        assert!(!done.load(std::sync::atomic::Ordering::SeqCst));
+        Ok(())
+    }
+}
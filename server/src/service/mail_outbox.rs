@@ -0,0 +1,175 @@
+//!
+//! outgoing mail : controllers and python templates queue an `OutgoingMail` in the `mail_outbox` table,
+//! a worker hands due mails to the smarthost. transient failures are retried with backoff,
+//! permanent ones (or too many attempts) bounce back into the local inbox.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use mail_server::outbound::{compose, retry_delay, Mailer, OutgoingMail, SendError, SmarthostConfig, SmarthostTls};
+use tracing::{error, info, warn};
+
+use shared::current_timestamp;
+
+use crate::{AppState, get_last_insert_id};
+use crate::config::OutboundConfig;
+use crate::service::{backplane, lock, mail_store};
+use crate::service::event_bus::AppEvent;
+use crate::tables::email_inbox::EmailInbox;
+use crate::tables::mail_outbox::{MailOutbox, STATUS_QUEUED};
+
+/// one instance delivers at a time, two of them would send the same mail twice.
+const OUTBOX_LOCK: &str = "mail-outbox";
+/// mails handed to the smarthost per round.
+const BATCH_SIZE: i64 = 20;
+const BOUNCE_FROM: &str = "MAILER-DAEMON";
+
+/// composes the mail first so a broken one is rejected here instead of bouncing later.
+pub async fn enqueue(s: &AppState, mail: &OutgoingMail) -> anyhow::Result<i64> {
+    compose(mail)?;
+    let now = current_timestamp!();
+    let r = MailOutbox::insert(&MailOutbox {
+        from_mail: mail.from.to_string(),
+        to_mail: mail.recipients().join(","),
+        subject: mail.subject.to_string(),
+        mail_json: serde_json::to_string(mail)?,
+        status: STATUS_QUEUED.to_string(),
+        next_attempt_at: now,
+        create_time: now,
+        update_time: now,
+        ..Default::default()
+    }, &s.db).await?;
+    Ok(get_last_insert_id!(r))
+}
+
+pub fn smarthost(config: &OutboundConfig) -> anyhow::Result<SmarthostConfig> {
+    let tls = match config.tls.as_str() {
+        "none" => SmarthostTls::None,
+        "starttls" => SmarthostTls::Starttls,
+        "tls" => SmarthostTls::Tls,
+        other => bail!("unknown outbound tls mode : {}", other),
+    };
+    Ok(SmarthostConfig {
+        host: config.smarthost.to_string(),
+        port: config.port,
+        tls,
+        username: config.username.to_string(),
+        password: config.password.to_string(),
+    })
+}
+
+///
+/// one round : try every due mail once. returns how many were tried.
+pub async fn deliver_due(s: &AppState, mailer: &Mailer) -> anyhow::Result<usize> {
+    let max_attempts = s.config.outbound.max_attempts.max(1) as i64;
+    let due = MailOutbox::query_due(current_timestamp!(), BATCH_SIZE, &s.db).await?;
+    for row in &due {
+        let attempts = row.attempts + 1;
+        let r = match serde_json::from_str::<OutgoingMail>(&row.mail_json) {
+            Ok(mail) => mailer.send(&mail).await,
+            Err(e) => Err(SendError::Permanent(format!("broken queue entry : {}", e))),
+        };
+        let now = current_timestamp!();
+        match r {
+            Ok(_) => {
+                info!("outbox #{} sent after {} attempt(s)", row.id, attempts);
+                MailOutbox::mark_sent(row.id, attempts, now, &s.db).await?;
+            }
+            Err(SendError::Transient(e)) if attempts < max_attempts => {
+                let next = now + retry_delay(attempts as u32).as_millis() as i64;
+                warn!("outbox #{} attempt {} failed, retry at {} : {}", row.id, attempts, next, e);
+                MailOutbox::reschedule(row.id, attempts, next, &e, now, &s.db).await?;
+            }
+            Err(SendError::Transient(e) | SendError::Permanent(e)) => {
+                warn!("outbox #{} failed for good after {} attempt(s) : {}", row.id, attempts, e);
+                MailOutbox::mark_failed(row.id, attempts, &e, now, &s.db).await?;
+                if let Err(e) = bounce(s, row, &e).await {
+                    error!("bounce for outbox #{} failed : {:?}", row.id, e);
+                }
+            }
+        }
+    }
+    Ok(due.len())
+}
+
+///
+/// tell the sender : a delivery failure notice lands in the local inbox, addressed to them.
+pub async fn bounce(s: &AppState, row: &MailOutbox, error: &str) -> anyhow::Result<i64> {
+    let to = serde_json::from_str::<OutgoingMail>(&row.mail_json)
+        .map(|m| m.from_address())
+        .unwrap_or(row.from_mail.to_string());
+    let subject = format!("Undelivered Mail Returned to Sender : {}", row.subject);
+    let plain = format!("your mail to {} could not be delivered after {} attempt(s).\n\n{}\n", row.to_mail, row.attempts.max(1), error);
+    let raw = format!("From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}", BOUNCE_FROM, to, subject, plain);
+
+    let id = mail_store::save_email(s, EmailInbox {
+        from_mail: BOUNCE_FROM.to_string(),
+        to_mail: to.to_string(),
+        envelope_to: to,
+        subject: subject.to_string(),
+        plain_content: plain,
+        create_time: current_timestamp!(),
+        ..Default::default()
+    }, raw.as_bytes(), &[]).await.context("save bounce")?;
+    backplane::emit(s, AppEvent::NewMail { id, from: BOUNCE_FROM.to_string(), subject }).await;
+    Ok(id)
+}
+
+///
+/// the delivery loop, a no-op without a smarthost : mails just stay queued.
+pub fn start(s: Arc<AppState>) {
+    let config = s.config.outbound.clone();
+    if config.smarthost.is_empty() {
+        info!("no smarthost configured, outgoing mail stays queued.");
+        return;
+    }
+    let mailer = match smarthost(&config).and_then(|c| Mailer::new(&c)) {
+        Ok(m) => m,
+        Err(e) => {
+            error!("outbound mail disabled : {:?}", e);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.poll_secs.max(1)));
+        loop {
+            interval.tick().await;
+            match lock::with_lock(s.redis_service.as_ref(), OUTBOX_LOCK, 60, deliver_due(&s, &mailer)).await {
+                Ok(Some(Err(e))) | Err(e) => warn!("outbox round failed : {:?}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+/// mails from python templates arrive as json, broken ones are only logged.
+#[cfg(feature = "tpl")]
+pub fn drain_template_mails(s: Arc<AppState>) {
+    tokio::spawn(async move {
+        while let Ok(mail_json) = tpl::OUTGOING_MAILS.1.recv().await {
+            let r = match serde_json::from_str::<OutgoingMail>(&mail_json) {
+                Ok(mail) => enqueue(&s, &mail).await,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = r {
+                warn!("template mail dropped : {:?}", e);
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smarthost() {
+        let config = OutboundConfig { smarthost: "smtp.x.com".to_string(), tls: "tls".to_string(), ..Default::default() };
+        let c = smarthost(&config).unwrap();
+        assert_eq!((c.host.as_str(), c.port, c.tls), ("smtp.x.com", 587, SmarthostTls::Tls));
+        assert!(smarthost(&OutboundConfig { tls: "ssl".to_string(), ..Default::default() }).is_err());
+    }
+}
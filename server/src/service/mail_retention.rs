@@ -0,0 +1,209 @@
+//!
+//! keeps `email_inbox` from growing forever : per mailbox limits on age, count and bytes,
+//! checked by a background task. a mail in several mailboxes goes as soon as one of them drops it.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::Local;
+use tracing::{info, warn};
+
+use shared::constants::DATA_DIR;
+use shared::current_timestamp;
+
+use crate::AppState;
+use crate::config::RetentionConfig;
+use crate::service::{lock, mail_store};
+use crate::tables::email_inbox::{EmailInbox, InboxFilter};
+
+pub const ARCHIVE_DIR: &str = "mail-archive";
+const RETENTION_LOCK: &str = "mail-retention";
+const DAY_MILLIS: i64 = 24 * 3600 * 1000;
+
+/// 0 means no limit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+    pub max_age_days: u64,
+    pub max_count: u64,
+    pub max_bytes: u64,
+}
+
+impl Policy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_age_days == 0 && self.max_count == 0 && self.max_bytes == 0
+    }
+
+    /// `30 days, 100 mails` for the inbox page.
+    pub fn describe(&self) -> String {
+        let mut limits = vec![];
+        if self.max_age_days > 0 {
+            limits.push(format!("{} days", self.max_age_days));
+        }
+        if self.max_count > 0 {
+            limits.push(format!("{} mails", self.max_count));
+        }
+        if self.max_bytes > 0 {
+            limits.push(format!("{:.1} MB", self.max_bytes as f64 / 1024.0 / 1024.0));
+        }
+        match limits.is_empty() {
+            true => "kept forever".to_string(),
+            false => format!("kept for at most {}", limits.join(", ")),
+        }
+    }
+}
+
+/// the limits of its own entry, or the global ones.
+pub fn policy_for(config: &RetentionConfig, mailbox: &str) -> Policy {
+    match config.mailboxes.iter().find(|m| m.mailbox.eq_ignore_ascii_case(mailbox.trim())) {
+        Some(m) => Policy { max_age_days: m.max_age_days, max_count: m.max_count, max_bytes: m.max_bytes },
+        None => Policy { max_age_days: config.max_age_days, max_count: config.max_count, max_bytes: config.max_bytes },
+    }
+}
+
+///
+/// the ids of the mails past the limits, `mails` are one mailbox newest first.
+/// the newest ones are kept while they fit, `now` in milliseconds like `create_time`.
+pub fn expired(mails: &[EmailInbox], policy: &Policy, now: i64) -> Vec<i64> {
+    let oldest = now - policy.max_age_days as i64 * DAY_MILLIS;
+    let mut bytes = 0;
+    let mut ids = vec![];
+    for (i, mail) in mails.iter().enumerate() {
+        bytes += mail.size.max(0) as u64;
+        let too_old = policy.max_age_days > 0 && mail.create_time < oldest;
+        let too_many = policy.max_count > 0 && i as u64 >= policy.max_count;
+        let too_big = policy.max_bytes > 0 && bytes > policy.max_bytes;
+        if too_old || too_many || too_big {
+            ids.push(mail.id);
+        }
+    }
+    ids
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct PurgeReport {
+    pub deleted: usize,
+    /// the zip under `DATA_DIR`, when archiving is on and something was purged.
+    pub archive: Option<PathBuf>,
+}
+
+/// one round over every mailbox.
+pub async fn purge(s: &AppState) -> anyhow::Result<PurgeReport> {
+    let config = &s.config.retention;
+    let mut ids = BTreeSet::new();
+    for mailbox in EmailInbox::mailboxes(&s.db).await? {
+        let policy = policy_for(config, &mailbox.address);
+        if policy.is_unlimited() {
+            continue;
+        }
+        let filter = InboxFilter { mailbox: mailbox.address.to_string(), ..Default::default() };
+        let mails = EmailInbox::search(&filter, 0, mailbox.total, &s.db).await?;
+        ids.extend(expired(&mails, &policy, current_timestamp!()));
+    }
+    if ids.is_empty() {
+        return Ok(PurgeReport::default());
+    }
+
+    let archive = match config.archive {
+        true => Some(archive(s, &ids).await?),
+        false => None,
+    };
+    for id in &ids {
+        mail_store::delete_email(s, *id).await?;
+    }
+    info!("retention purged {} mails", ids.len());
+    Ok(PurgeReport { deleted: ids.len(), archive })
+}
+
+///
+/// `DATA_DIR/mail-archive/purged-<time>.zip` : `<id>.eml` with the raw message when it was kept,
+/// `<id>.json` with the row, tags and checks included.
+async fn archive(s: &AppState, ids: &BTreeSet<i64>) -> anyhow::Result<PathBuf> {
+    let mut files: Vec<(String, Vec<u8>)> = vec![];
+    for id in ids {
+        let Some(mail) = EmailInbox::get_by_id(*id, &s.db).await?.pop() else { continue };
+        // a missing or unreadable source only loses the `.eml`, the purge still goes on.
+        if !mail.full_body.is_empty() {
+            match mail_store::read_file(&mail.full_body).await {
+                Ok(raw) => files.push((format!("{}.eml", id), raw)),
+                Err(e) => warn!("archiving email {} without its raw source : {:?}", id, e),
+            }
+        }
+        files.push((format!("{}.json", id), serde_json::to_vec_pretty(&mail)?));
+    }
+
+    let dir = Path::new(&std::env::var(DATA_DIR).context("DATA_DIR is not set")?).join(ARCHIVE_DIR);
+    let path = dir.join(format!("purged-{}.zip", Local::now().format("%Y%m%d-%H%M%S-%3f")));
+    let zip_path = path.clone();
+    tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path)?);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, body) in files {
+            zip.start_file(name, options)?;
+            zip.write_all(&body)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }).await??;
+    Ok(path)
+}
+
+/// the purge loop, a no-op when `interval_secs` is 0.
+pub fn start(s: Arc<AppState>) {
+    let interval_secs = s.config.retention.interval_secs;
+    if interval_secs == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            match lock::with_lock(s.redis_service.as_ref(), RETENTION_LOCK, 600, purge(&s)).await {
+                Ok(Some(Err(e))) | Err(e) => warn!("retention round failed : {:?}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MailboxRetention;
+
+    use super::*;
+
+    fn mail(id: i64, age_days: i64, size: i64) -> EmailInbox {
+        EmailInbox { id, create_time: 100 * DAY_MILLIS - age_days * DAY_MILLIS, size, ..Default::default() }
+    }
+
+    #[test]
+    fn test_expired() {
+        let now = 100 * DAY_MILLIS;
+        let mails = vec![mail(4, 0, 10), mail(3, 5, 10), mail(2, 20, 10), mail(1, 40, 10)];
+        let policy = |max_age_days, max_count, max_bytes| Policy { max_age_days, max_count, max_bytes };
+
+        assert!(expired(&mails, &policy(0, 0, 0), now).is_empty());
+        assert_eq!(expired(&mails, &policy(10, 0, 0), now), vec![2, 1]);
+        assert_eq!(expired(&mails, &policy(0, 3, 0), now), vec![1]);
+        assert_eq!(expired(&mails, &policy(0, 0, 25), now), vec![2, 1]);
+        assert_eq!(expired(&mails, &policy(30, 3, 0), now), vec![1]);
+    }
+
+    #[test]
+    fn test_policy_for() {
+        let config = RetentionConfig {
+            max_count: 10,
+            mailboxes: vec![MailboxRetention { mailbox: "CI@x.com".to_string(), max_age_days: 1, ..Default::default() }],
+            ..Default::default()
+        };
+        assert_eq!(policy_for(&config, "ci@x.com"), Policy { max_age_days: 1, ..Default::default() });
+        assert_eq!(policy_for(&config, "me@x.com"), Policy { max_count: 10, ..Default::default() });
+        assert_eq!(policy_for(&config, "me@x.com").describe(), "kept for at most 10 mails");
+        assert_eq!(Policy::default().describe(), "kept forever");
+    }
+}
@@ -0,0 +1,390 @@
+//!
+//! rules for received mails : match on sender, recipient, subject or attachment type,
+//! then tag, move, drop, auto reply, post to a webhook or run a python handler.
+//! `handle_email_message` runs them in priority order and logs every match as a hit of the rule.
+
+use anyhow::{ensure, Context};
+use regex::Regex;
+use tracing::warn;
+
+use crate::AppState;
+use crate::tables::email_inbox::EmailInbox;
+use crate::tables::mail_rule::MailRule;
+
+pub const ACTION_TAG: &str = "tag";
+pub const ACTION_MOVE: &str = "move";
+pub const ACTION_DROP: &str = "drop";
+pub const ACTION_AUTO_REPLY: &str = "auto_reply";
+pub const ACTION_WEBHOOK: &str = "webhook";
+pub const ACTION_PYTHON: &str = "python";
+
+pub const ACTIONS: [&str; 6] = [ACTION_TAG, ACTION_MOVE, ACTION_DROP, ACTION_AUTO_REPLY, ACTION_WEBHOOK, ACTION_PYTHON];
+
+/// what the rules look at in a received mail.
+#[derive(Debug, Clone, Default)]
+pub struct Incoming {
+    /// the `From:` header and `MAIL FROM`.
+    pub senders: Vec<String>,
+    /// `To:`, `Cc:` and the envelope recipients.
+    pub recipients: Vec<String>,
+    pub subject: String,
+    pub attachment_types: Vec<String>,
+}
+
+/// checked when a rule is saved, so a broken one never reaches `handle_email_message`.
+pub fn validate(rule: &MailRule) -> anyhow::Result<()> {
+    ensure!(ACTIONS.contains(&rule.action.as_str()), "unknown action : {}", rule.action);
+    if !rule.match_subject.is_empty() {
+        Regex::new(&rule.match_subject).context("invalid subject regex")?;
+    }
+    match rule.action.as_str() {
+        ACTION_WEBHOOK => ensure!(rule.action_arg.starts_with("http://") || rule.action_arg.starts_with("https://"), "a webhook needs an http(s) url"),
+        ACTION_DROP => {}
+        action => ensure!(!rule.action_arg.trim().is_empty(), "{} needs an argument", action),
+    }
+    Ok(())
+}
+
+pub fn matches(rule: &MailRule, mail: &Incoming) -> bool {
+    let contains = |values: &[String], part: &str| values.iter().any(|v| v.to_lowercase().contains(&part.to_lowercase()));
+    (rule.match_from.is_empty() || contains(&mail.senders, &rule.match_from))
+        && (rule.match_to.is_empty() || contains(&mail.recipients, &rule.match_to))
+        && (rule.match_subject.is_empty() || Regex::new(&rule.match_subject).map(|r| r.is_match(&mail.subject)).unwrap_or(false))
+        && (rule.match_attachment_type.is_empty() || mail.attachment_types.iter()
+            .any(|t| t.to_lowercase().starts_with(&rule.match_attachment_type.to_lowercase())))
+}
+
+/// the rules firing for `mail`, in order. nothing after a `drop` runs.
+pub fn select<'a>(rules: &'a [MailRule], mail: &Incoming) -> Vec<&'a MailRule> {
+    let mut selected = vec![];
+    for rule in rules.iter().filter(|r| r.enabled && matches(r, mail)) {
+        selected.push(rule);
+        if rule.action == ACTION_DROP {
+            break;
+        }
+    }
+    selected
+}
+
+/// tags and moves change the mail before it is stored, false when it is dropped.
+pub fn apply_before_save(rules: &[&MailRule], mail: &mut EmailInbox) -> bool {
+    for rule in rules {
+        match rule.action.as_str() {
+            ACTION_TAG => {
+                let mut tags: Vec<String> = mail.tags.split(',').map(|t| t.to_string()).filter(|t| !t.is_empty()).collect();
+                for tag in rule.action_arg.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+                    if !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+                mail.tags = tags.join(",");
+            }
+            // lowercase, the mailbox list groups that way.
+            ACTION_MOVE => mail.mailbox = rule.action_arg.trim().to_lowercase(),
+            ACTION_DROP => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+/// enabled rules, none when they can't be loaded : mail keeps coming in either way.
+pub async fn enabled_rules(s: &AppState) -> Vec<MailRule> {
+    MailRule::query_enabled(&s.db).await.unwrap_or_else(|e| {
+        warn!("load mail rules failed : {:?}", e);
+        vec![]
+    })
+}
+
+
+#[cfg(feature = "mail_server")]
+mod actions {
+    use std::time::Duration;
+
+    use mail_server::auth::{AuthResult, AuthResults};
+    use mail_server::auth::dkim::header;
+    use mail_server::models::message::Message;
+    use mail_server::outbound::OutgoingMail;
+    use serde::Serialize;
+    use serde_json::json;
+
+    use shared::current_timestamp;
+    use shared::tpl_engine_api::Template;
+
+    use crate::service::mail_outbox;
+    use crate::tables::mail_rule::MailRuleHit;
+
+    use super::*;
+
+    /// hit results are cut to this many chars, a python handler may print a lot.
+    const MAX_RESULT_LEN: usize = 500;
+    /// one auto reply per sender and rule within this time, RFC 3834 asks for a period of days.
+    const AUTO_REPLY_INTERVAL_SECS: u64 = 24 * 3600;
+
+    pub fn incoming(msg: &Message) -> Incoming {
+        Incoming {
+            senders: vec![msg.sender.to_string(), msg.envelope_from.to_string()],
+            recipients: msg.recipients.iter().chain(&msg.cc).chain(&msg.envelope_recipients).cloned().collect(),
+            subject: msg.subject.to_string(),
+            attachment_types: msg.attachments.iter().map(|a| a.file_type.to_string()).collect(),
+        }
+    }
+
+    ///
+    /// run what is left of the selected rules once the mail is stored (`email_id` 0 when it was dropped)
+    /// and log a hit for each of them, tags and moves included.
+    pub async fn run_actions(s: &AppState, rules: &[&MailRule], msg: &Message, email_id: i64, auth: &AuthResults) {
+        for rule in rules {
+            let r = match rule.action.as_str() {
+                ACTION_TAG => Ok(format!("tagged {}", rule.action_arg)),
+                ACTION_MOVE => Ok(format!("moved to {}", rule.action_arg)),
+                ACTION_DROP => Ok("dropped".to_string()),
+                ACTION_WEBHOOK => webhook(&rule.action_arg, &WebhookPayload::new(msg, email_id, auth)).await,
+                ACTION_PYTHON => python(s, rule, msg, email_id).await,
+                ACTION_AUTO_REPLY => auto_reply(s, rule, msg, auth).await,
+                action => Err(anyhow::anyhow!("unknown action : {}", action)),
+            };
+            let result = r.unwrap_or_else(|e| format!("error : {:#}", e));
+            log_hit(s, rule, email_id, &msg.sender, &msg.subject, &result).await;
+        }
+    }
+
+    async fn log_hit(s: &AppState, rule: &MailRule, email_id: i64, from: &str, subject: &str, result: &str) {
+        let result: String = result.chars().take(MAX_RESULT_LEN).collect();
+        if let Err(e) = MailRuleHit::insert(&MailRuleHit {
+            rule_id: rule.id,
+            email_id,
+            from_mail: from.to_string(),
+            subject: subject.to_string(),
+            result,
+            create_time: current_timestamp!(),
+            ..Default::default()
+        }, &s.db).await {
+            warn!("log hit of rule {} failed : {:?}", rule.id, e);
+        }
+    }
+
+    /// what a webhook receives : the message with its content, attachments without their bodies.
+    #[derive(Serialize, Debug)]
+    pub struct WebhookPayload<'a> {
+        /// 0 when the mail was dropped.
+        pub email_id: i64,
+        pub sender: &'a str,
+        pub recipients: &'a [String],
+        pub cc: &'a [String],
+        pub envelope_from: &'a str,
+        pub envelope_recipients: &'a [String],
+        pub subject: &'a str,
+        pub created_at: Option<&'a str>,
+        pub plain: Option<&'a str>,
+        pub html: Option<&'a str>,
+        pub attachments: Vec<WebhookAttachment<'a>>,
+        pub auth: &'a AuthResults,
+    }
+
+    #[derive(Serialize, Debug)]
+    pub struct WebhookAttachment<'a> {
+        pub filename: &'a str,
+        pub content_type: &'a str,
+        pub cid: &'a str,
+        pub size: usize,
+    }
+
+    impl<'a> WebhookPayload<'a> {
+        pub fn new(msg: &'a Message, email_id: i64, auth: &'a AuthResults) -> Self {
+            WebhookPayload {
+                email_id,
+                sender: &msg.sender,
+                recipients: &msg.recipients,
+                cc: &msg.cc,
+                envelope_from: &msg.envelope_from,
+                envelope_recipients: &msg.envelope_recipients,
+                subject: &msg.subject,
+                created_at: msg.created_at.as_deref(),
+                plain: msg.plain.as_deref(),
+                html: msg.html.as_deref(),
+                attachments: msg.attachments.iter().map(|a| WebhookAttachment {
+                    filename: &a.filename,
+                    content_type: &a.file_type,
+                    cid: &a.cid,
+                    size: a.body.len(),
+                }).collect(),
+                auth,
+            }
+        }
+    }
+
+    async fn webhook(url: &str, payload: &WebhookPayload<'_>) -> anyhow::Result<String> {
+        let client = reqwest::ClientBuilder::new().timeout(Duration::from_secs(5)).build()?;
+        let response = client.post(url).json(payload).send().await?.error_for_status()?;
+        Ok(format!("webhook {}", response.status()))
+    }
+
+    /// the handler is a template, rendered with the message. what it prints goes to the hit log.
+    async fn python(s: &AppState, rule: &MailRule, msg: &Message, email_id: i64) -> anyhow::Result<String> {
+        s.template_service.render_template(Template::DynamicTemplate {
+            name: format!("<mail_rule_{}>", rule.id),
+            content: rule.action_arg.to_string(),
+        }, json!({
+            "message": msg,
+            "email_id": email_id,
+            "plain": msg.plain,
+            "html": msg.html,
+        })).await
+    }
+
+    ///
+    /// why a mail gets no auto reply (RFC 3834) : bounces, list and other automatic mail,
+    /// and return paths SPF didn't vouch for, answering those would be backscatter.
+    pub fn auto_reply_skip(msg: &Message, auth: &AuthResults) -> Option<&'static str> {
+        let sender = msg.envelope_from.to_lowercase();
+        let local = sender.rsplit_once('@').map(|(l, _)| l).unwrap_or(&sender);
+        if sender.is_empty() || ["mailer-daemon", "postmaster", "noreply", "no-reply"].contains(&local)
+            || local.starts_with("owner-") || local.ends_with("-request") {
+            return Some("no return path");
+        }
+        let field = |name: &str| header(&msg.source, name).map(|v| v.to_lowercase());
+        if field("auto-submitted").is_some_and(|v| v.split(';').next().unwrap_or_default().trim() != "no") {
+            return Some("auto-submitted");
+        }
+        if field("precedence").is_some_and(|v| ["bulk", "list", "junk"].contains(&v.as_str()))
+            || field("list-id").is_some() || field("list-unsubscribe").is_some() {
+            return Some("list or bulk mail");
+        }
+        // dmarc `none` only means the domain publishes no policy.
+        if auth.spf != AuthResult::Pass || auth.dmarc == AuthResult::Fail {
+            return Some("sender not verified");
+        }
+        None
+    }
+
+    /// queued in the outbox, from the address the mail was delivered to.
+    async fn auto_reply(s: &AppState, rule: &MailRule, msg: &Message, auth: &AuthResults) -> anyhow::Result<String> {
+        if let Some(reason) = auto_reply_skip(msg, auth) {
+            return Ok(format!("skipped, {}", reason));
+        }
+        let Some(from) = msg.envelope_recipients.first().or(msg.recipients.first()) else {
+            return Ok("skipped, no recipient to reply from".to_string());
+        };
+        let key = format!("auto-reply:{}:{}", rule.id, msg.envelope_from.to_lowercase());
+        if !s.redis_service.set_nx_ex(&key, "1", AUTO_REPLY_INTERVAL_SECS).await? {
+            return Ok("skipped, replied to this sender recently".to_string());
+        }
+        let subject = match msg.subject.to_lowercase().starts_with("re:") {
+            true => msg.subject.to_string(),
+            false => format!("Re: {}", msg.subject),
+        };
+        let mut headers = vec![("Auto-Submitted".to_string(), "auto-replied".to_string())];
+        if let Some(id) = header(&msg.source, "message-id") {
+            headers.push(("In-Reply-To".to_string(), id.to_string()));
+            headers.push(("References".to_string(), id));
+        }
+        let id = mail_outbox::enqueue(s, &OutgoingMail {
+            from: from.to_string(),
+            to: vec![msg.envelope_from.to_string()],
+            subject,
+            text: rule.action_arg.to_string(),
+            headers,
+            ..Default::default()
+        }).await?;
+        Ok(format!("reply queued #{}", id))
+    }
+}
+
+#[cfg(feature = "mail_server")]
+pub use actions::{auto_reply_skip, incoming, run_actions, WebhookPayload};
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(action: &str, arg: &str) -> MailRule {
+        MailRule { id: 1, enabled: true, action: action.to_string(), action_arg: arg.to_string(), ..Default::default() }
+    }
+
+    fn mail() -> Incoming {
+        Incoming {
+            senders: vec!["Shop <Billing@Shop.com>".to_string(), "bounce@shop.com".to_string()],
+            recipients: vec!["me@x.com".to_string(), "hidden@x.com".to_string()],
+            subject: "Invoice #42".to_string(),
+            attachment_types: vec!["application/pdf".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let with = |f: fn(&mut MailRule)| {
+            let mut r = rule(ACTION_DROP, "");
+            f(&mut r);
+            matches(&r, &mail())
+        };
+        assert!(with(|_| {}));
+        assert!(with(|r| r.match_from = "billing@shop".to_string()));
+        assert!(!with(|r| r.match_from = "other.com".to_string()));
+        assert!(with(|r| r.match_to = "HIDDEN@".to_string()));
+        assert!(with(|r| r.match_subject = r"^Invoice #\d+$".to_string()));
+        assert!(!with(|r| r.match_subject = "^Receipt".to_string()));
+        assert!(with(|r| r.match_attachment_type = "application/".to_string()));
+        assert!(!with(|r| r.match_attachment_type = "image/".to_string()));
+        // every condition has to match.
+        assert!(!with(|r| {
+            r.match_from = "shop.com".to_string();
+            r.match_subject = "Receipt".to_string();
+        }));
+    }
+
+    #[test]
+    fn test_select_and_apply() {
+        let rules = vec![
+            MailRule { id: 1, match_subject: "Invoice".to_string(), ..rule(ACTION_TAG, "invoice, money") },
+            MailRule { id: 2, enabled: false, ..rule(ACTION_DROP, "") },
+            MailRule { id: 3, ..rule(ACTION_MOVE, "Billing") },
+            MailRule { id: 4, ..rule(ACTION_DROP, "") },
+            MailRule { id: 5, ..rule(ACTION_TAG, "never") },
+        ];
+        let selected = select(&rules, &mail());
+        assert_eq!(selected.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1, 3, 4]);
+
+        let mut inbox = EmailInbox { tags: "money".to_string(), ..Default::default() };
+        assert!(apply_before_save(&selected[..2], &mut inbox));
+        assert_eq!((inbox.tags.as_str(), inbox.mailbox.as_str()), ("money,invoice", "billing"));
+        assert!(!apply_before_save(&selected, &mut inbox));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&rule(ACTION_DROP, "")).is_ok());
+        assert!(validate(&rule("forward", "")).is_err());
+        assert!(validate(&rule(ACTION_TAG, " ")).is_err());
+        assert!(validate(&rule(ACTION_WEBHOOK, "ftp://x")).is_err());
+        assert!(validate(&rule(ACTION_WEBHOOK, "https://x.com/hook")).is_ok());
+        assert!(validate(&MailRule { match_subject: "(".to_string(), ..rule(ACTION_DROP, "") }).is_err());
+    }
+
+    #[cfg(feature = "mail_server")]
+    #[test]
+    fn test_auto_reply_skip() {
+        use mail_server::auth::{AuthResult, AuthResults};
+        use mail_server::models::message::Message;
+
+        let msg = |from: &str, headers: &str| Message {
+            envelope_from: from.to_string(),
+            source: format!("From: {}\r\n{}Subject: hi\r\n\r\nbody\r\n", from, headers).into_bytes(),
+            ..Default::default()
+        };
+        let pass = AuthResults { spf: AuthResult::Pass, ..Default::default() };
+
+        assert_eq!(auto_reply_skip(&msg("a@x.com", ""), &pass), None);
+        assert_eq!(auto_reply_skip(&msg("a@x.com", "Auto-Submitted: no\r\n"), &pass), None);
+        assert_eq!(auto_reply_skip(&msg("", ""), &pass), Some("no return path"));
+        assert_eq!(auto_reply_skip(&msg("MAILER-DAEMON@x.com", ""), &pass), Some("no return path"));
+        assert_eq!(auto_reply_skip(&msg("list-request@x.com", ""), &pass), Some("no return path"));
+        assert_eq!(auto_reply_skip(&msg("a@x.com", "Auto-Submitted: auto-replied\r\n"), &pass), Some("auto-submitted"));
+        assert_eq!(auto_reply_skip(&msg("a@x.com", "Precedence: Bulk\r\n"), &pass), Some("list or bulk mail"));
+        assert_eq!(auto_reply_skip(&msg("a@x.com", "List-Id: <dev.x.com>\r\n"), &pass), Some("list or bulk mail"));
+        // a forged return path is never answered.
+        assert_eq!(auto_reply_skip(&msg("a@x.com", ""), &AuthResults::default()), Some("sender not verified"));
+        assert_eq!(auto_reply_skip(&msg("a@x.com", ""), &AuthResults { dmarc: AuthResult::Fail, ..pass.clone() }), Some("sender not verified"));
+    }
+}
@@ -0,0 +1,160 @@
+//!
+//! received emails on disk : the raw `.eml` and every attachment live under `DATA_DIR/mails/<email id>/`,
+//! the tables only keep their metadata and relative paths.
+
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context};
+
+use shared::constants::DATA_DIR;
+use shared::current_timestamp;
+
+use crate::{AppState, get_last_insert_id};
+use crate::tables::email_attachment::EmailAttachment;
+use crate::tables::email_inbox::EmailInbox;
+
+pub const MAILS_DIR: &str = "mails";
+
+/// an attachment as parsed from the message, before it is stored.
+#[derive(Debug, Clone, Default)]
+pub struct NewAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub cid: String,
+    pub body: Vec<u8>,
+}
+
+fn data_dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(env::var(DATA_DIR).context("DATA_DIR is not set")?))
+}
+
+/// keeps the name readable but safe to use as a file name on any os.
+fn safe_file_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .take(100)
+        .collect();
+    match name.trim_matches('.') {
+        "" => "attachment".to_string(),
+        n => n.to_string(),
+    }
+}
+
+///
+/// insert the email, then write its raw source and attachments next to each other.
+/// `mail.full_body` ends up as the path of the `.eml`, `mail.attachments` as the file names.
+pub async fn save_email(s: &AppState, mut mail: EmailInbox, raw: &[u8], attachments: &[NewAttachment]) -> anyhow::Result<i64> {
+    mail.full_body = "".to_string();
+    mail.attachments = attachments.iter().map(|a| a.filename.to_string()).collect::<Vec<String>>().join(",");
+    mail.size = (raw.len() + attachments.iter().map(|a| a.body.len()).sum::<usize>()) as i64;
+    let r = EmailInbox::insert(&mail, &s.db).await?;
+    let id = get_last_insert_id!(r);
+
+    let dir = Path::new(MAILS_DIR).join(id.to_string());
+    tokio::fs::create_dir_all(data_dir()?.join(&dir)).await?;
+
+    let raw_path = dir.join("raw.eml");
+    tokio::fs::write(data_dir()?.join(&raw_path), raw).await?;
+    EmailInbox::update_full_body(id, &raw_path.to_string_lossy(), &s.db).await?;
+
+    for (i, a) in attachments.iter().enumerate() {
+        // prefixed with the index, two attachments may share a name.
+        let path = dir.join(format!("{}-{}", i, safe_file_name(&a.filename)));
+        tokio::fs::write(data_dir()?.join(&path), &a.body).await?;
+        EmailAttachment::insert(&EmailAttachment {
+            email_id: id,
+            filename: a.filename.to_string(),
+            content_type: a.content_type.to_string(),
+            size: a.body.len() as i64,
+            cid: a.cid.to_string(),
+            file_path: path.to_string_lossy().to_string(),
+            create_time: current_timestamp!(),
+            ..Default::default()
+        }, &s.db).await?;
+    }
+    Ok(id)
+}
+
+/// the absolute path of a relative one kept in the tables, refused if it leaves the mails dir.
+fn stored_path(relative_path: &str) -> anyhow::Result<PathBuf> {
+    let path = Path::new(relative_path);
+    if relative_path.is_empty() || !path.starts_with(MAILS_DIR) || path.components().any(|c| !matches!(c, Component::Normal(_))) {
+        bail!("not a stored mail file : {}", relative_path);
+    }
+    Ok(data_dir()?.join(path))
+}
+
+/// read a stored file by the relative path kept in the tables.
+pub async fn read_file(relative_path: &str) -> anyhow::Result<Vec<u8>> {
+    tokio::fs::read(stored_path(relative_path)?).await.with_context(|| format!("read {}", relative_path))
+}
+
+pub async fn file_size(relative_path: &str) -> anyhow::Result<u64> {
+    Ok(tokio::fs::metadata(stored_path(relative_path)?).await.with_context(|| format!("stat {}", relative_path))?.len())
+}
+
+/// the row, its attachments and their files. false when there was no such email.
+pub async fn delete_email(s: &AppState, id: i64) -> anyhow::Result<bool> {
+    let r = EmailInbox::delete(id, &s.db).await?;
+    EmailAttachment::delete_by_email(id, &s.db).await?;
+    remove_email_files(id).await?;
+    Ok(r.rows_affected() == 1)
+}
+
+///
+/// html bodies get rendered in the inbox : scripts, handlers and the like are dropped,
+/// `cid:` references point at the stored attachments so inline images still show.
+pub fn sanitize_html(html: &str, attachments: &[EmailAttachment]) -> String {
+    let mut html = html.to_string();
+    for a in attachments.iter().filter(|a| !a.cid.is_empty()) {
+        html = html.replace(&format!("cid:{}", a.cid), &format!("/email-inbox/attachment?id={}", a.id));
+    }
+    ammonia::clean(&html)
+}
+
+/// drop the files of one email, the rows are the caller's business.
+pub async fn remove_email_files(email_id: i64) -> anyhow::Result<()> {
+    let dir = data_dir()?.join(MAILS_DIR).join(email_id.to_string());
+    if dir.exists() {
+        tokio::fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+}
+
+pub async fn remove_all_files() -> anyhow::Result<()> {
+    let dir = data_dir()?.join(MAILS_DIR);
+    if dir.exists() {
+        tokio::fs::remove_dir_all(dir).await?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_file_name() {
+        assert_eq!(safe_file_name("report 2023.pdf"), "report_2023.pdf");
+        assert_eq!(safe_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(safe_file_name(".."), "attachment");
+        assert_eq!(safe_file_name("简历.docx"), "简历.docx");
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let files = vec![EmailAttachment { id: 3, cid: "logo".to_string(), ..Default::default() }];
+        let html = sanitize_html(r#"<p onclick="x()">hi<script>alert(1)</script><img src="cid:logo"><a href="javascript:x()">a</a></p>"#, &files);
+        assert_eq!(html, r#"<p>hi<img src="/email-inbox/attachment?id=3"><a rel="noopener noreferrer">a</a></p>"#);
+    }
+
+    #[tokio::test]
+    async fn test_read_file_stays_in_mails_dir() {
+        assert!(read_file("").await.is_err());
+        assert!(read_file("mails/../config.toml").await.is_err());
+        assert!(read_file("/etc/passwd").await.is_err());
+        assert!(read_file("config.toml").await.is_err());
+    }
+}
@@ -1,8 +1,28 @@
 pub mod template_service;
+pub mod api_entry_io;
+pub mod api_mock;
+pub mod text_diff;
+pub mod html;
+pub mod ws_hub;
+pub mod backplane;
+pub mod event_bus;
+pub mod response_cache;
+pub mod lock;
+pub mod rate_limit;
+pub mod mail_store;
+pub mod mail_rules;
+pub mod mail_retention;
+pub mod cert_status;
 
 #[cfg(not(feature = "redis"))]
-pub mod redis_fake_service;
+pub mod redis_memory_service;
 #[cfg(not(feature = "tpl"))]
 pub mod tpl_fake_engine;
-
-
+#[cfg(feature = "mail_server")]
+pub mod smtp_setup;
+#[cfg(feature = "mail_server")]
+pub mod mail_outbox;
+#[cfg(feature = "pop3")]
+pub mod pop3_setup;
+#[cfg(feature = "https")]
+pub mod vhost;
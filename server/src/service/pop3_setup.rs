@@ -0,0 +1,76 @@
+//!
+//! POP3 over the stored mails : a user sees the mailbox named like their account,
+//! the same mails the inbox page lists for that address. DELE + QUIT deletes for real.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{error, info, warn};
+
+use mail_server::pop3::{Maildrop, MaildropEntry};
+
+use crate::AppState;
+use crate::service::mail_store;
+use crate::service::smtp_setup::ConfigAccounts;
+use crate::tables::email_inbox::{EmailInbox, InboxFilter};
+
+pub struct InboxMaildrop(pub Arc<AppState>);
+
+#[async_trait]
+impl Maildrop for InboxMaildrop {
+    async fn list(&self, user: &str) -> anyhow::Result<Vec<MaildropEntry>> {
+        let filter = InboxFilter { mailbox: user.to_string(), ..Default::default() };
+        let total = EmailInbox::count(&filter, &self.0.db).await?;
+        let mut entries = vec![];
+        // newest first from the table, pop3 clients expect the oldest first.
+        for mail in EmailInbox::search(&filter, 0, total, &self.0.db).await?.into_iter().rev() {
+            // the raw source is what RETR sends, mails stored without one are left out.
+            if mail.full_body.is_empty() {
+                continue;
+            }
+            // one broken mail shouldn't lock the whole mailbox.
+            match mail_store::file_size(&mail.full_body).await {
+                Ok(size) => entries.push(MaildropEntry { id: mail.id, size }),
+                Err(e) => warn!("pop3 leaves out email {} : {:?}", mail.id, e),
+            }
+        }
+        Ok(entries)
+    }
+
+    async fn retrieve(&self, id: i64) -> anyhow::Result<Vec<u8>> {
+        let mails = EmailInbox::get_by_id(id, &self.0.db).await?;
+        anyhow::ensure!(mails.len() == 1, "email {} not found", id);
+        mail_store::read_file(&mails[0].full_body).await
+    }
+
+    async fn delete(&self, ids: &[i64]) -> anyhow::Result<()> {
+        for id in ids {
+            mail_store::delete_email(&self.0, *id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// listens on `mail_server.pop3_bind`:`pop3_port` in the background, nothing when the port is 0.
+pub fn start(s: Arc<AppState>) {
+    let config = s.config.mail_server.clone();
+    if config.pop3_port == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind((config.pop3_bind.as_str(), config.pop3_port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("pop3 bind on port {} failed : {:?}", config.pop3_port, e);
+                return;
+            }
+        };
+        info!("pop3 server on {}:{}", config.pop3_bind, config.pop3_port);
+        if config.pop3_bind != "127.0.0.1" && config.pop3_bind != "::1" {
+            warn!("pop3 takes passwords in clear on {}, put a tls tunnel in front.", config.pop3_bind);
+        }
+        if let Err(e) = mail_server::pop3::serve(listener, Arc::new(InboxMaildrop(s)), Arc::new(ConfigAccounts(config.accounts))).await {
+            error!("pop3 server stopped : {:?}", e);
+        }
+    });
+}
@@ -0,0 +1,258 @@
+//!
+//! per caller rate limits on `RedisAPI`, shared by every instance using the same redis.
+//! `RateLimitLayer` applies the `rate_limit` rules from `Config` to the routes.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use futures::future::BoxFuture;
+use tower::{Layer, Service};
+use tracing::warn;
+
+use shared::current_timestamp;
+use shared::redis_api::RedisAPI;
+
+use crate::AppState;
+use crate::config::{RateLimitBy, RateLimitRule};
+use crate::service::response_cache::fnv;
+
+const KEY_PREFIX: &str = "rate:";
+
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    Allowed { remaining: u64 },
+    Limited { retry_after: u64 },
+}
+
+///
+/// a sliding window counter : the current fixed window's count, plus the previous window's
+/// weighted by how much of it still overlaps the last `window_secs`.
+/// rejected requests aren't counted, so a client backing off gets through again.
+pub async fn check(redis: &(dyn RedisAPI + Send + Sync), key: &str, limit: u64, window_secs: u64) -> anyhow::Result<Decision> {
+    let window_secs = window_secs.max(1);
+    let window = window_secs as i64 * 1000;
+    let now = current_timestamp!();
+    let (index, elapsed) = (now / window, now % window);
+
+    let current_key = format!("{}{}:{}", KEY_PREFIX, key, index);
+    let count = redis.incr_by(&current_key, 1).await?;
+    if count == 1 {
+        // kept for one more window, the next one still reads it.
+        redis.expire(&current_key, window_secs * 2).await?;
+    }
+    let previous = redis.get(&format!("{}{}:{}", KEY_PREFIX, key, index - 1)).await
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(0);
+
+    let estimated = previous * (window - elapsed) / window + count;
+    if estimated > limit as i64 {
+        redis.incr_by(&current_key, -1).await?;
+        return Ok(Decision::Limited { retry_after: ((window - elapsed) / 1000).max(1) as u64 });
+    }
+    Ok(Decision::Allowed { remaining: limit - estimated as u64 })
+}
+
+/// `10.0.0.1` or `10.0.0.0/8`.
+fn is_trusted(ip: IpAddr, trusted_proxies: &[String]) -> bool {
+    trusted_proxies.iter().any(|proxy| {
+        let (net, bits) = match proxy.split_once('/') {
+            Some((net, bits)) => (net, bits.parse::<u32>().ok()),
+            None => (proxy.as_str(), None),
+        };
+        match (net.trim().parse::<IpAddr>(), ip) {
+            (Ok(IpAddr::V4(net)), IpAddr::V4(ip)) => same_prefix(u32::from(net) as u128, u32::from(ip) as u128, bits.unwrap_or(32), 32),
+            (Ok(IpAddr::V6(net)), IpAddr::V6(ip)) => same_prefix(u128::from(net), u128::from(ip), bits.unwrap_or(128), 128),
+            _ => false,
+        }
+    })
+}
+
+fn same_prefix(net: u128, ip: u128, bits: u32, width: u32) -> bool {
+    let bits = bits.min(width);
+    bits == 0 || (net ^ ip) >> (width - bits) == 0
+}
+
+///
+/// the peer address, unless the peer is one of `trusted_proxies` : then the rightmost
+/// `X-Forwarded-For` hop that isn't, anything left of it could be made up by the client.
+fn client_ip<B>(req: &Request<B>, trusted_proxies: &[String]) -> String {
+    let Some(peer) = req.extensions().get::<ConnectInfo<SocketAddr>>().map(|c| c.0.ip()) else {
+        return "unknown".to_string();
+    };
+    if !is_trusted(peer, trusted_proxies) {
+        return peer.to_string();
+    }
+    let hops: Vec<&str> = req.headers().get_all("x-forwarded-for").iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    let client = hops.iter().rev()
+        .find(|hop| !hop.parse::<IpAddr>().is_ok_and(|ip| is_trusted(ip, trusted_proxies)))
+        .or(hops.first());
+    client.map(|c| c.to_string()).unwrap_or(peer.to_string())
+}
+
+/// the buckets a request counts against. the `Authorization` header isn't verified here,
+/// so users are limited on top of their ip rather than instead of it.
+fn callers<B>(req: &Request<B>, by: RateLimitBy, trusted_proxies: &[String]) -> Vec<String> {
+    let mut callers = vec![format!("ip:{}", client_ip(req, trusted_proxies))];
+    let user = req.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+    if let (RateLimitBy::User, Some(user)) = (by, user) {
+        // hashed, the keys shouldn't carry credentials.
+        callers.insert(0, format!("user:{:016x}", fnv(user.as_bytes())));
+    }
+    callers
+}
+
+fn too_many_requests(retry_after: u64) -> Response {
+    let mut response = (StatusCode::TOO_MANY_REQUESTS, "too many requests, slow down.").into_response();
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+///
+/// a tower layer applying `Config::rate_limit`, requests matching no rule pass straight through.
+/// when redis is unreachable requests are let through rather than failing the whole site.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    state: Arc<AppState>,
+}
+
+impl RateLimitLayer {
+    pub fn new(state: Arc<AppState>) -> Self {
+        RateLimitLayer { state }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, state: self.state.clone() }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    state: Arc<AppState>,
+}
+
+impl<S, B> Service<Request<B>> for RateLimitService<S>
+    where S: Service<Request<B>, Response=Response> + Clone + Send + 'static,
+          S::Future: Send + 'static,
+          B: Send + 'static {
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        // `inner` is the one that was polled ready, leave a fresh clone behind for the next call.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let state = self.state.clone();
+
+        Box::pin(async move {
+            let rule: Option<RateLimitRule> = state.config.rate_limit.iter().find(|r| req.uri().path().starts_with(&r.path)).cloned();
+            let Some(rule) = rule else {
+                return inner.call(req).await;
+            };
+
+            let mut decision = Ok(Decision::Allowed { remaining: rule.limit });
+            for caller in callers(&req, rule.by, &state.config.trusted_proxies) {
+                let key = format!("{}|{}", rule.path, caller);
+                decision = match (decision, check(state.redis_service.as_ref(), &key, rule.limit, rule.window_secs).await) {
+                    (Ok(Decision::Allowed { remaining: a }), Ok(Decision::Allowed { remaining: b })) => Ok(Decision::Allowed { remaining: a.min(b) }),
+                    (Ok(Decision::Allowed { .. }), other) => other,
+                    (limited_or_failed, _) => limited_or_failed,
+                };
+                if !matches!(decision, Ok(Decision::Allowed { .. })) {
+                    break;
+                }
+            }
+            match decision {
+                Ok(Decision::Limited { retry_after }) => Ok(too_many_requests(retry_after)),
+                Ok(Decision::Allowed { remaining }) => {
+                    let mut response = inner.call(req).await?;
+                    response.headers_mut().insert("x-ratelimit-limit", HeaderValue::from(rule.limit));
+                    response.headers_mut().insert("x-ratelimit-remaining", HeaderValue::from(remaining));
+                    Ok(response)
+                }
+                Err(e) => {
+                    warn!("rate limit check failed, letting the request through : {:?}", e);
+                    inner.call(req).await
+                }
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "redis"))]
+    #[tokio::test]
+    async fn test_check() -> anyhow::Result<()> {
+        let redis = crate::service::redis_memory_service::RedisMemoryService::open(None)?;
+        assert_eq!(check(&redis, "a", 2, 60).await?, Decision::Allowed { remaining: 1 });
+        assert_eq!(check(&redis, "a", 2, 60).await?, Decision::Allowed { remaining: 0 });
+        assert!(matches!(check(&redis, "a", 2, 60).await?, Decision::Limited { retry_after } if retry_after <= 60));
+        // limits are per key.
+        assert_eq!(check(&redis, "b", 2, 60).await?, Decision::Allowed { remaining: 1 });
+        Ok(())
+    }
+
+    #[test]
+    fn test_callers() {
+        let req = |peer: Option<[u8; 4]>, headers: &[(&str, &str)]| {
+            let mut builder = Request::get("/x");
+            for (k, v) in headers {
+                builder = builder.header(*k, *v);
+            }
+            if let Some(peer) = peer {
+                builder = builder.extension(ConnectInfo(SocketAddr::from((peer, 4000))));
+            }
+            builder.body(()).unwrap()
+        };
+        let trusted = vec!["10.0.0.0/8".to_string(), "192.168.1.1".to_string()];
+        let ip = |r: &Request<()>| callers(r, RateLimitBy::Ip, &trusted);
+
+        assert_eq!(ip(&req(None, &[])), vec!["ip:unknown"]);
+        // forwarded addresses only count from a trusted peer.
+        assert_eq!(ip(&req(Some([8, 8, 8, 8]), &[("x-forwarded-for", "1.2.3.4")])), vec!["ip:8.8.8.8"]);
+        assert_eq!(ip(&req(Some([10, 1, 2, 3]), &[("x-forwarded-for", "1.2.3.4")])), vec!["ip:1.2.3.4"]);
+        // the rightmost untrusted hop, whatever the client put in front of it.
+        assert_eq!(ip(&req(Some([10, 1, 2, 3]), &[("x-forwarded-for", "6.6.6.6, 1.2.3.4, 192.168.1.1")])), vec!["ip:1.2.3.4"]);
+        assert_eq!(ip(&req(Some([192, 168, 1, 1]), &[])), vec!["ip:192.168.1.1"]);
+
+        let peer = Some([8, 8, 8, 8]);
+        assert_eq!(callers(&req(peer, &[("authorization", "Bearer t")]), RateLimitBy::User, &trusted),
+                   vec![format!("user:{:016x}", fnv(b"Bearer t")), "ip:8.8.8.8".to_string()]);
+        assert_eq!(callers(&req(peer, &[("authorization", "Bearer t")]), RateLimitBy::Ip, &trusted), vec!["ip:8.8.8.8"]);
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let trusted = vec!["10.0.0.0/8".to_string(), "::1".to_string(), "fd00::/8".to_string()];
+        assert!(is_trusted("10.255.0.1".parse().unwrap(), &trusted));
+        assert!(!is_trusted("11.0.0.1".parse().unwrap(), &trusted));
+        assert!(is_trusted("::1".parse().unwrap(), &trusted));
+        assert!(is_trusted("fd12::1".parse().unwrap(), &trusted));
+        assert!(!is_trusted("127.0.0.1".parse().unwrap(), &trusted));
+        assert!(!is_trusted("10.0.0.1".parse().unwrap(), &[]));
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info};
+
+use shared::constants::DATA_DIR;
+
+use shared::current_timestamp;
+use shared::redis_api::RedisAPI;
+
+use crate::config::Config;
+
+/// buffered messages per channel, a subscriber lagging behind more than this loses the oldest.
+const CHANNEL_CAPACITY: usize = 256;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+/// how often a changed store is written to its file.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    /// pub/sub channels are process wide, so several app states in one process
+    /// talk to each other the same way separate instances do through one redis.
+    static ref CHANNELS: DashMap<String, broadcast::Sender<String>> = DashMap::new();
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Hash(HashMap<String, String>),
+    List(VecDeque<String>),
+    Set(BTreeSet<String>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Entry {
+    value: Value,
+    /// unix millis
+    expire_at: Option<i64>,
+}
+
+impl Entry {
+    fn expired(&self) -> bool {
+        matches!(self.expire_at, Some(at) if at <= current_timestamp!())
+    }
+}
+
+///
+/// embedded redis for deployments without one, used when the `redis` feature is off.
+/// expired keys are dropped when touched and by a background sweep,
+/// the store can be snapshotted to a json file under `DATA_DIR`.
+pub struct RedisMemoryService {
+    kv: Arc<DashMap<String, Entry>>,
+    dirty: Arc<AtomicBool>,
+}
+
+fn channel(name: &str) -> broadcast::Sender<String> {
+    CHANNELS.entry(name.to_string())
+        .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+        .clone()
+}
+
+fn wrong_type() -> anyhow::Error {
+    anyhow!("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+/// glob match supporting `*` and `?`
+fn glob(pattern: &[u8], s: &[u8]) -> bool {
+    match (pattern.first(), s.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => glob(&pattern[1..], s) || (!s.is_empty() && glob(pattern, &s[1..])),
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &s[1..]),
+        (Some(p), Some(c)) if p == c => glob(&pattern[1..], &s[1..]),
+        _ => false,
+    }
+}
+
+impl RedisMemoryService {
+    /// `redis_memory_file` in the config turns persistence on, tests always stay in memory.
+    pub fn from_config(config: &Config, is_test: bool) -> anyhow::Result<Self> {
+        if is_test || config.redis_memory_file.is_empty() {
+            return Self::open(None);
+        }
+        Self::open(Some(Path::new(env::var(DATA_DIR)?.as_str()).join(&config.redis_memory_file)))
+    }
+
+    pub fn open(file: Option<PathBuf>) -> anyhow::Result<Self> {
+        let kv = Arc::new(DashMap::new());
+        if let Some(file) = file.as_ref().filter(|f| f.exists()) {
+            let snapshot: HashMap<String, Entry> = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+            for (k, e) in snapshot.into_iter().filter(|(_, e)| !e.expired()) {
+                kv.insert(k, e);
+            }
+            info!("redis memory store loaded {} keys from {:?}", kv.len(), file);
+        }
+
+        let service = Self { kv, dirty: Arc::new(AtomicBool::new(false)) };
+        tokio::spawn(housekeeping(Arc::downgrade(&service.kv), service.dirty.clone(), file));
+        Ok(service)
+    }
+
+    fn read<T>(&self, key: &str, f: impl FnOnce(&Value) -> anyhow::Result<T>) -> anyhow::Result<Option<T>> {
+        match self.kv.get(key) {
+            Some(e) if !e.expired() => return f(&e.value).map(Some),
+            None => return Ok(None),
+            _ => {}
+        }
+        self.kv.remove_if(key, |_, e| e.expired());
+        Ok(None)
+    }
+
+    fn write<T>(&self, key: &str, default: fn() -> Value, f: impl FnOnce(&mut Value) -> anyhow::Result<T>) -> anyhow::Result<T> {
+        self.kv.remove_if(key, |_, e| e.expired());
+        self.dirty.store(true, Ordering::Relaxed);
+        let mut e = self.kv.entry(key.to_string()).or_insert_with(|| Entry { value: default(), expire_at: None });
+        f(&mut e.value)
+    }
+
+    fn put(&self, key: &str, val: &str, expire_at: Option<i64>) {
+        self.dirty.store(true, Ordering::Relaxed);
+        self.kv.insert(key.to_string(), Entry { value: Value::Str(val.to_string()), expire_at });
+    }
+
+    fn push(&self, key: &str, val: &str, front: bool) -> anyhow::Result<usize> {
+        self.write(key, || Value::List(VecDeque::new()), |v| match v {
+            Value::List(l) => {
+                if front { l.push_front(val.to_string()) } else { l.push_back(val.to_string()) }
+                Ok(l.len())
+            }
+            _ => Err(wrong_type()),
+        })
+    }
+
+    fn pop(&self, key: &str, front: bool) -> anyhow::Result<Option<String>> {
+        if self.read(key, |_| Ok(()))?.is_none() {
+            return Ok(None);
+        }
+        let r = self.write(key, || Value::List(VecDeque::new()), |v| match v {
+            Value::List(l) => Ok(if front { l.pop_front() } else { l.pop_back() }),
+            _ => Err(wrong_type()),
+        });
+        // like redis, an emptied list is gone.
+        self.kv.remove_if(key, |_, e| matches!(&e.value, Value::List(l) if l.is_empty()));
+        r
+    }
+}
+
+/// sweeps expired keys and saves the store while the service is alive.
+async fn housekeeping(kv: Weak<DashMap<String, Entry>>, dirty: Arc<AtomicBool>, file: Option<PathBuf>) {
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+    let mut last_save = tokio::time::Instant::now();
+    loop {
+        ticker.tick().await;
+        let Some(kv) = kv.upgrade() else { return; };
+        kv.retain(|_, e| !e.expired());
+
+        if let Some(file) = &file {
+            if last_save.elapsed() >= SAVE_INTERVAL && dirty.swap(false, Ordering::Relaxed) {
+                last_save = tokio::time::Instant::now();
+                if let Err(e) = save(&kv, file) {
+                    error!("save redis memory store to {:?} failed : {:?}", file, e);
+                    dirty.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+fn save(kv: &DashMap<String, Entry>, file: &Path) -> anyhow::Result<()> {
+    let snapshot: HashMap<String, Entry> = kv.iter().map(|e| (e.key().to_string(), e.value().clone())).collect();
+    // write aside and rename, a crash mid-write never leaves half a file.
+    let tmp = file.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_string(&snapshot)?)?;
+    std::fs::rename(&tmp, file)?;
+    Ok(())
+}
+
+
+#[async_trait]
+impl RedisAPI for RedisMemoryService {
+    async fn new(_redis_uri: Vec<String>, _is_test: bool) -> anyhow::Result<Self>{
+        Self::open(None)
+    }
+
+    async fn set(&self, key: &str, val: &str) -> anyhow::Result<()> {
+        self.put(key, val, None);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<String> {
+        self.read(key, |v| match v {
+            Value::Str(s) => Ok(s.to_string()),
+            _ => Err(wrong_type()),
+        })?.ok_or(anyhow!("key not found : {}", key))
+    }
+
+    async fn publish(&self, channel_name: &str, message: &str) -> anyhow::Result<()> {
+        // like redis, publishing to a channel nobody listens to is fine.
+        let _ = channel(channel_name).send(message.to_string());
+        Ok(())
+    }
+
+    async fn subscribe(&self, channel_name: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        let receiver = channel(channel_name).subscribe();
+        Ok(futures::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(msg) => return Some((msg, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }).boxed())
+    }
+
+    async fn set_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<()> {
+        self.put(key, val, Some(current_timestamp!() + seconds as i64 * 1000));
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> anyhow::Result<bool> {
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(self.kv.remove(key).map(|(_, e)| !e.expired()).unwrap_or(false))
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(self.read(key, |_| Ok(()))?.is_some())
+    }
+
+    async fn expire(&self, key: &str, seconds: u64) -> anyhow::Result<bool> {
+        if self.read(key, |_| Ok(()))?.is_none() {
+            return Ok(false);
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        if let Some(mut e) = self.kv.get_mut(key) {
+            e.expire_at = Some(current_timestamp!() + seconds as i64 * 1000);
+        }
+        Ok(true)
+    }
+
+    async fn incr_by(&self, key: &str, delta: i64) -> anyhow::Result<i64> {
+        self.write(key, || Value::Str("0".to_string()), |v| match v {
+            Value::Str(s) => {
+                let n = s.parse::<i64>().map_err(|_| anyhow!("ERR value is not an integer or out of range"))?
+                    .checked_add(delta).ok_or_else(|| anyhow!("ERR increment or decrement would overflow"))?;
+                *s = n.to_string();
+                Ok(n)
+            }
+            _ => Err(wrong_type()),
+        })
+    }
+
+    async fn hset(&self, key: &str, field: &str, val: &str) -> anyhow::Result<()> {
+        self.write(key, || Value::Hash(HashMap::new()), |v| match v {
+            Value::Hash(h) => {
+                h.insert(field.to_string(), val.to_string());
+                Ok(())
+            }
+            _ => Err(wrong_type()),
+        })
+    }
+
+    async fn hget(&self, key: &str, field: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.read(key, |v| match v {
+            Value::Hash(h) => Ok(h.get(field).cloned()),
+            _ => Err(wrong_type()),
+        })?.flatten())
+    }
+
+    async fn hgetall(&self, key: &str) -> anyhow::Result<HashMap<String, String>> {
+        Ok(self.read(key, |v| match v {
+            Value::Hash(h) => Ok(h.clone()),
+            _ => Err(wrong_type()),
+        })?.unwrap_or_default())
+    }
+
+    async fn lpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        self.push(key, val, true)
+    }
+
+    async fn rpush(&self, key: &str, val: &str) -> anyhow::Result<usize> {
+        self.push(key, val, false)
+    }
+
+    async fn lpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        self.pop(key, true)
+    }
+
+    async fn rpop(&self, key: &str) -> anyhow::Result<Option<String>> {
+        self.pop(key, false)
+    }
+
+    async fn lrange(&self, key: &str, start: isize, stop: isize) -> anyhow::Result<Vec<String>> {
+        Ok(self.read(key, |v| match v {
+            Value::List(l) => {
+                let len = l.len() as isize;
+                let index = |i: isize| if i < 0 { (len + i).max(0) } else { i };
+                let (start, stop) = (index(start), index(stop).min(len - 1));
+                if start > stop {
+                    return Ok(vec![]);
+                }
+                Ok(l.range(start as usize..=stop as usize).cloned().collect())
+            }
+            _ => Err(wrong_type()),
+        })?.unwrap_or_default())
+    }
+
+    async fn sadd(&self, key: &str, member: &str) -> anyhow::Result<bool> {
+        self.write(key, || Value::Set(BTreeSet::new()), |v| match v {
+            Value::Set(s) => Ok(s.insert(member.to_string())),
+            _ => Err(wrong_type()),
+        })
+    }
+
+    async fn smembers(&self, key: &str) -> anyhow::Result<Vec<String>> {
+        Ok(self.read(key, |v| match v {
+            Value::Set(s) => Ok(s.iter().cloned().collect()),
+            _ => Err(wrong_type()),
+        })?.unwrap_or_default())
+    }
+
+    async fn scan(&self, pattern: &str) -> anyhow::Result<BoxStream<'static, String>> {
+        if pattern.is_empty() {
+            bail!("empty scan pattern");
+        }
+        let keys: Vec<String> = self.kv.iter()
+            .filter(|e| !e.expired() && glob(pattern.as_bytes(), e.key().as_bytes()))
+            .map(|e| e.key().to_string())
+            .collect();
+        Ok(futures::stream::iter(keys).boxed())
+    }
+
+    async fn set_nx_ex(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        self.kv.remove_if(key, |_, e| e.expired());
+        match self.kv.entry(key.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => Ok(false),
+            dashmap::mapref::entry::Entry::Vacant(v) => {
+                v.insert(Entry { value: Value::Str(val.to_string()), expire_at: Some(current_timestamp!() + seconds as i64 * 1000) });
+                self.dirty.store(true, Ordering::Relaxed);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn del_if_eq(&self, key: &str, val: &str) -> anyhow::Result<bool> {
+        let removed = self.kv.remove_if(key, |_, e| !e.expired() && matches!(&e.value, Value::Str(s) if s == val)).is_some();
+        if removed {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        Ok(removed)
+    }
+
+    async fn expire_if_eq(&self, key: &str, val: &str, seconds: u64) -> anyhow::Result<bool> {
+        let Some(mut e) = self.kv.get_mut(key) else { return Ok(false); };
+        if e.expired() || !matches!(&e.value, Value::Str(s) if s == val) {
+            return Ok(false);
+        }
+        e.expire_at = Some(current_timestamp!() + seconds as i64 * 1000);
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pub_sub() -> anyhow::Result<()> {
+        let a = RedisMemoryService::new(vec![], true).await?;
+        let b = RedisMemoryService::new(vec![], true).await?;
+
+        let mut stream = b.subscribe("fake-test").await?;
+        a.publish("fake-test", "hello").await?;
+        assert_eq!(stream.next().await.unwrap(), "hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_conformance() -> anyhow::Result<()> {
+        let r = RedisMemoryService::new(vec![], true).await?;
+        shared::redis_conformance::conformance_suite(&r, "memory").await
+    }
+
+    #[tokio::test]
+    async fn test_incr_overflow() -> anyhow::Result<()> {
+        let r = RedisMemoryService::new(vec![], true).await?;
+        r.set("n", &(i64::MAX - 1).to_string()).await?;
+        assert_eq!(r.incr_by("n", 1).await?, i64::MAX);
+        let e = r.incr_by("n", 1).await.unwrap_err();
+        assert_eq!(e.to_string(), "ERR increment or decrement would overflow");
+        // the value is left alone, like redis does.
+        assert_eq!(r.get("n").await?, i64::MAX.to_string());
+        assert!(r.incr_by("n", i64::MIN).await.is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_persistence() -> anyhow::Result<()> {
+        let file = env::temp_dir().join(format!("play-redis-{}.json", current_timestamp!()));
+        let r = RedisMemoryService::open(Some(file.clone()))?;
+        r.set("k", "v").await?;
+        r.hset("h", "f", "1").await?;
+        r.set_ex("short", "v", 1).await?;
+        save(&r.kv, &file)?;
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let reopened = RedisMemoryService::open(Some(file.clone()))?;
+        assert_eq!(reopened.get("k").await?, "v");
+        assert_eq!(reopened.hget("h", "f").await?.as_deref(), Some("1"));
+        assert!(!reopened.exists("short").await?);
+
+        std::fs::remove_file(&file)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sweep() -> anyhow::Result<()> {
+        let r = RedisMemoryService::new(vec![], true).await?;
+        r.set_ex("gone", "v", 1).await?;
+        tokio::time::sleep(SWEEP_INTERVAL * 2 + Duration::from_millis(100)).await;
+        // removed by the sweep, not by touching it.
+        assert!(r.kv.get("gone").is_none());
+        Ok(())
+    }
+}
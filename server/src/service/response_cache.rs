@@ -0,0 +1,263 @@
+//!
+//! an opt-in response cache for routes that are expensive to render.
+//! entries are stored through `RedisAPI`, so instances sharing a redis share the cache,
+//! and without redis they land in the embedded in-memory service.
+//!
+//! routes opt in through `method_router!` :
+//! ```ignore
+//! method_router!(
+//!     get : "/todo/list" -> todo_list with CacheOptions::ttl(60).tag(TAG_TODO_ITEM),
+//! );
+//! ```
+
+use std::sync::Arc;
+
+use axum::body::{Body, boxed, Full, HttpBody};
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use shared::redis_api::RedisAPI;
+
+use crate::AppState;
+
+pub const TAG_TODO_ITEM: &str = "todo_item";
+pub const TAG_API_ENTRY: &str = "api_entry";
+
+const KEY_PREFIX: &str = "resp-cache:";
+const GENERATION_PREFIX: &str = "resp-cache-gen:";
+/// tells whether the response came from the cache, handy when debugging.
+pub const X_CACHE: &str = "x-cache";
+
+#[derive(Clone, Debug, Default)]
+pub struct CacheOptions {
+    ttl: u64,
+    vary_headers: Vec<String>,
+    ignore_query: bool,
+    vary_user: bool,
+    tags: Vec<String>,
+}
+
+impl CacheOptions {
+    /// cache for `seconds`, keyed by method, path and query string.
+    pub fn ttl(seconds: u64) -> Self {
+        CacheOptions { ttl: seconds, ..Default::default() }
+    }
+
+    pub fn vary_header(mut self, name: &str) -> Self {
+        self.vary_headers.push(name.to_lowercase());
+        self
+    }
+
+    /// one entry whatever the query string, for routes that don't read it.
+    pub fn ignore_query(mut self) -> Self {
+        self.ignore_query = true;
+        self
+    }
+
+    /// one entry per caller, told apart by the `authorization` and `cookie` headers.
+    pub fn vary_user(mut self) -> Self {
+        self.vary_user = true;
+        self
+    }
+
+    /// `invalidate(s, tag)` drops every entry carrying this tag.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// base64, bodies aren't always utf8.
+    body: String,
+    etag: String,
+}
+
+/// FNV-1a, stable across builds and processes unlike `DefaultHasher`.
+pub(crate) fn fnv(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| (h ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn etag(body: &[u8]) -> String {
+    format!("W/\"{:016x}\"", fnv(body))
+}
+
+fn generation_key(tag: &str) -> String {
+    format!("{}{}", GENERATION_PREFIX, tag)
+}
+
+///
+/// entries aren't deleted one by one, every tag has a generation counter that is part of the key,
+/// bumping it makes the old entries unreachable and their ttl cleans them up.
+pub async fn invalidate(s: &AppState, tag: &str) {
+    if let Err(e) = s.redis_service.incr_by(&generation_key(tag), 1).await {
+        warn!("invalidate response cache tag {} failed : {:?}", tag, e);
+    }
+}
+
+async fn cache_key(redis: &(dyn RedisAPI + Send + Sync), opts: &CacheOptions, method: &Method, path: &str, query: Option<&str>, headers: &HeaderMap) -> anyhow::Result<String> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+
+    let mut vary = vec![];
+    if !opts.ignore_query {
+        vary.push(format!("q={}", query.unwrap_or_default()));
+    }
+    for name in &opts.vary_headers {
+        vary.push(format!("h:{}={}", name, header(name)));
+    }
+    if opts.vary_user {
+        vary.push(format!("u={}|{}", header("authorization"), header("cookie")));
+    }
+    for tag in &opts.tags {
+        // adding 0 reads the counter, a missing one starts at 0.
+        vary.push(format!("g:{}={}", tag, redis.incr_by(&generation_key(tag), 0).await?));
+    }
+    Ok(format!("{}{}:{}:{:016x}", KEY_PREFIX, method, path, fnv(vary.join("\n").as_bytes())))
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers.get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|t| t.trim() == etag || t.trim() == "*"))
+        .unwrap_or(false)
+}
+
+fn not_modified(etag: &str) -> Response {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    if let Ok(v) = HeaderValue::from_str(etag) {
+        response.headers_mut().insert(header::ETAG, v);
+    }
+    response
+}
+
+fn rebuild(cached: CachedResponse, hit: &'static str) -> anyhow::Result<Response> {
+    let mut builder = Response::builder().status(cached.status);
+    for (name, value) in &cached.headers {
+        builder = builder.header(name, value);
+    }
+    Ok(builder
+        .header(header::ETAG, &cached.etag)
+        .header(X_CACHE, hit)
+        .body(boxed(Full::from(STANDARD.decode(&cached.body)?)))?)
+}
+
+///
+/// the middleware behind `method_router!`'s `with CacheOptions`.
+/// it needs the `AppState` extension `routers()` adds, without it requests just pass through.
+pub async fn middleware(State(opts): State<CacheOptions>, req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(s) = req.extensions().get::<Arc<AppState>>().cloned() else {
+        return next.run(req).await;
+    };
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+
+    let key = match cache_key(s.redis_service.as_ref(), &opts, req.method(), req.uri().path(), req.uri().query(), req.headers()).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("response cache unavailable : {:?}", e);
+            return next.run(req).await;
+        }
+    };
+    let request_headers = req.headers().clone();
+
+    if let Ok(json) = s.redis_service.get(&key).await {
+        if let Ok(cached) = serde_json::from_str::<CachedResponse>(&json) {
+            if if_none_match(&request_headers, &cached.etag) {
+                return not_modified(&cached.etag);
+            }
+            match rebuild(cached, "HIT") {
+                Ok(response) => return response,
+                Err(e) => warn!("broken response cache entry {} : {:?}", key, e),
+            }
+        }
+    }
+
+    let response = next.run(req).await;
+    // only plain successes are shared, never anything setting a cookie.
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::SET_COOKIE) {
+        return response;
+    }
+
+    let (parts, mut body) = response.into_parts();
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) => bytes.extend_from_slice(&chunk),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    let cached = CachedResponse {
+        status: parts.status.as_u16(),
+        headers: parts.headers.iter()
+            .filter(|(name, _)| *name != header::ETAG && name.as_str() != X_CACHE)
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect(),
+        body: STANDARD.encode(&bytes),
+        etag: etag(&bytes),
+    };
+    if let Ok(json) = serde_json::to_string(&cached) {
+        if let Err(e) = s.redis_service.set_ex(&key, &json, opts.ttl).await {
+            warn!("store response cache entry {} failed : {:?}", key, e);
+        }
+    }
+
+    if if_none_match(&request_headers, &cached.etag) {
+        return not_modified(&cached.etag);
+    }
+    rebuild(cached, "MISS").unwrap_or_else(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_is_stable() {
+        assert_eq!(etag(b"hello"), etag(b"hello"));
+        assert_ne!(etag(b"hello"), etag(b"hello!"));
+        assert!(etag(b"").starts_with("W/\""));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(&format!("\"x\", {}", etag(b"a"))).unwrap());
+        assert!(if_none_match(&headers, &etag(b"a")));
+        assert!(!if_none_match(&headers, &etag(b"b")));
+    }
+
+    #[cfg(not(feature = "redis"))]
+    #[tokio::test]
+    async fn test_cache_key() -> anyhow::Result<()> {
+        let redis = crate::service::redis_memory_service::RedisMemoryService::open(None)?;
+        let mut headers = HeaderMap::new();
+        headers.insert("accept-language", HeaderValue::from_static("en"));
+        let key = |opts: CacheOptions, query: Option<&'static str>, headers: HeaderMap| {
+            let redis = &redis;
+            async move { cache_key(redis, &opts, &Method::GET, "/a", query, &headers).await.unwrap() }
+        };
+
+        // `/a?page=2` isn't `/a` unless the route says so.
+        assert_ne!(key(CacheOptions::ttl(1), Some("page=2"), HeaderMap::new()).await, key(CacheOptions::ttl(1), None, HeaderMap::new()).await);
+        assert_ne!(key(CacheOptions::ttl(1), Some("x=1"), HeaderMap::new()).await, key(CacheOptions::ttl(1), Some("x=2"), HeaderMap::new()).await);
+        assert_eq!(key(CacheOptions::ttl(1).ignore_query(), Some("x=1"), HeaderMap::new()).await, key(CacheOptions::ttl(1).ignore_query(), None, HeaderMap::new()).await);
+        assert_ne!(key(CacheOptions::ttl(1).vary_header("Accept-Language"), None, headers.clone()).await, key(CacheOptions::ttl(1).vary_header("Accept-Language"), None, HeaderMap::new()).await);
+
+        // bumping a tag generation moves its entries to new keys.
+        let tagged = key(CacheOptions::ttl(1).tag("t"), None, HeaderMap::new()).await;
+        assert_eq!(tagged, key(CacheOptions::ttl(1).tag("t"), None, HeaderMap::new()).await);
+        redis.incr_by(&generation_key("t"), 1).await?;
+        assert_ne!(tagged, key(CacheOptions::ttl(1).tag("t"), None, HeaderMap::new()).await);
+        Ok(())
+    }
+}
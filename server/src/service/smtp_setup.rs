@@ -0,0 +1,198 @@
+//!
+//! `Config::mail_server` to an smtp `Builder` : the accounts AUTH checks against, and the STARTTLS certificate.
+
+use std::fs;
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+
+use mail_server::smtp::{Authenticator, Builder};
+
+use crate::config::{Config, MailAccount};
+
+pub struct ConfigAccounts(pub Vec<MailAccount>);
+
+impl Authenticator for ConfigAccounts {
+    fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.0.iter().any(|a| a.username.eq_ignore_ascii_case(username) && constant_time_eq(a.password.as_bytes(), password.as_bytes()))
+    }
+}
+
+/// doesn't stop at the first differing byte, so timing tells nothing about the password.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// how often the certificate STARTTLS was set up with is checked for a newer one.
+const WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// where the STARTTLS certificate comes from.
+#[derive(Debug, PartialEq)]
+enum TlsSource {
+    Files(String, String),
+    /// the certificate the https server obtained, in DATA_DIR/certs.
+    #[cfg(feature = "https")]
+    AcmeCache(std::path::PathBuf),
+}
+
+/// the mail_server files, otherwise the certificate of the https server in its `https_cert.mode`.
+fn tls_source(config: &Config) -> anyhow::Result<Option<TlsSource>> {
+    let mail = &config.mail_server;
+    if !mail.tls_cert_path.is_empty() && !mail.tls_key_path.is_empty() {
+        return Ok(Some(TlsSource::Files(mail.tls_cert_path.to_string(), mail.tls_key_path.to_string())));
+    }
+
+    #[cfg(feature = "https")]
+    let source = {
+        use crate::config::CertMode;
+        let https = &config.https_cert;
+        let certs_dir = || anyhow::Ok(std::path::Path::new(&std::env::var(shared::constants::DATA_DIR)?).join("certs"));
+        Some(match https.mode {
+            CertMode::Acme => TlsSource::AcmeCache(certs_dir()?),
+            CertMode::Static => TlsSource::Files(https.cert_path.to_string(), https.key_path.to_string()),
+            CertMode::SelfSigned => {
+                let (cert_path, key_path) = https::cert_files::self_signed(certs_dir()?.join("self-signed"), &https.domains)?;
+                TlsSource::Files(cert_path.to_string_lossy().to_string(), key_path.to_string_lossy().to_string())
+            }
+        })
+    };
+    #[cfg(not(feature = "https"))]
+    let source = None;
+    Ok(source)
+}
+
+/// the cert chain the source has right now, to notice renewals.
+fn current_chain(source: &TlsSource) -> Option<Vec<u8>> {
+    match source {
+        TlsSource::Files(cert_path, _) => fs::read(cert_path).ok(),
+        #[cfg(feature = "https")]
+        TlsSource::AcmeCache(dir) => https::load_cached_certificate(&dir.to_string_lossy()).ok().flatten().map(|c| c.cert_chain.into_bytes()),
+    }
+}
+
+///
+/// the cert and key files for STARTTLS : the configured ones, otherwise the ones of the https server.
+/// an acme certificate is written out next to the cache as `certs/smtp/*.pem`.
+pub fn tls_files(config: &Config) -> anyhow::Result<Option<(String, String)>> {
+    match tls_source(config)? {
+        Some(TlsSource::Files(cert_path, key_path)) => Ok(Some((cert_path, key_path))),
+        #[cfg(feature = "https")]
+        Some(TlsSource::AcmeCache(dir)) => {
+            let Some(cert) = https::load_cached_certificate(&dir.to_string_lossy())? else { return Ok(None) };
+            let dir = dir.join("smtp");
+            fs::create_dir_all(&dir)?;
+            let (cert_path, key_path) = (dir.join("fullchain.pem"), dir.join("key.pem"));
+            fs::write(&cert_path, cert.cert_chain)?;
+            https::cert_files::write_private(&key_path, cert.private_key.as_bytes())?;
+            Ok(Some((cert_path.to_string_lossy().to_string(), key_path.to_string_lossy().to_string())))
+        }
+        _ => Ok(None),
+    }
+}
+
+///
+/// mailin sets up tls once when the smtp server is built and can't be stopped, so a renewed
+/// certificate (or the first one acme issues after boot) needs a new process : it re-executes
+/// itself once the certificate differs from the one read now. call it before `builder`.
+pub fn watch(config: &Config) -> anyhow::Result<()> {
+    let Some(source) = tls_source(config)? else { return Ok(()) };
+    let served = current_chain(&source);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let chain = current_chain(&source);
+            if chain.is_none() || chain == served {
+                continue;
+            }
+            info!("the smtp certificate changed, restarting to serve it over STARTTLS.");
+            restart();
+            break;
+        }
+    });
+    Ok(())
+}
+
+fn restart() {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        let e = std::env::current_exe().map(|exe| std::process::Command::new(exe).args(std::env::args_os().skip(1)).exec());
+        error!("restarting failed : {:?}", e);
+    }
+    warn!("restart manually to serve the new certificate over STARTTLS.");
+}
+
+pub fn builder(config: &Config) -> anyhow::Result<Builder> {
+    let tls = tls_files(config)?;
+    let config = &config.mail_server;
+    let mut builder = Builder::new()
+        .with_max_message_size(config.max_message_size)
+        .with_auth(config.auth)
+        .with_authenticator(ConfigAccounts(config.accounts.clone()));
+
+    match tls {
+        Some((cert_path, key_path)) => {
+            info!("smtp STARTTLS with {}", cert_path);
+            builder = builder.with_ssl(Some(cert_path), Some(key_path));
+        }
+        None if config.auth => warn!("no certificate for smtp STARTTLS yet, AUTH is refused until there is one."),
+        None => {}
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_accounts() {
+        let accounts = ConfigAccounts(vec![MailAccount { username: "Me@x.com".to_string(), password: "secret".to_string() }]);
+        assert!(accounts.authenticate("me@x.com", "secret"));
+        assert!(!accounts.authenticate("me@x.com", "Secret"));
+        assert!(!accounts.authenticate("me@x.com", "secret2"));
+        assert!(!accounts.authenticate("you@x.com", "secret"));
+        assert!(!ConfigAccounts(vec![]).authenticate("", ""));
+    }
+
+    #[test]
+    fn test_configured_tls_files_win() -> anyhow::Result<()> {
+        let mut config = crate::config::init_config(true);
+        config.https_cert.mode = crate::config::CertMode::Static;
+        config.https_cert.cert_path = "/etc/https/cert.pem".to_string();
+        config.https_cert.key_path = "/etc/https/key.pem".to_string();
+        config.mail_server.tls_cert_path = "/etc/smtp/cert.pem".to_string();
+        config.mail_server.tls_key_path = "/etc/smtp/key.pem".to_string();
+        assert_eq!(tls_files(&config)?, Some(("/etc/smtp/cert.pem".to_string(), "/etc/smtp/key.pem".to_string())));
+        Ok(())
+    }
+
+    #[cfg(feature = "https")]
+    #[test]
+    fn test_static_https_files() -> anyhow::Result<()> {
+        let mut config = crate::config::init_config(true);
+        config.https_cert.mode = crate::config::CertMode::Static;
+        config.https_cert.cert_path = "/etc/https/cert.pem".to_string();
+        config.https_cert.key_path = "/etc/https/key.pem".to_string();
+        assert_eq!(tls_files(&config)?, Some(("/etc/https/cert.pem".to_string(), "/etc/https/key.pem".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_chain_follows_the_files() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("smtp-setup-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        let source = TlsSource::Files(cert_path.to_string_lossy().to_string(), key_path.to_string_lossy().to_string());
+        assert_eq!(current_chain(&source), None);
+
+        fs::write(&cert_path, "first")?;
+        assert_eq!(current_chain(&source), Some(b"first".to_vec()));
+        fs::write(&cert_path, "renewed")?;
+        assert_eq!(current_chain(&source), Some(b"renewed".to_vec()));
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+use serde_json::Value;
+
+use crate::service::html::escape_html;
+
+/// beyond this many edits we stop searching and treat the rest as one big replacement.
+/// the trace grows with its square, 1000 keeps it around 8 MB.
+const MAX_EDIT_DISTANCE: usize = 1000;
+/// bytes of both texts together the compare endpoint accepts.
+pub const MAX_INPUT: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// a piece of a changed line, `changed` marks the words that differ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    pub changed: bool,
+}
+
+/// one row of the diff, line numbers are 1 based.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffRow {
+    Same { left_no: usize, right_no: usize, text: String },
+    Changed { left_no: usize, right_no: usize, left: Vec<Segment>, right: Vec<Segment> },
+    Removed { left_no: usize, text: String },
+    Added { right_no: usize, text: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonChange {
+    pub path: String,
+    pub kind: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffMode {
+    SideBySide,
+    Unified,
+}
+
+impl DiffMode {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "unified" => DiffMode::Unified,
+            _ => DiffMode::SideBySide,
+        }
+    }
+}
+
+
+/// myers' O(ND) diff, returns the edit script turning `a` into `b`.
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    // strip the common prefix and suffix first, most inputs only differ in a few places.
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let middle = myers_middle(mid_a, mid_b).unwrap_or_else(|| {
+        (0..mid_a.len()).map(Edit::Delete).chain((0..mid_b.len()).map(Edit::Insert)).collect()
+    });
+    edits.extend(middle.into_iter().map(|e| match e {
+        Edit::Equal(x, y) => Edit::Equal(x + prefix, y + prefix),
+        Edit::Delete(x) => Edit::Delete(x + prefix),
+        Edit::Insert(y) => Edit::Insert(y + prefix),
+    }));
+    edits.extend((0..suffix).map(|i| Edit::Equal(a.len() - suffix + i, b.len() - suffix + i)));
+    edits
+}
+
+fn myers_middle<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    // `k` stays within the searched distance, however long the inputs are.
+    let bound = max.min(MAX_EDIT_DISTANCE);
+    let offset = bound as isize + 1;
+    let mut v = vec![0isize; 2 * bound + 3];
+    // trace[d] keeps v[-d-1..=d+1] as it was before round d.
+    let mut trace: Vec<Vec<isize>> = vec![];
+
+    'search: for d in 0..=max as isize {
+        if d as usize > MAX_EDIT_DISTANCE {
+            return None;
+        }
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) { v[i + 1] } else { v[i - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(Edit::Equal((x - 1) as usize, (y - 1) as usize));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert((y - 1) as usize));
+            } else {
+                edits.push(Edit::Delete((x - 1) as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+
+/// split a line into words, whitespace runs and single punctuation chars.
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut start = 0;
+    let mut last_kind = None;
+    for (i, c) in line.char_indices() {
+        let kind = if c.is_alphanumeric() || c == '_' { 0 } else if c.is_whitespace() { 1 } else { 2 };
+        if i > start && (Some(kind) != last_kind || kind == 2) {
+            tokens.push(&line[start..i]);
+            start = i;
+        }
+        last_kind = Some(kind);
+    }
+    if start < line.len() {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+fn push_segment(segments: &mut Vec<Segment>, text: &str, changed: bool) {
+    match segments.last_mut() {
+        Some(last) if last.changed == changed => last.text.push_str(text),
+        _ => segments.push(Segment { text: text.to_string(), changed }),
+    }
+}
+
+/// word level diff of a changed line pair.
+pub fn diff_words(left: &str, right: &str) -> (Vec<Segment>, Vec<Segment>) {
+    let (a, b) = (tokenize(left), tokenize(right));
+    let (mut l, mut r) = (vec![], vec![]);
+    for e in myers(&a, &b) {
+        match e {
+            Edit::Equal(x, y) => {
+                push_segment(&mut l, a[x], false);
+                push_segment(&mut r, b[y], false);
+            }
+            Edit::Delete(x) => push_segment(&mut l, a[x], true),
+            Edit::Insert(y) => push_segment(&mut r, b[y], true),
+        }
+    }
+    (l, r)
+}
+
+/// line level diff, changed line pairs inside one hunk are refined with a word diff.
+pub fn diff_lines(left: &str, right: &str) -> Vec<DiffRow> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (left.lines().collect(), right.lines().collect());
+    let mut rows = vec![];
+    let (mut deleted, mut inserted): (Vec<usize>, Vec<usize>) = (vec![], vec![]);
+
+    let flush = |rows: &mut Vec<DiffRow>, deleted: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+        let pairs = deleted.len().min(inserted.len());
+        for i in 0..pairs {
+            let (x, y) = (deleted[i], inserted[i]);
+            let (l, r) = diff_words(a[x], b[y]);
+            rows.push(DiffRow::Changed { left_no: x + 1, right_no: y + 1, left: l, right: r });
+        }
+        rows.extend(deleted[pairs..].iter().map(|x| DiffRow::Removed { left_no: x + 1, text: a[*x].to_string() }));
+        rows.extend(inserted[pairs..].iter().map(|y| DiffRow::Added { right_no: y + 1, text: b[*y].to_string() }));
+        deleted.clear();
+        inserted.clear();
+    };
+
+    for e in myers(&a, &b) {
+        match e {
+            Edit::Equal(x, y) => {
+                flush(&mut rows, &mut deleted, &mut inserted);
+                rows.push(DiffRow::Same { left_no: x + 1, right_no: y + 1, text: a[x].to_string() });
+            }
+            Edit::Delete(x) => deleted.push(x),
+            Edit::Insert(y) => inserted.push(y),
+        }
+    }
+    flush(&mut rows, &mut deleted, &mut inserted);
+    rows
+}
+
+
+/// structural diff of two json documents, paths look like `$.a.b[0]`.
+pub fn diff_json(left: &Value, right: &Value) -> Vec<JsonChange> {
+    let mut changes = vec![];
+    walk_json("$", left, right, &mut changes);
+    changes
+}
+
+fn walk_json(path: &str, left: &Value, right: &Value, out: &mut Vec<JsonChange>) {
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            let mut keys: Vec<&String> = l.keys().chain(r.keys().filter(|k| !l.contains_key(*k))).collect();
+            keys.sort();
+            for k in keys {
+                let child = format!("{}.{}", path, k);
+                match (l.get(k), r.get(k)) {
+                    (Some(lv), Some(rv)) => walk_json(&child, lv, rv, out),
+                    (Some(lv), None) => out.push(JsonChange { path: child, kind: "removed", old: Some(lv.to_string()), new: None }),
+                    (None, Some(rv)) => out.push(JsonChange { path: child, kind: "added", old: None, new: Some(rv.to_string()) }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for i in 0..l.len().max(r.len()) {
+                let child = format!("{}[{}]", path, i);
+                match (l.get(i), r.get(i)) {
+                    (Some(lv), Some(rv)) => walk_json(&child, lv, rv, out),
+                    (Some(lv), None) => out.push(JsonChange { path: child, kind: "removed", old: Some(lv.to_string()), new: None }),
+                    (None, Some(rv)) => out.push(JsonChange { path: child, kind: "added", old: None, new: Some(rv.to_string()) }),
+                    (None, None) => {}
+                }
+            }
+        }
+        (l, r) if l != r => out.push(JsonChange { path: path.to_string(), kind: "changed", old: Some(l.to_string()), new: Some(r.to_string()) }),
+        _ => {}
+    }
+}
+
+
+fn segments_html(segments: &[Segment], tag: &str) -> String {
+    segments.iter().map(|s| if s.changed {
+        format!("<{}>{}</{}>", tag, escape_html(&s.text), tag)
+    } else {
+        escape_html(&s.text)
+    }).collect()
+}
+
+const DIFF_STYLE: &str = r#"<style>
+.text-compare{border-collapse:collapse;width:98%;text-align:left;}
+.text-compare td{font-family:monospace;white-space:pre-wrap;word-break:break-all;vertical-align:top;padding:0 4px;}
+.text-compare td.ln{color:#999;text-align:right;width:3em;user-select:none;background:#f9f9f9;}
+.text-compare tr.removed td.text, .text-compare td.text.removed{background:#ffecec;}
+.text-compare tr.added td.text, .text-compare td.text.added{background:#eaffea;}
+.text-compare del{background:#f8cbcb;text-decoration:none;}
+.text-compare ins{background:#a6f3a6;text-decoration:none;}
+.diff-summary{margin:10px;}
+</style>"#;
+
+///
+/// render the comparison of two texts as an html fragment.
+/// a structural summary is added on top when both sides are json.
+pub fn render_html(left: &str, right: &str, mode: DiffMode) -> String {
+    let rows = diff_lines(left, right);
+    let json_changes = match (serde_json::from_str::<Value>(left), serde_json::from_str::<Value>(right)) {
+        (Ok(l), Ok(r)) => Some(diff_json(&l, &r)),
+        _ => None,
+    };
+
+    let differs = rows.iter().any(|r| !matches!(r, DiffRow::Same { .. }));
+    if !differs && json_changes.as_ref().map(|c| c.is_empty()).unwrap_or(true) {
+        return "<h2>No Diff!</h2>".to_string();
+    }
+
+    let count = |f: fn(&DiffRow) -> bool| rows.iter().filter(|r| f(r)).count();
+    let mut html = String::from(DIFF_STYLE);
+    html.push_str(&format!("<div class=\"diff-summary\">{} changed, {} removed, {} added</div>",
+                           count(|r| matches!(r, DiffRow::Changed { .. })),
+                           count(|r| matches!(r, DiffRow::Removed { .. })),
+                           count(|r| matches!(r, DiffRow::Added { .. }))));
+
+    if let Some(changes) = json_changes.filter(|c| !c.is_empty()) {
+        html.push_str("<table class=\"text-compare json-diff\"><tr><th>path</th><th>change</th><th>left</th><th>right</th></tr>");
+        for c in changes {
+            html.push_str(&format!("<tr class=\"{}\"><td>{}</td><td>{}</td><td class=\"text removed\">{}</td><td class=\"text added\">{}</td></tr>",
+                                   c.kind, escape_html(&c.path), c.kind,
+                                   escape_html(c.old.as_deref().unwrap_or("")),
+                                   escape_html(c.new.as_deref().unwrap_or(""))));
+        }
+        html.push_str("</table><br/>");
+    }
+
+    match mode {
+        DiffMode::SideBySide => render_side_by_side(&rows, &mut html),
+        DiffMode::Unified => render_unified(&rows, &mut html),
+    }
+    html
+}
+
+fn render_side_by_side(rows: &[DiffRow], html: &mut String) {
+    html.push_str("<table class=\"text-compare diff-side\">");
+    for row in rows {
+        html.push_str(&match row {
+            DiffRow::Same { left_no, right_no, text } => format!(
+                "<tr><td class=\"ln\">{}</td><td class=\"text\">{}</td><td class=\"ln\">{}</td><td class=\"text\">{}</td></tr>",
+                left_no, escape_html(text), right_no, escape_html(text)),
+            DiffRow::Changed { left_no, right_no, left, right } => format!(
+                "<tr><td class=\"ln\">{}</td><td class=\"text removed\">{}</td><td class=\"ln\">{}</td><td class=\"text added\">{}</td></tr>",
+                left_no, segments_html(left, "del"), right_no, segments_html(right, "ins")),
+            DiffRow::Removed { left_no, text } => format!(
+                "<tr><td class=\"ln\">{}</td><td class=\"text removed\">{}</td><td class=\"ln\"></td><td></td></tr>",
+                left_no, escape_html(text)),
+            DiffRow::Added { right_no, text } => format!(
+                "<tr><td class=\"ln\"></td><td></td><td class=\"ln\">{}</td><td class=\"text added\">{}</td></tr>",
+                right_no, escape_html(text)),
+        });
+    }
+    html.push_str("</table>");
+}
+
+fn render_unified(rows: &[DiffRow], html: &mut String) {
+    html.push_str("<table class=\"text-compare diff-unified\">");
+    let removed = |no: &usize, content: String| format!(
+        "<tr class=\"removed\"><td class=\"ln\">{}</td><td class=\"ln\"></td><td class=\"text\">- {}</td></tr>", no, content);
+    let added = |no: &usize, content: String| format!(
+        "<tr class=\"added\"><td class=\"ln\"></td><td class=\"ln\">{}</td><td class=\"text\">+ {}</td></tr>", no, content);
+    for row in rows {
+        match row {
+            DiffRow::Same { left_no, right_no, text } => html.push_str(&format!(
+                "<tr><td class=\"ln\">{}</td><td class=\"ln\">{}</td><td class=\"text\">  {}</td></tr>",
+                left_no, right_no, escape_html(text))),
+            DiffRow::Changed { left_no, right_no, left, right } => {
+                html.push_str(&removed(left_no, segments_html(left, "del")));
+                html.push_str(&added(right_no, segments_html(right, "ins")));
+            }
+            DiffRow::Removed { left_no, text } => html.push_str(&removed(left_no, escape_html(text))),
+            DiffRow::Added { right_no, text } => html.push_str(&added(right_no, escape_html(text))),
+        }
+    }
+    html.push_str("</table>");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn apply(a: &[&str], b: &[&str]) -> Vec<String> {
+        // replaying the edit script on `a` must give `b`.
+        myers(a, b).into_iter().filter_map(|e| match e {
+            Edit::Equal(x, _) => Some(a[x].to_string()),
+            Edit::Insert(y) => Some(b[y].to_string()),
+            Edit::Delete(_) => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_myers() {
+        let a = vec!["a", "b", "c", "a", "b", "b", "a"];
+        let b = vec!["c", "b", "a", "b", "a", "c"];
+        assert_eq!(apply(&a, &b), b);
+        // the shortest edit script for the classic example has 5 edits.
+        assert_eq!(myers(&a, &b).iter().filter(|e| !matches!(e, Edit::Equal(..))).count(), 5);
+
+        assert!(myers::<&str>(&[], &[]).is_empty());
+        assert_eq!(apply(&[], &["x"]), vec!["x"]);
+        assert_eq!(apply(&["x"], &[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_edit_distance_limit() {
+        let a: Vec<usize> = (0..3 * MAX_EDIT_DISTANCE).collect();
+        let b: Vec<usize> = (0..3 * MAX_EDIT_DISTANCE).map(|i| if i % 2 == 0 { i } else { i + a.len() }).collect();
+        assert!(myers_middle(&a, &b).is_none());
+        // too far apart, one big replacement.
+        let edits = myers(&a, &b);
+        assert_eq!(edits.len(), a.len() + b.len() - 1);
+
+        let few: Vec<usize> = a.iter().map(|i| if i % 100 == 0 { i + a.len() } else { *i }).collect();
+        assert!(myers_middle(&a, &few).is_some());
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let rows = diff_lines("one\ntwo\nthree", "one\n2\nthree\nfour");
+        assert_eq!(rows.len(), 4);
+        assert!(matches!(&rows[0], DiffRow::Same { left_no: 1, right_no: 1, .. }));
+        assert!(matches!(&rows[1], DiffRow::Changed { left_no: 2, right_no: 2, .. }));
+        assert!(matches!(&rows[3], DiffRow::Added { right_no: 4, .. }));
+    }
+
+    #[test]
+    fn test_diff_words() {
+        let (l, r) = diff_words("let a = 1;", "let b = 1;");
+        assert_eq!(l, vec![
+            Segment { text: "let ".to_string(), changed: false },
+            Segment { text: "a".to_string(), changed: true },
+            Segment { text: " = 1;".to_string(), changed: false },
+        ]);
+        assert_eq!(r[1], Segment { text: "b".to_string(), changed: true });
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let changes = diff_json(&json!({"a": 1, "b": [1, 2], "c": "x"}), &json!({"a": 2, "b": [1], "d": true, "c": "x"}));
+        assert_eq!(changes, vec![
+            JsonChange { path: "$.a".to_string(), kind: "changed", old: Some("1".to_string()), new: Some("2".to_string()) },
+            JsonChange { path: "$.b[1]".to_string(), kind: "removed", old: Some("2".to_string()), new: None },
+            JsonChange { path: "$.d".to_string(), kind: "added", old: None, new: Some("true".to_string()) },
+        ]);
+    }
+
+    #[test]
+    fn test_render_html() {
+        assert_eq!(render_html("same\ntext", "same\ntext", DiffMode::SideBySide), "<h2>No Diff!</h2>");
+
+        let html = render_html("<b>a</b>", "<b>b</b>", DiffMode::Unified);
+        assert!(html.contains("<del>a</del>"));
+        assert!(html.contains("<ins>b</ins>"));
+        assert!(html.contains("&lt;b&gt;"));
+
+        let html = render_html(r#"{"a": 1}"#, r#"{"a": 2}"#, DiffMode::SideBySide);
+        assert!(html.contains("json-diff"));
+        assert!(html.contains("$.a"));
+    }
+}
@@ -0,0 +1,48 @@
+use std::{env, path::Path};
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::middleware::{from_fn, Next};
+use axum::response::IntoResponse;
+use axum::Router;
+use tower_http::services::ServeDir;
+use tracing::warn;
+
+use shared::constants::DATA_DIR;
+
+use crate::config::HttpsCert;
+
+///
+/// `https_cert.vhosts` on top of the app : a domain gets either a static root or only
+/// the app routes under its prefixes. domains without an entry get the whole app.
+pub fn router(config: &HttpsCert, app: Router) -> anyhow::Result<Router> {
+    let mut hosts = https::VirtualHosts::default();
+    for vhost in &config.vhosts {
+        if !config.domains.iter().any(|d| d.eq_ignore_ascii_case(&vhost.domain)) {
+            warn!("vhost {} is not in https_cert.domains, its certificate will not cover it", vhost.domain);
+        }
+        let router = if !vhost.static_root.is_empty() {
+            let root = Path::new(&env::var(DATA_DIR)?).join(&vhost.static_root);
+            Router::new().fallback_service(ServeDir::new(root))
+        } else if !vhost.routes.is_empty() {
+            only(vhost.routes.clone(), app.clone())
+        } else {
+            app.clone()
+        };
+        hosts = hosts.host(&vhost.domain, router);
+    }
+    Ok(hosts.into_router(app))
+}
+
+/// 404 for paths outside of `prefixes`.
+fn only(prefixes: Vec<String>, app: Router) -> Router {
+    app.layer(from_fn(move |request: Request<Body>, next: Next<Body>| {
+        let allowed = prefixes.iter().any(|p| request.uri().path().starts_with(p.as_str()));
+        async move {
+            match allowed {
+                true => next.run(request).await,
+                false => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+    }))
+}
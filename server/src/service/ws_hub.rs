@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tracing::info;
+
+/// queued messages per connection before we start dropping.
+pub const DEFAULT_CLIENT_CAPACITY: usize = 64;
+/// a client dropping this many messages in a row is considered dead and gets disconnected.
+const MAX_LAGGED: usize = 32;
+
+struct WsClient {
+    topics: HashSet<String>,
+    sender: mpsc::Sender<String>,
+    lagged: usize,
+}
+
+///
+/// keeps track of websocket connections and the topics they subscribed.
+/// each connection owns a bounded channel, publishing never blocks on a slow client.
+pub struct WsHub {
+    next_id: AtomicU64,
+    clients: DashMap<u64, WsClient>,
+    capacity: usize,
+}
+
+impl Default for WsHub {
+    fn default() -> Self {
+        WsHub::new(DEFAULT_CLIENT_CAPACITY)
+    }
+}
+
+impl WsHub {
+    pub fn new(capacity: usize) -> Self {
+        WsHub {
+            next_id: AtomicU64::new(1),
+            clients: DashMap::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// register a connection, messages for it arrive on the returned receiver.
+    pub fn connect<I: IntoIterator<Item=String>>(&self, topics: I) -> (u64, mpsc::Receiver<String>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        self.clients.insert(id, WsClient {
+            topics: topics.into_iter().filter(|t| !t.is_empty()).collect(),
+            sender,
+            lagged: 0,
+        });
+        (id, receiver)
+    }
+
+    pub fn disconnect(&self, id: u64) {
+        self.clients.remove(&id);
+    }
+
+    pub fn subscribe(&self, id: u64, topic: &str) {
+        if let Some(mut c) = self.clients.get_mut(&id) {
+            c.topics.insert(topic.to_string());
+        }
+    }
+
+    pub fn unsubscribe(&self, id: u64, topic: &str) {
+        if let Some(mut c) = self.clients.get_mut(&id) {
+            c.topics.remove(topic);
+        }
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.clients.iter().filter(|c| c.topics.contains(topic)).count()
+    }
+
+    /// send `payload` to every subscriber of `topic`, returns how many clients got it queued.
+    pub fn publish(&self, topic: &str, payload: impl Into<String>) -> usize {
+        self.send_where(payload.into(), |c| c.topics.contains(topic))
+    }
+
+    /// send to every connected client, whatever they subscribed.
+    pub fn broadcast(&self, payload: impl Into<String>) -> usize {
+        self.send_where(payload.into(), |_| true)
+    }
+
+    /// publish an html fragment as an htmx out-of-band swap into element `#target_id`.
+    pub fn publish_oob(&self, topic: &str, target_id: &str, swap: &str, html: &str) -> usize {
+        self.publish(topic, oob(target_id, swap, html))
+    }
+
+    pub fn publish_json(&self, topic: &str, value: &Value) -> usize {
+        self.publish(topic, value.to_string())
+    }
+
+    fn send_where<F: Fn(&WsClient) -> bool>(&self, payload: String, filter: F) -> usize {
+        let mut delivered = 0;
+        let mut dead = vec![];
+        for mut c in self.clients.iter_mut() {
+            if !filter(&c) {
+                continue;
+            }
+            match c.sender.try_send(payload.clone()) {
+                Ok(_) => {
+                    c.lagged = 0;
+                    delivered += 1;
+                }
+                Err(TrySendError::Full(_)) => {
+                    c.lagged += 1;
+                    if c.lagged >= MAX_LAGGED {
+                        dead.push(*c.key());
+                    }
+                }
+                Err(TrySendError::Closed(_)) => dead.push(*c.key()),
+            }
+        }
+        // dropping the sender ends the connection loop of a dead client.
+        for id in dead {
+            info!("ws client {} dropped, too slow or gone", id);
+            self.clients.remove(&id);
+        }
+        delivered
+    }
+}
+
+/// wrap a fragment so the htmx ws extension swaps it into `#target_id`.
+pub fn oob(target_id: &str, swap: &str, html: &str) -> String {
+    let swap = if swap.is_empty() { "true" } else { swap };
+    format!(r#"<div id="{}" hx-swap-oob="{}">{}</div>"#, target_id, swap, html)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let hub = WsHub::new(8);
+        let (a, mut rx_a) = hub.connect(vec!["chat".to_string()]);
+        let (_b, mut rx_b) = hub.connect(vec![]);
+
+        assert_eq!(hub.publish("chat", "hi"), 1);
+        assert_eq!(rx_a.recv().await.unwrap(), "hi");
+        assert!(rx_b.try_recv().is_err());
+
+        assert_eq!(hub.broadcast("all"), 2);
+        assert_eq!(rx_b.recv().await.unwrap(), "all");
+
+        hub.unsubscribe(a, "chat");
+        assert_eq!(hub.publish("chat", "gone"), 0);
+        assert_eq!(hub.subscriber_count("chat"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let hub = WsHub::new(2);
+        let (_id, rx) = hub.connect(vec!["t".to_string()]);
+
+        assert_eq!(hub.publish("t", "1"), 1);
+        assert_eq!(hub.publish("t", "2"), 1);
+        // the channel is full now, messages get dropped until the client is cut off.
+        for _ in 0..MAX_LAGGED {
+            assert_eq!(hub.publish("t", "x"), 0);
+        }
+        assert_eq!(hub.client_count(), 0);
+        drop(rx);
+
+        let (_id, rx) = hub.connect(vec!["t".to_string()]);
+        drop(rx);
+        hub.publish("t", "closed");
+        assert_eq!(hub.client_count(), 0);
+    }
+
+    #[test]
+    fn test_oob() {
+        assert_eq!(oob("notifications", "beforeend", "<p>x</p>"),
+                   r#"<div id="notifications" hx-swap-oob="beforeend"><p>x</p></div>"#);
+    }
+}
@@ -3,7 +3,7 @@ use sqlx::{Error, FromRow};
 
 use crate::tables::{DBPool, DBQueryResult};
 
-#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
 pub struct ApiEntry {
     pub id: i64,
     pub url: String,
@@ -12,10 +12,29 @@ pub struct ApiEntry {
     pub headers: String,
     pub body: String,
     pub updated: String,
+    #[sqlx(default)]
+    pub collection: String,
+    /// 1 means this entry is served under `/mock/*`.
+    #[sqlx(default)]
+    pub mock_enabled: i32,
+    #[sqlx(default)]
+    pub mock_status: i32,
+    #[sqlx(default)]
+    pub mock_headers: String,
+    #[sqlx(default)]
+    pub mock_body: String,
+    #[sqlx(default)]
+    pub mock_delay_ms: i64,
+    /// `k=v&k2=v2` pairs the request query must contain.
+    #[sqlx(default)]
+    pub mock_query_match: String,
+    /// a regex the request body must match.
+    #[sqlx(default)]
+    pub mock_body_match: String,
 }
 
 
-#[derive(Serialize,Deserialize)]
+#[derive(Serialize,Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct UpdateApiEntry {
     pub id: Option<i64>,
     pub url: String,
@@ -23,6 +42,26 @@ pub struct UpdateApiEntry {
     pub url_params: String,
     pub headers: String,
     pub body: String,
+    #[serde(default)]
+    pub collection: String,
+    #[serde(default)]
+    pub mock_enabled: i32,
+    #[serde(default = "default_mock_status")]
+    pub mock_status: i32,
+    #[serde(default)]
+    pub mock_headers: String,
+    #[serde(default)]
+    pub mock_body: String,
+    #[serde(default)]
+    pub mock_delay_ms: i64,
+    #[serde(default)]
+    pub mock_query_match: String,
+    #[serde(default)]
+    pub mock_body_match: String,
+}
+
+fn default_mock_status() -> i32 {
+    200
 }
 
 #[derive(Serialize,Deserialize)]
@@ -46,12 +85,20 @@ pub struct ApiEntryVo {
 impl ApiEntry {
     pub async fn insert(t: UpdateApiEntry, pool: &DBPool) -> Result<DBQueryResult, Error> {
         //todo: this is just a template code, write your own business.
-        sqlx::query("INSERT INTO api_entry (url,method,url_params,headers,body) VALUES (?,?,?,?,?)")
+        sqlx::query("INSERT INTO api_entry (url,method,url_params,headers,body,collection,mock_enabled,mock_status,mock_headers,mock_body,mock_delay_ms,mock_query_match,mock_body_match) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?)")
             .bind(&t.url)
             .bind(&t.method)
             .bind(&t.url_params)
             .bind(&t.headers)
             .bind(&t.body)
+            .bind(&t.collection)
+            .bind(&t.mock_enabled)
+            .bind(&t.mock_status)
+            .bind(&t.mock_headers)
+            .bind(&t.mock_body)
+            .bind(&t.mock_delay_ms)
+            .bind(&t.mock_query_match)
+            .bind(&t.mock_body_match)
             .execute(pool)
             .await
     }
@@ -66,12 +113,20 @@ impl ApiEntry {
 
     pub async fn update(id: i64, t: UpdateApiEntry, pool: &DBPool) -> Result<DBQueryResult, Error> {
         //todo: this is just a template code, write your own business.
-        sqlx::query("UPDATE api_entry set url=?,method=?,url_params=?,headers=?,body=? WHERE id =?")
+        sqlx::query("UPDATE api_entry set url=?,method=?,url_params=?,headers=?,body=?,collection=?,mock_enabled=?,mock_status=?,mock_headers=?,mock_body=?,mock_delay_ms=?,mock_query_match=?,mock_body_match=? WHERE id =?")
             .bind(&t.url)
             .bind(&t.method)
             .bind(&t.url_params)
             .bind(&t.headers)
             .bind(&t.body)
+            .bind(&t.collection)
+            .bind(&t.mock_enabled)
+            .bind(&t.mock_status)
+            .bind(&t.mock_headers)
+            .bind(&t.mock_body)
+            .bind(&t.mock_delay_ms)
+            .bind(&t.mock_query_match)
+            .bind(&t.mock_body_match)
             .bind(&id)
             .execute(pool)
             .await
@@ -95,6 +150,17 @@ impl ApiEntry {
             .fetch_all(pool)
             .await
     }
+    pub async fn query_mocks(pool: &DBPool) -> Result<Vec<ApiEntry>, Error> {
+        sqlx::query_as::<_, ApiEntry>("SELECT * FROM api_entry where mock_enabled = 1")
+            .fetch_all(pool)
+            .await
+    }
+    pub async fn query_by_collection(collection: &str, pool: &DBPool) -> Result<Vec<ApiEntry>, Error> {
+        sqlx::query_as::<_, ApiEntry>("SELECT * FROM api_entry where collection = ?")
+            .bind(collection)
+            .fetch_all(pool)
+            .await
+    }
 }
 
 
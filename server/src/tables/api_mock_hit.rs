@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+use crate::tables::{DBPool, DBQueryResult};
+
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
+pub struct ApiMockHit {
+    pub id: i64,
+    /// 0 when no mock entry matched the request.
+    pub api_entry_id: i64,
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: String,
+    pub body: String,
+    pub status: i32,
+    pub create_time: i64,
+}
+
+
+impl ApiMockHit {
+    pub async fn insert(t: &ApiMockHit, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("INSERT INTO api_mock_hit (api_entry_id,method,path,query,headers,body,status,create_time) VALUES (?,?,?,?,?,?,?,?)")
+            .bind(&t.api_entry_id)
+            .bind(&t.method)
+            .bind(&t.path)
+            .bind(&t.query)
+            .bind(&t.headers)
+            .bind(&t.body)
+            .bind(&t.status)
+            .bind(&t.create_time)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn query_recent(limit: i64, pool: &DBPool) -> Result<Vec<ApiMockHit>, Error> {
+        sqlx::query_as::<_, ApiMockHit>("SELECT * FROM api_mock_hit order by id desc limit ?")
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn delete_all(pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from api_mock_hit")
+            .execute(pool)
+            .await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::init_test_pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all() -> anyhow::Result<()> {
+        //the test pool is just a memory sqlite.
+        let pool = init_test_pool().await;
+
+        for i in 0..3 {
+            let r = ApiMockHit::insert(&ApiMockHit {
+                api_entry_id: i,
+                method: "GET".to_string(),
+                path: format!("/users/{}", i),
+                status: 200,
+                ..Default::default()
+            }, &pool).await?;
+            assert_eq!(r.rows_affected(), 1);
+        }
+
+        let rows = ApiMockHit::query_recent(2, &pool).await?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].path, "/users/2");
+
+        let r = ApiMockHit::delete_all(&pool).await?;
+        assert_eq!(r.rows_affected(), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+use crate::tables::{DBPool, DBQueryResult};
+
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
+pub struct EmailAttachment {
+    pub id: i64,
+    pub email_id: i64,
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    pub cid: String,
+    /// relative to `DATA_DIR`
+    pub file_path: String,
+    pub create_time: i64,
+}
+
+
+impl EmailAttachment {
+    pub async fn insert(t: &EmailAttachment, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("INSERT INTO email_attachment (email_id,filename,content_type,size,cid,file_path,create_time) VALUES (?,?,?,?,?,?,?)")
+            .bind(&t.email_id)
+            .bind(&t.filename)
+            .bind(&t.content_type)
+            .bind(&t.size)
+            .bind(&t.cid)
+            .bind(&t.file_path)
+            .bind(&t.create_time)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn get_by_id(id: i64, pool: &DBPool) -> Result<Vec<EmailAttachment>, Error> {
+        sqlx::query_as::<_, EmailAttachment>("SELECT * FROM email_attachment where id = ?")
+            .bind(id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn query_by_email(email_id: i64, pool: &DBPool) -> Result<Vec<EmailAttachment>, Error> {
+        sqlx::query_as::<_, EmailAttachment>("SELECT * FROM email_attachment where email_id = ? order by id")
+            .bind(email_id)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn query_all(pool: &DBPool) -> Result<Vec<EmailAttachment>, Error> {
+        sqlx::query_as::<_, EmailAttachment>("SELECT * FROM email_attachment order by id")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn delete_by_email(email_id: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from email_attachment WHERE email_id = ?")
+            .bind(email_id)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn delete_all(pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from email_attachment")
+            .execute(pool)
+            .await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::init_test_pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all() -> anyhow::Result<()> {
+        let pool = init_test_pool().await;
+
+        for name in ["a.txt", "b.png"] {
+            let r = EmailAttachment::insert(&EmailAttachment {
+                email_id: 7,
+                filename: name.to_string(),
+                size: 3,
+                ..Default::default()
+            }, &pool).await?;
+            assert_eq!(r.rows_affected(), 1);
+        }
+
+        let rows = EmailAttachment::query_by_email(7, &pool).await?;
+        assert_eq!(rows.iter().map(|r| r.filename.as_str()).collect::<Vec<_>>(), vec!["a.txt", "b.png"]);
+        assert_eq!(EmailAttachment::get_by_id(rows[1].id, &pool).await?[0].filename, "b.png");
+
+        let r = EmailAttachment::delete_by_email(7, &pool).await?;
+        assert_eq!(r.rows_affected(), 2);
+        assert!(EmailAttachment::query_by_email(7, &pool).await?.is_empty());
+        Ok(())
+    }
+}
@@ -16,12 +16,82 @@ pub struct EmailInbox {
     pub full_body: String,
     pub attachments: String,
     pub create_time: i64,
+    pub is_read: bool,
+    /// comma joined, like `to_mail`.
+    pub cc_mail: String,
+    /// `MAIL FROM` of the smtp session.
+    pub envelope_from: String,
+    /// comma joined `RCPT TO` of the smtp session, the addresses the message was delivered to.
+    pub envelope_to: String,
+    /// comma joined, set by mail rules.
+    pub tags: String,
+    /// set when a mail rule moved the message, it then shows up in this mailbox only.
+    pub mailbox: String,
+    /// `pass`, `fail`, ... of the sender checks, empty for mails not received over smtp.
+    pub spf_result: String,
+    pub dkim_result: String,
+    pub dmarc_result: String,
+    /// the checks in `Authentication-Results` form.
+    pub auth_details: String,
+    /// bytes on disk, the raw message plus its attachments. 0 for mails stored before it was counted.
+    pub size: i64,
+}
+
+/// one recipient address with its message counts, the server is a catch-all so every address gets one.
+/// grouped by the envelope recipients, bcc included, or by `To:` for mails stored without an envelope.
+/// a mail moved by a rule counts for its `mailbox` instead.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, PartialEq)]
+pub struct Mailbox {
+    pub address: String,
+    pub total: i64,
+    pub unread: i64,
+    pub bytes: i64,
+}
+
+/// `q` matches subject, sender and bodies, `mailbox` one of the recipients, both skipped when empty.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct InboxFilter {
+    pub q: String,
+    pub mailbox: String,
+}
+
+impl InboxFilter {
+    fn where_clause(&self) -> (String, Vec<String>) {
+        let mut clause = vec!["1 = 1".to_string()];
+        let mut binds = vec![];
+        if !self.q.trim().is_empty() {
+            clause.push("(subject like ? escape '\\' or from_mail like ? escape '\\' or plain_content like ? escape '\\' or html_content like ? escape '\\' or tags like ? escape '\\')".to_string());
+            binds.extend(vec![format!("%{}%", escape_like(self.q.trim())); 5]);
+        }
+        if !self.mailbox.trim().is_empty() {
+            // a comma joined list, wrapped so the first and last address match too.
+            clause.push(format!("(',' || ({}) || ',') like ? escape '\\'", MAILBOX_EXPR));
+            binds.push(format!("%,{},%", escape_like(self.mailbox.trim())));
+        }
+        (clause.join(" and "), binds)
+    }
+}
+
+/// the comma joined mailboxes a message belongs to.
+const MAILBOX_EXPR: &str = "case when mailbox != '' then mailbox when envelope_to = '' then to_mail else envelope_to end";
+
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
 }
 
 
 impl EmailInbox {
+/// envelope recipients missing from both `To:` and `Cc:`.
+pub fn bcc(&self) -> Vec<String> {
+    let split = |s: &str| s.split(',').map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect::<Vec<String>>();
+    let headers = [split(&self.to_mail), split(&self.cc_mail)];
+    split(&self.envelope_to).into_iter()
+        .filter(|e| !headers.iter().flatten().any(|a| a.eq_ignore_ascii_case(e)))
+        .collect()
+}
+
 pub async fn insert(t: &EmailInbox, pool: &DBPool) -> Result<DBQueryResult, Error> {
-    sqlx::query("INSERT INTO email_inbox (from_mail,to_mail,send_date,subject,plain_content,html_content,full_body,attachments,create_time) VALUES (?,?,?,?,?,?,?,?,?)")
+    sqlx::query("INSERT INTO email_inbox (from_mail,to_mail,send_date,subject,plain_content,html_content,full_body,attachments,create_time,cc_mail,envelope_from,envelope_to,tags,mailbox,spf_result,dkim_result,dmarc_result,auth_details,size) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)")
     .bind(&t.from_mail)
     .bind(&t.to_mail)
     .bind(&t.send_date)
@@ -31,6 +101,16 @@ pub async fn insert(t: &EmailInbox, pool: &DBPool) -> Result<DBQueryResult, Erro
     .bind(&t.full_body)
     .bind(&t.attachments)
     .bind(&t.create_time)
+    .bind(&t.cc_mail)
+    .bind(&t.envelope_from)
+    .bind(&t.envelope_to)
+    .bind(&t.tags)
+    .bind(&t.mailbox)
+    .bind(&t.spf_result)
+    .bind(&t.dkim_result)
+    .bind(&t.dmarc_result)
+    .bind(&t.auth_details)
+    .bind(&t.size)
     .execute(pool)
     .await
 }
@@ -51,7 +131,7 @@ pub async fn delete_all(pool: &DBPool) -> Result<DBQueryResult, Error> {
 
 pub async fn update(id: i64, t: &EmailInbox, pool: &DBPool) -> Result<DBQueryResult, Error> {
     //todo: this is just a template code, write your own business.
-    sqlx::query("UPDATE email_inbox set from_mail=?,to_mail=?,send_date=?,subject=?,plain_content=?,html_content=?,full_body=?,attachments=?,create_time=? WHERE id =?")
+    sqlx::query("UPDATE email_inbox set from_mail=?,to_mail=?,send_date=?,subject=?,plain_content=?,html_content=?,full_body=?,attachments=?,create_time=?,cc_mail=?,envelope_from=?,envelope_to=?,tags=?,mailbox=?,spf_result=?,dkim_result=?,dmarc_result=?,auth_details=?,size=? WHERE id =?")
     .bind(&t.from_mail)
     .bind(&t.to_mail)
     .bind(&t.send_date)
@@ -61,11 +141,90 @@ pub async fn update(id: i64, t: &EmailInbox, pool: &DBPool) -> Result<DBQueryRes
     .bind(&t.full_body)
     .bind(&t.attachments)
     .bind(&t.create_time)
+    .bind(&t.cc_mail)
+    .bind(&t.envelope_from)
+    .bind(&t.envelope_to)
+    .bind(&t.tags)
+    .bind(&t.mailbox)
+    .bind(&t.spf_result)
+    .bind(&t.dkim_result)
+    .bind(&t.dmarc_result)
+    .bind(&t.auth_details)
+    .bind(&t.size)
+    .bind(&id)
+    .execute(pool)
+    .await
+}
+
+pub async fn update_full_body(id: i64, full_body: &str, pool: &DBPool) -> Result<DBQueryResult, Error> {
+    sqlx::query("UPDATE email_inbox set full_body=? WHERE id =?")
+    .bind(full_body)
+    .bind(&id)
+    .execute(pool)
+    .await
+}
+
+pub async fn get_by_id(id: i64, pool: &DBPool) -> Result<Vec<EmailInbox>, Error> {
+    sqlx::query_as::<_, EmailInbox>("SELECT * FROM email_inbox where id = ?")
+    .bind(&id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn mark_read(id: i64, is_read: bool, pool: &DBPool) -> Result<DBQueryResult, Error> {
+    sqlx::query("UPDATE email_inbox set is_read=? WHERE id =?")
+    .bind(is_read)
     .bind(&id)
     .execute(pool)
     .await
 }
 
+/// newest first, one page of the messages matching `filter`.
+pub async fn search(filter: &InboxFilter, offset: i64, limit: i64, pool: &DBPool) -> Result<Vec<EmailInbox>, Error> {
+    let (clause, binds) = filter.where_clause();
+    let sql = format!("SELECT * FROM email_inbox where {} order by id desc limit ? offset ?", clause);
+    let mut query = sqlx::query_as::<_, EmailInbox>(&sql);
+    for b in binds {
+        query = query.bind(b);
+    }
+    query.bind(limit).bind(offset)
+    .fetch_all(pool)
+    .await
+}
+
+/// how many mails and how many bytes they take, over every mailbox.
+pub async fn totals(pool: &DBPool) -> Result<(i64, i64), Error> {
+    sqlx::query_as("SELECT count(*), coalesce(sum(size), 0) FROM email_inbox")
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn count(filter: &InboxFilter, pool: &DBPool) -> Result<i64, Error> {
+    let (clause, binds) = filter.where_clause();
+    let sql = format!("SELECT count(*) FROM email_inbox where {}", clause);
+    let mut query = sqlx::query_scalar::<_, i64>(&sql);
+    for b in binds {
+        query = query.bind(b);
+    }
+    query.fetch_one(pool).await
+}
+
+pub async fn mailboxes(pool: &DBPool) -> Result<Vec<Mailbox>, Error> {
+    let rows: Vec<(String, bool, i64)> = sqlx::query_as(&format!("SELECT {}, is_read, size FROM email_inbox", MAILBOX_EXPR))
+    .fetch_all(pool)
+    .await?;
+    let mut boxes: std::collections::BTreeMap<String, Mailbox> = Default::default();
+    for (to_mail, is_read, size) in rows {
+        for address in to_mail.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()) {
+            let b = boxes.entry(address.to_lowercase()).or_insert_with(|| Mailbox { address: address.to_lowercase(), ..Default::default() });
+            b.total += 1;
+            b.unread += if is_read { 0 } else { 1 };
+            b.bytes += size;
+        }
+    }
+    Ok(boxes.into_values().collect())
+}
+
 pub async fn query(q: &EmailInbox, pool: &DBPool) -> Result<Vec<EmailInbox>, Error> {
     //todo: this is just a template code, write your own business.
     sqlx::query_as::<_, EmailInbox>("SELECT * FROM email_inbox where from_mail = ?")
@@ -93,41 +252,69 @@ mod tests {
     async fn test_all() -> anyhow::Result<()> {
         //the test pool is just a memory sqlite.
         let pool = init_test_pool().await;
+        EmailInbox::delete_all(&pool).await?;
 
-        //todo: uncomment below code and write your tests.
-        /*
-        let r = EmailInbox::insert(&EmailInbox {
-             ..Default::default()
-        }, &pool).await?;
+        for (to, subject, body) in [("a@x.com", "hello", "first"), ("b@x.com,A@x.com", "50% off", "second"), ("c@x.com", "re: hello", "third")] {
+            let r = EmailInbox::insert(&EmailInbox {
+                to_mail: to.to_string(),
+                subject: subject.to_string(),
+                plain_content: body.to_string(),
+                ..Default::default()
+            }, &pool).await?;
+            assert_eq!(r.rows_affected(), 1);
+        }
 
-        assert_eq!(r.rows_affected(), 1);
+        let filter = |q: &str, mailbox: &str| InboxFilter { q: q.to_string(), mailbox: mailbox.to_string() };
+        let subjects = |rows: Vec<EmailInbox>| rows.into_iter().map(|r| r.subject).collect::<Vec<_>>();
 
-        let rows = EmailInbox::query(&EmailInbox {
-            ..Default::default()
-        }, &pool).await?;
-        assert_eq!(rows.len(), 1);
+        assert_eq!(subjects(EmailInbox::search(&filter("hello", ""), 0, 10, &pool).await?), vec!["re: hello", "hello"]);
+        assert_eq!(subjects(EmailInbox::search(&filter("SECOND", ""), 0, 10, &pool).await?), vec!["50% off"]);
+        // `%` is matched literally.
+        assert_eq!(EmailInbox::count(&filter("%", ""), &pool).await?, 1);
+        // addresses match case insensitively, the same as the mailbox list groups them.
+        assert_eq!(subjects(EmailInbox::search(&filter("", "a@x.com"), 0, 10, &pool).await?), vec!["50% off", "hello"]);
+        assert_eq!(EmailInbox::count(&filter("", ""), &pool).await?, 3);
+        assert_eq!(subjects(EmailInbox::search(&filter("", ""), 1, 1, &pool).await?), vec!["50% off"]);
 
-        let r = EmailInbox::update(1, &EmailInbox {
-            ..Default::default()
-        }, &pool).await?;
-        assert_eq!(r.rows_affected(), 1);
+        let first = EmailInbox::search(&filter("first", ""), 0, 1, &pool).await?;
+        EmailInbox::mark_read(first[0].id, true, &pool).await?;
+        assert!(EmailInbox::get_by_id(first[0].id, &pool).await?[0].is_read);
 
-        let rows = EmailInbox::query(&EmailInbox {
+        let boxes = EmailInbox::mailboxes(&pool).await?;
+        assert_eq!(boxes[0], Mailbox { address: "a@x.com".to_string(), total: 2, unread: 1, bytes: 0 });
+        assert_eq!(boxes.iter().map(|b| b.address.as_str()).collect::<Vec<_>>(), vec!["a@x.com", "b@x.com", "c@x.com"]);
+
+        // delivered by envelope, the bcc'd address gets the mail in its mailbox too.
+        EmailInbox::insert(&EmailInbox {
+            to_mail: "a@x.com".to_string(),
+            cc_mail: "b@x.com".to_string(),
+            envelope_to: "a@x.com,b@x.com,hidden@x.com".to_string(),
+            subject: "bcc".to_string(),
             ..Default::default()
         }, &pool).await?;
-        assert_eq!(rows[0].id, 1);
-
-        let  r = EmailInbox::delete(1, &pool).await?;
-        assert_eq!(r.rows_affected(),1);
+        let rows = EmailInbox::search(&filter("", "hidden@x.com"), 0, 10, &pool).await?;
+        assert_eq!(subjects(rows.clone()), vec!["bcc"]);
+        assert_eq!(rows[0].bcc(), vec!["hidden@x.com"]);
+        assert!(EmailInbox::mailboxes(&pool).await?.iter().any(|b| b.address == "hidden@x.com"));
 
-        let rows = EmailInbox::query(&EmailInbox {
+        // moved by a rule : only in its new mailbox, and found by tag.
+        EmailInbox::insert(&EmailInbox {
+            to_mail: "a@x.com".to_string(),
+            subject: "moved".to_string(),
+            tags: "invoice".to_string(),
+            mailbox: "archive".to_string(),
+            spf_result: "pass".to_string(),
+            auth_details: "spf=pass smtp.mailfrom=x.com".to_string(),
+            size: 100,
             ..Default::default()
         }, &pool).await?;
-        assert_eq!(rows.len(), 0);
-
-        */
-
-
+        let moved = EmailInbox::search(&filter("", "archive"), 0, 10, &pool).await?;
+        assert_eq!(subjects(moved.clone()), vec!["moved"]);
+        assert_eq!((moved[0].spf_result.as_str(), moved[0].dkim_result.as_str()), ("pass", ""));
+        assert!(!subjects(EmailInbox::search(&filter("", "a@x.com"), 0, 10, &pool).await?).contains(&"moved".to_string()));
+        assert_eq!(subjects(EmailInbox::search(&filter("invoice", ""), 0, 10, &pool).await?), vec!["moved"]);
+        assert_eq!(EmailInbox::totals(&pool).await?, (5, 100));
+        assert_eq!(EmailInbox::mailboxes(&pool).await?.iter().find(|b| b.address == "archive").map(|b| b.bytes), Some(100));
         Ok(())
     }
-}
\ No newline at end of file
+}
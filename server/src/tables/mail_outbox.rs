@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+use crate::tables::{DBPool, DBQueryResult};
+
+pub const STATUS_QUEUED: &str = "queued";
+pub const STATUS_SENT: &str = "sent";
+pub const STATUS_FAILED: &str = "failed";
+
+/// a mail waiting for (or done with) the smarthost, `mail_json` is the whole `OutgoingMail`.
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
+pub struct MailOutbox {
+    pub id: i64,
+    pub from_mail: String,
+    pub to_mail: String,
+    pub subject: String,
+    pub mail_json: String,
+    pub status: String,
+    pub attempts: i64,
+    /// unix millis, the worker skips it until then.
+    pub next_attempt_at: i64,
+    pub last_error: String,
+    pub create_time: i64,
+    pub update_time: i64,
+}
+
+
+impl MailOutbox {
+    pub async fn insert(t: &MailOutbox, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("INSERT INTO mail_outbox (from_mail,to_mail,subject,mail_json,status,attempts,next_attempt_at,last_error,create_time,update_time) VALUES (?,?,?,?,?,?,?,?,?,?)")
+            .bind(&t.from_mail)
+            .bind(&t.to_mail)
+            .bind(&t.subject)
+            .bind(&t.mail_json)
+            .bind(&t.status)
+            .bind(&t.attempts)
+            .bind(&t.next_attempt_at)
+            .bind(&t.last_error)
+            .bind(&t.create_time)
+            .bind(&t.update_time)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn get_by_id(id: i64, pool: &DBPool) -> Result<Vec<MailOutbox>, Error> {
+        sqlx::query_as::<_, MailOutbox>("SELECT * FROM mail_outbox where id = ?")
+            .bind(id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// queued mails whose next attempt is due, oldest first.
+    pub async fn query_due(now: i64, limit: i64, pool: &DBPool) -> Result<Vec<MailOutbox>, Error> {
+        sqlx::query_as::<_, MailOutbox>("SELECT * FROM mail_outbox where status = ? and next_attempt_at <= ? order by id limit ?")
+            .bind(STATUS_QUEUED)
+            .bind(now)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// newest first, every status when `status` is empty.
+    pub async fn query_by_status(status: &str, pool: &DBPool) -> Result<Vec<MailOutbox>, Error> {
+        sqlx::query_as::<_, MailOutbox>("SELECT * FROM mail_outbox where ? = '' or status = ? order by id desc")
+            .bind(status)
+            .bind(status)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn count_by_status(status: &str, pool: &DBPool) -> Result<i64, Error> {
+        sqlx::query_scalar("SELECT count(*) FROM mail_outbox where status = ?")
+            .bind(status)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn mark_sent(id: i64, attempts: i64, now: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("UPDATE mail_outbox set status = ?, attempts = ?, last_error = '', update_time = ? WHERE id = ?")
+            .bind(STATUS_SENT)
+            .bind(attempts)
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn mark_failed(id: i64, attempts: i64, error: &str, now: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("UPDATE mail_outbox set status = ?, attempts = ?, last_error = ?, update_time = ? WHERE id = ?")
+            .bind(STATUS_FAILED)
+            .bind(attempts)
+            .bind(error)
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+
+    /// a transient failure, stays queued until `next_attempt_at`.
+    pub async fn reschedule(id: i64, attempts: i64, next_attempt_at: i64, error: &str, now: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("UPDATE mail_outbox set attempts = ?, next_attempt_at = ?, last_error = ?, update_time = ? WHERE id = ?")
+            .bind(attempts)
+            .bind(next_attempt_at)
+            .bind(error)
+            .bind(now)
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+
+    /// puts a failed mail back in the queue, due right away with a fresh attempt count.
+    pub async fn retry(id: i64, now: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("UPDATE mail_outbox set status = ?, attempts = 0, next_attempt_at = ?, update_time = ? WHERE id = ? and status = ?")
+            .bind(STATUS_QUEUED)
+            .bind(now)
+            .bind(now)
+            .bind(id)
+            .bind(STATUS_FAILED)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn delete(id: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from mail_outbox WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::init_test_pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all() -> anyhow::Result<()> {
+        let pool = init_test_pool().await;
+
+        let mut ids = vec![];
+        for (subject, due) in [("a", 10), ("b", 20)] {
+            let r = MailOutbox::insert(&MailOutbox {
+                subject: subject.to_string(),
+                mail_json: "{}".to_string(),
+                status: STATUS_QUEUED.to_string(),
+                next_attempt_at: due,
+                ..Default::default()
+            }, &pool).await?;
+            ids.push(get_last_insert_id!(r));
+        }
+
+        assert_eq!(MailOutbox::query_due(15, 10, &pool).await?.len(), 1);
+        assert_eq!(MailOutbox::query_due(20, 10, &pool).await?.len(), 2);
+
+        MailOutbox::reschedule(ids[0], 1, 100, "421 busy", 15, &pool).await?;
+        let due = MailOutbox::query_due(20, 10, &pool).await?;
+        assert_eq!(due.iter().map(|m| m.subject.as_str()).collect::<Vec<_>>(), vec!["b"]);
+
+        MailOutbox::mark_sent(ids[1], 1, 20, &pool).await?;
+        MailOutbox::mark_failed(ids[0], 2, "550 no such user", 30, &pool).await?;
+        assert_eq!(MailOutbox::count_by_status(STATUS_SENT, &pool).await?, 1);
+        assert_eq!(MailOutbox::count_by_status(STATUS_FAILED, &pool).await?, 1);
+        assert_eq!(MailOutbox::query_by_status("", &pool).await?.len(), 2);
+        assert!(MailOutbox::query_due(1000, 10, &pool).await?.is_empty());
+
+        // only failed mails go back in the queue.
+        assert_eq!(MailOutbox::retry(ids[1], 40, &pool).await?.rows_affected(), 0);
+        assert_eq!(MailOutbox::retry(ids[0], 40, &pool).await?.rows_affected(), 1);
+        let m = &MailOutbox::get_by_id(ids[0], &pool).await?[0];
+        assert_eq!((m.status.as_str(), m.attempts, m.next_attempt_at), (STATUS_QUEUED, 0, 40));
+
+        MailOutbox::delete(ids[0], &pool).await?;
+        assert!(MailOutbox::get_by_id(ids[0], &pool).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, FromRow};
+
+use crate::tables::{DBPool, DBQueryResult};
+
+///
+/// a rule for received mails, every non empty `match_*` has to match.
+/// `action` is one of `service::mail_rules::ACTIONS`, `action_arg` depends on it.
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
+pub struct MailRule {
+    pub id: i64,
+    pub name: String,
+    pub enabled: bool,
+    /// lower runs first.
+    pub priority: i64,
+    /// part of the sender address, case insensitive.
+    pub match_from: String,
+    /// part of any recipient address, bcc included.
+    pub match_to: String,
+    /// a regex.
+    pub match_subject: String,
+    /// prefix of any attachment content type, e.g. `image/` or `application/pdf`.
+    pub match_attachment_type: String,
+    pub action: String,
+    pub action_arg: String,
+    pub create_time: i64,
+}
+
+/// a rule matched a mail, `email_id` is 0 when the mail was dropped.
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize, Default)]
+pub struct MailRuleHit {
+    pub id: i64,
+    pub rule_id: i64,
+    pub email_id: i64,
+    pub from_mail: String,
+    pub subject: String,
+    pub result: String,
+    pub create_time: i64,
+}
+
+
+impl MailRule {
+    pub async fn insert(t: &MailRule, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("INSERT INTO mail_rule (name,enabled,priority,match_from,match_to,match_subject,match_attachment_type,action,action_arg,create_time) VALUES (?,?,?,?,?,?,?,?,?,?)")
+            .bind(&t.name)
+            .bind(&t.enabled)
+            .bind(&t.priority)
+            .bind(&t.match_from)
+            .bind(&t.match_to)
+            .bind(&t.match_subject)
+            .bind(&t.match_attachment_type)
+            .bind(&t.action)
+            .bind(&t.action_arg)
+            .bind(&t.create_time)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn get_by_id(id: i64, pool: &DBPool) -> Result<Vec<MailRule>, Error> {
+        sqlx::query_as::<_, MailRule>("SELECT * FROM mail_rule where id = ?")
+            .bind(id)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// in the order they run.
+    pub async fn query_all(pool: &DBPool) -> Result<Vec<MailRule>, Error> {
+        sqlx::query_as::<_, MailRule>("SELECT * FROM mail_rule order by priority, id")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn query_enabled(pool: &DBPool) -> Result<Vec<MailRule>, Error> {
+        sqlx::query_as::<_, MailRule>("SELECT * FROM mail_rule where enabled = 1 order by priority, id")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn set_enabled(id: i64, enabled: bool, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("UPDATE mail_rule set enabled = ? WHERE id = ?")
+            .bind(enabled)
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+
+    pub async fn delete(id: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from mail_rule WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await
+    }
+}
+
+impl MailRuleHit {
+    pub async fn insert(t: &MailRuleHit, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("INSERT INTO mail_rule_hit (rule_id,email_id,from_mail,subject,result,create_time) VALUES (?,?,?,?,?,?)")
+            .bind(&t.rule_id)
+            .bind(&t.email_id)
+            .bind(&t.from_mail)
+            .bind(&t.subject)
+            .bind(&t.result)
+            .bind(&t.create_time)
+            .execute(pool)
+            .await
+    }
+
+    /// newest first.
+    pub async fn query_by_rule(rule_id: i64, limit: i64, pool: &DBPool) -> Result<Vec<MailRuleHit>, Error> {
+        sqlx::query_as::<_, MailRuleHit>("SELECT * FROM mail_rule_hit where rule_id = ? order by id desc limit ?")
+            .bind(rule_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn count_by_rule(rule_id: i64, pool: &DBPool) -> Result<i64, Error> {
+        sqlx::query_scalar("SELECT count(*) FROM mail_rule_hit where rule_id = ?")
+            .bind(rule_id)
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn delete_by_rule(rule_id: i64, pool: &DBPool) -> Result<DBQueryResult, Error> {
+        sqlx::query("DELETE from mail_rule_hit WHERE rule_id = ?")
+            .bind(rule_id)
+            .execute(pool)
+            .await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::init_test_pool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_all() -> anyhow::Result<()> {
+        let pool = init_test_pool().await;
+
+        let mut ids = vec![];
+        for (name, priority) in [("late", 10), ("early", 1)] {
+            let r = MailRule::insert(&MailRule { name: name.to_string(), enabled: true, priority, action: "drop".to_string(), ..Default::default() }, &pool).await?;
+            ids.push(get_last_insert_id!(r));
+        }
+        let names = |rules: Vec<MailRule>| rules.into_iter().map(|r| r.name).collect::<Vec<_>>();
+        assert_eq!(names(MailRule::query_all(&pool).await?), vec!["early", "late"]);
+
+        MailRule::set_enabled(ids[1], false, &pool).await?;
+        assert_eq!(names(MailRule::query_enabled(&pool).await?), vec!["late"]);
+
+        for subject in ["a", "b"] {
+            MailRuleHit::insert(&MailRuleHit { rule_id: ids[0], subject: subject.to_string(), result: "dropped".to_string(), ..Default::default() }, &pool).await?;
+        }
+        assert_eq!(MailRuleHit::count_by_rule(ids[0], &pool).await?, 2);
+        assert_eq!(MailRuleHit::query_by_rule(ids[0], 1, &pool).await?[0].subject, "b");
+
+        MailRule::delete(ids[0], &pool).await?;
+        MailRuleHit::delete_by_rule(ids[0], &pool).await?;
+        assert!(MailRule::get_by_id(ids[0], &pool).await?.is_empty());
+        assert_eq!(MailRuleHit::count_by_rule(ids[0], &pool).await?, 0);
+        Ok(())
+    }
+}
@@ -14,6 +14,7 @@ pub mod user;
 pub mod article;
 pub mod todo_item;
 pub mod api_entry;
+pub mod api_mock_hit;
 
 
 #[cfg(feature =  "use_sqlite")]
@@ -63,9 +64,65 @@ pub async fn init_pool(config : &Config) -> DBPool {
     let db = SqlitePool::connect(db_url).await.unwrap();
     let result = sqlx::query(include_str!(file_path!("/../doc/db_sqlite.sql"))).execute(&db).await.unwrap();
     // info!("Create  table result: {:?}", result);
+    add_missing_columns(&db).await.unwrap();
+    clear_legacy_mail_bodies(&db).await.unwrap();
     db
 }
 
+/// columns added after a table shipped, `CREATE TABLE IF NOT EXISTS` leaves existing dbs without them.
+#[cfg(feature =   "use_sqlite")]
+const ADDED_COLUMNS: [(&str, &str, &str); 19] = [
+    ("api_entry", "collection", "VARCHAR DEFAULT ''"),
+    ("api_entry", "mock_enabled", "INTEGER DEFAULT 0"),
+    ("api_entry", "mock_status", "INTEGER DEFAULT 200"),
+    ("api_entry", "mock_headers", "VARCHAR DEFAULT ''"),
+    ("api_entry", "mock_body", "VARCHAR DEFAULT ''"),
+    ("api_entry", "mock_delay_ms", "INTEGER DEFAULT 0"),
+    ("api_entry", "mock_query_match", "VARCHAR DEFAULT ''"),
+    ("api_entry", "mock_body_match", "VARCHAR DEFAULT ''"),
+    ("email_inbox", "is_read", "INTEGER NOT NULL DEFAULT 0"),
+    ("email_inbox", "cc_mail", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "envelope_from", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "envelope_to", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "tags", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "mailbox", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "spf_result", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "dkim_result", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "dmarc_result", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "auth_details", "VARCHAR NOT NULL DEFAULT ''"),
+    ("email_inbox", "size", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+#[cfg(feature =   "use_sqlite")]
+async fn add_missing_columns(db: &DBPool) -> Result<(), sqlx::Error> {
+    for (table, column, definition) in ADDED_COLUMNS {
+        let exists: i64 = sqlx::query_scalar("SELECT count(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(db)
+            .await?;
+        if exists == 0 {
+            info!("add column {}.{}", table, column);
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition)).execute(db).await?;
+        }
+    }
+    Ok(())
+}
+
+/// before mails were stored on disk `full_body` and `attachments` held a `<TODO>` placeholder,
+/// blanked so they read as mails without a raw source. only `mails/..` paths are kept.
+async fn clear_legacy_mail_bodies(db: &DBPool) -> Result<(), sqlx::Error> {
+    let r = sqlx::query("UPDATE email_inbox SET full_body = '' WHERE full_body IS NULL OR (full_body <> '' AND substr(full_body, 1, 6) <> ? AND substr(full_body, 1, 6) <> ?)")
+        .bind("mails/")
+        .bind("mails\\")
+        .execute(db)
+        .await?;
+    if r.rows_affected() > 0 {
+        info!("cleared the raw source of {} mails stored before the mails dir", r.rows_affected());
+    }
+    sqlx::query("UPDATE email_inbox SET attachments = '' WHERE attachments IS NULL OR attachments = '<TODO>'").execute(db).await?;
+    Ok(())
+}
 
 #[cfg(feature =   "use_sqlite")]
 pub async fn init_test_pool() -> DBPool {
@@ -102,6 +159,7 @@ pub async fn init_pool(config : &Config) -> DBPool {
         let result = sqlx::query(s).execute(&db).await.unwrap();
         // info!("Create  table result: {:?}", result);
     };
+    clear_legacy_mail_bodies(&db).await.unwrap();
 
 
 
@@ -146,4 +204,7 @@ pub mod users;
 
 
 pub mod english_card;
-pub mod email_inbox;
\ No newline at end of file
+pub mod email_inbox;
+pub mod email_attachment;
+pub mod mail_outbox;
+pub mod mail_rule;
@@ -0,0 +1,35 @@
+use axum_test::TestServer;
+
+use play::init_app_state;
+use play::routers;
+use shared::constants::DATA_DIR;
+
+#[tokio::test]
+async fn test_save_config_is_validated() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-admin-config-test-{}", std::process::id()));
+    std::fs::create_dir_all(&data_dir)?;
+    std::env::set_var(DATA_DIR, &data_dir);
+    let config_path = data_dir.join("config.toml");
+    std::fs::write(&config_path, "server_port = 3000\n")?;
+    let server = TestServer::new(routers(init_app_state(&play::config::init_config(true), true).await))?;
+
+    let response = server.post("/admin/validate-config").form(&[("new_content", "server_port = \"x\"")]).await;
+    assert!(response.text().contains("in `server_port`"), "{}", response.text());
+    let response = server.post("/admin/validate-config").form(&[("new_content", "[https_cert]\nmode = \"static\"")]).await;
+    assert!(response.text().contains("https_cert.cert_path : required for the static mode"), "{}", response.text());
+    let response = server.post("/admin/validate-config").form(&[("new_content", "server_port = 4000")]).await;
+    assert_eq!(response.text(), "config ok.");
+    assert_eq!(std::fs::read_to_string(&config_path)?, "server_port = 3000\n");
+
+    // a broken config never reaches the disk.
+    let response = server.post("/admin/save-config").form(&[("new_content", "server_port = [")]).await;
+    assert!(response.text().starts_with("not saved."), "{}", response.text());
+    assert_eq!(std::fs::read_to_string(&config_path)?, "server_port = 3000\n");
+
+    let response = server.post("/admin/save-config").form(&[("new_content", "server_port = 4000")]).await;
+    assert_eq!(response.text(), "save ok.");
+    assert_eq!(std::fs::read_to_string(&config_path)?, "server_port = 4000");
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use axum_test::TestServer;
+
+use play::init_app_state;
+use play::routers;
+use play::tables::api_mock_hit::ApiMockHit;
+
+#[tokio::test]
+async fn test_import_and_export() -> anyhow::Result<()> {
+    let server = TestServer::new(routers(init_app_state(&play::config::init_config(true), true).await))?;
+
+    let response = server.post("/api-entry/import").form(&[
+        ("format", "postman"),
+        ("content", include_str!("fixtures/api_entry/requests.postman_collection.json")),
+        ("collection", "imported"),
+    ]).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.text(), "3");
+
+    let response = server.get("/api-entry/export").add_query_params(&[("format", "curl"), ("collection", "imported")]).await;
+    assert_eq!(response.status_code(), 200);
+    let text = response.text();
+    assert!(text.contains("curl -X GET 'https://httpbin.org/get?page=1'"));
+    assert!(text.contains("--data-raw 'name=play&lang=rust'"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_hits() -> anyhow::Result<()> {
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let server = TestServer::new(routers(state.clone()))?;
+
+    let mock = [("url", "/hits"), ("method", "POST"), ("headers", ""), ("url_params", ""), ("body", ""),
+        ("mock_enabled", "1"), ("mock_body", "ok")];
+    let response = server.post("/api-entry/save").form(&[&mock[..], &[("mock_delay_ms", "60000")]].concat()).await;
+    assert_eq!(response.status_code(), 500);
+    assert!(response.text().contains("mock delay should be between 0 and 2500 ms"));
+    let response = server.post("/api-entry/save").form(&mock).await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server.post("/mock/hits")
+        .add_header("authorization".parse()?, "Bearer secret".parse()?)
+        .add_header("cookie".parse()?, "session=secret".parse()?)
+        .text("x".repeat(100 * 1024)).await;
+    assert_eq!(response.text(), "ok");
+
+    let hit = &ApiMockHit::query_recent(1, &state.db).await?[0];
+    assert!(!hit.headers.contains("secret"));
+    assert!(hit.headers.contains("authorization: ***"));
+    assert!(hit.body.len() < 20 * 1024);
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use play::init_app_state;
+use play::service::backplane::{emit, publish_ws};
+use play::service::event_bus::AppEvent;
+
+#[tokio::test]
+async fn test_ws_fan_out_across_instances() {
+    // two app states in one process share the in-memory pub/sub, just like two instances on one redis.
+    let config = play::config::init_config(true);
+    let a = init_app_state(&config, true).await;
+    let b = init_app_state(&config, true).await;
+
+    let (_id, mut receiver) = b.ws_hub.connect(vec!["chat".to_string()]);
+
+    // the backplane subscribes in a background task, retry until it is up.
+    let mut received = None;
+    for _ in 0..20 {
+        publish_ws(&a, "chat", "<p>hello</p>").await;
+        if let Ok(msg) = tokio::time::timeout(Duration::from_millis(100), receiver.recv()).await {
+            received = msg;
+            break;
+        }
+    }
+    assert_eq!(received.as_deref(), Some("<p>hello</p>"));
+
+    // an instance doesn't deliver its own envelopes twice.
+    let (_id, mut own) = a.ws_hub.connect(vec!["chat".to_string()]);
+    publish_ws(&a, "chat", "once").await;
+    assert_eq!(own.recv().await.as_deref(), Some("once"));
+    assert!(tokio::time::timeout(Duration::from_millis(200), own.recv()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_event_ids_travel_with_the_events() {
+    let config = play::config::init_config(true);
+    let a = init_app_state(&config, true).await;
+    let b = init_app_state(&config, true).await;
+
+    let (_, mut receiver) = b.event_bus.subscribe(None);
+
+    let mut received = None;
+    for i in 0..20 {
+        emit(&a, AppEvent::UpgradeProgress { message: i.to_string() }).await;
+        if let Ok(event) = tokio::time::timeout(Duration::from_millis(100), receiver.recv()).await {
+            received = event.ok();
+            break;
+        }
+    }
+    let received = received.expect("no event from the other instance");
+
+    // b keeps the id a gave it, so a browser can reconnect to either one with its Last-Event-ID.
+    let (sent, _) = a.event_bus.subscribe(Some(0));
+    let sent = sent.into_iter().find(|e| e.event == received.event).unwrap();
+    assert_eq!(received.id, sent.id);
+    assert_eq!(b.event_bus.subscribe(Some(received.id - 1)).0, vec![received]);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "use_sqlite")]
+
+use sqlx::SqlitePool;
+
+use play::tables::api_entry::{ApiEntry, UpdateApiEntry};
+use play::tables::init_pool;
+
+/// a db file created by the first release, before any column was added.
+async fn baseline_db(name: &str) -> anyhow::Result<String> {
+    let path = std::env::temp_dir().join(format!("play-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = format!("sqlite://{}", path.display());
+    let db = SqlitePool::connect(&format!("{}?mode=rwc", url)).await?;
+    sqlx::query(include_str!("fixtures/db_sqlite_baseline.sql")).execute(&db).await?;
+    // what the first release stored for every received mail.
+    sqlx::query("INSERT INTO email_inbox(from_mail, to_mail, subject, full_body, attachments, create_time) VALUES ('a@x.com', 'b@x.com', 'old', '<TODO>', '<TODO>', 1)")
+        .execute(&db).await?;
+    db.close().await;
+    Ok(url)
+}
+
+async fn columns(db: &SqlitePool, table: &str) -> anyhow::Result<Vec<String>> {
+    Ok(sqlx::query_scalar("SELECT name FROM pragma_table_info(?)").bind(table).fetch_all(db).await?)
+}
+
+#[tokio::test]
+async fn test_baseline_db_gets_the_added_columns() -> anyhow::Result<()> {
+    let mut config = play::config::init_config(true);
+    config.database.url = baseline_db("upgrade").await?;
+    let db = init_pool(&config).await;
+
+    let api_entry = columns(&db, "api_entry").await?;
+    assert!(api_entry.contains(&"collection".to_string()), "{:?}", api_entry);
+
+    // the old rows and new ones with mocks both load.
+    sqlx::query("INSERT INTO api_entry(url, method, url_params, headers, body) VALUES ('/old', 'GET', '', '', '')").execute(&db).await?;
+    ApiEntry::insert(UpdateApiEntry { url: "/new".to_string(), method: "GET".to_string(), mock_enabled: 1, mock_body: "ok".to_string(), ..Default::default() }, &db).await?;
+    let entries = ApiEntry::query_all(&db).await?;
+    assert_eq!(entries.iter().map(|e| (e.url.as_str(), e.mock_enabled, e.mock_status)).collect::<Vec<_>>(), vec![("/old", 0, 200), ("/new", 1, 0)]);
+    assert_eq!(ApiEntry::query_mocks(&db).await?.len(), 1);
+
+    let email_inbox = columns(&db, "email_inbox").await?;
+    assert!(email_inbox.contains(&"mailbox".to_string()), "{:?}", email_inbox);
+    let legacy: (String, String) = sqlx::query_as("SELECT full_body, attachments FROM email_inbox WHERE subject = 'old'").fetch_one(&db).await?;
+    assert_eq!(legacy, ("".to_string(), "".to_string()));
+
+    db.close().await;
+    let _ = std::fs::remove_file(config.database.url.trim_start_matches("sqlite://"));
+    Ok(())
+}
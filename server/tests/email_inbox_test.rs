@@ -0,0 +1,133 @@
+use axum_test::TestServer;
+
+use play::init_app_state;
+use play::routers;
+use play::service::mail_store::{NewAttachment, save_email};
+use play::tables::email_attachment::EmailAttachment;
+use play::tables::email_inbox::{EmailInbox, InboxFilter};
+use shared::constants::DATA_DIR;
+
+#[tokio::test]
+async fn test_raw_message_and_attachments() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-mail-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let raw = "Subject: hi\r\n\r\nhello\r\n";
+    let id = save_email(&state, EmailInbox {
+        from_mail: "aa@qq.com".to_string(),
+        to_mail: "bb@cc.com".to_string(),
+        subject: "hi".to_string(),
+        ..Default::default()
+    }, raw.as_bytes(), &[NewAttachment {
+        filename: "../notes.txt".to_string(),
+        content_type: "text/plain".to_string(),
+        cid: "n1".to_string(),
+        body: b"some notes".to_vec(),
+    }]).await?;
+
+    let mail = &EmailInbox::get_by_id(id, &state.db).await?[0];
+    assert_eq!(mail.attachments, "../notes.txt");
+    let files = EmailAttachment::query_by_email(id, &state.db).await?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].size, 10);
+    assert!(data_dir.join(&files[0].file_path).starts_with(data_dir.join("mails")));
+
+    let server = TestServer::new(routers(state.clone()))?;
+    let response = server.get("/email-inbox/raw").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.header("content-type"), "message/rfc822");
+    assert_eq!(response.text(), raw);
+
+    let response = server.get("/email-inbox/attachment").add_query_params(&[("id", files[0].id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.header("content-type"), "text/plain");
+    assert_eq!(response.header("content-disposition"), "attachment; filename=\".._notes.txt\"");
+    assert_eq!(response.text(), "some notes");
+
+    let other = save_email(&state, EmailInbox { subject: "other".to_string(), ..Default::default() }, raw.as_bytes(), &[]).await?;
+    assert!(data_dir.join("mails").join(other.to_string()).exists());
+    let response = server.get("/email-inbox/delete").add_query_params(&[("id", other)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert!(EmailInbox::get_by_id(other, &state.db).await?.is_empty());
+    assert!(!data_dir.join("mails").join(other.to_string()).exists());
+
+    server.get("/email-inbox/delete-all").await;
+    assert!(!data_dir.join("mails").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_detail_and_mark_read() -> anyhow::Result<()> {
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let r = EmailInbox::insert(&EmailInbox {
+        to_mail: "box@cc.com".to_string(),
+        subject: "detail".to_string(),
+        html_content: "<p>hi</p>".to_string(),
+        ..Default::default()
+    }, &state.db).await?;
+    let id = play::get_last_insert_id!(r);
+    let server = TestServer::new(routers(state.clone()))?;
+
+    let response = server.get("/email-inbox/list").add_query_params(&[("q", "detail"), ("mailbox", "box@cc.com"), ("page", "3")]).await;
+    assert_eq!(response.status_code(), 200);
+
+    // opening a message marks it read.
+    let response = server.get("/email-inbox/detail").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert!(EmailInbox::get_by_id(id, &state.db).await?[0].is_read);
+
+    for view in ["html", "plain", "source"] {
+        let response = server.get("/email-inbox/detail").add_query_params(&[("id", id.to_string()), ("view", view.to_string())]).await;
+        assert_eq!(response.status_code(), 200);
+    }
+    let response = server.get("/email-inbox/detail").add_query_params(&[("id", id.to_string()), ("view", "pdf".to_string())]).await;
+    assert_eq!(response.status_code(), 500);
+
+    let response = server.get("/email-inbox/mark-read").add_query_params(&[("id", id.to_string()), ("read", "false".to_string())]).await;
+    assert_eq!(response.status_code(), 200);
+    assert!(!EmailInbox::get_by_id(id, &state.db).await?[0].is_read);
+
+    let filter = InboxFilter { mailbox: "BOX@cc.com".to_string(), ..Default::default() };
+    assert_eq!(EmailInbox::count(&filter, &state.db).await?, 1);
+    Ok(())
+}
+
+#[cfg(feature = "mail_server")]
+#[tokio::test]
+async fn test_sender_checks_are_stored() -> anyhow::Result<()> {
+    use mail_server::auth::dns::StaticResolver;
+    use mail_server::models::message::Message;
+    use play::handle_email_message;
+
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let resolver = StaticResolver::default()
+        .with_txt("shop.example", "v=spf1 ip4:192.0.2.0/24 -all")
+        .with_txt("_dmarc.shop.example", "v=DMARC1; p=quarantine");
+    let message = |subject: &str, ip: &str| Message {
+        sender: "news@shop.example".to_string(),
+        recipients: vec!["me@x.com".to_string()],
+        envelope_from: "bounce@shop.example".to_string(),
+        envelope_recipients: vec!["me@x.com".to_string()],
+        remote_ip: Some(ip.parse().unwrap()),
+        helo: "mail.shop.example".to_string(),
+        subject: subject.to_string(),
+        source: format!("From: news@shop.example\r\nSubject: {}\r\n\r\nhi\r\n", subject).into_bytes(),
+        ..Default::default()
+    };
+    handle_email_message(&state, &message("auth ok", "192.0.2.10"), &resolver).await;
+    handle_email_message(&state, &message("auth spoofed", "203.0.113.5"), &resolver).await;
+
+    let mails = EmailInbox::query(&EmailInbox { from_mail: "news@shop.example".to_string(), ..Default::default() }, &state.db).await?;
+    let results = |subject: &str| mails.iter().find(|m| m.subject == subject)
+        .map(|m| (m.spf_result.to_string(), m.dkim_result.to_string(), m.dmarc_result.to_string())).unwrap();
+    assert_eq!(results("auth ok"), ("pass".to_string(), "none".to_string(), "pass".to_string()));
+    assert_eq!(results("auth spoofed"), ("fail".to_string(), "none".to_string(), "fail".to_string()));
+    let spoofed = mails.iter().find(|m| m.subject == "auth spoofed").unwrap();
+    assert!(spoofed.auth_details.contains("dmarc=fail policy.dmarc=quarantine header.from=shop.example"), "{}", spoofed.auth_details);
+
+    let server = TestServer::new(routers(state.clone()))?;
+    let response = server.get("/email-inbox/detail").add_query_params(&[("id", spoofed.id)]).await;
+    assert_eq!(response.status_code(), 200);
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use mail_server::auth::dns::StaticResolver;
 use mail_server::models::message::Message;
 use play::handle_email_message;
 use play::init_app_state;
@@ -12,6 +13,11 @@ async fn test_save_message() -> anyhow::Result<()> {
         id: Some(1),
         sender: "aa@qq.com".to_string(),
         recipients: vec!["bb@cc.com".to_string(),"111@cc.com".to_string()],
+        cc: vec!["222@cc.com".to_string()],
+        envelope_from: "aa@qq.com".to_string(),
+        envelope_recipients: vec!["bb@cc.com".to_string(),"111@cc.com".to_string(),"222@cc.com".to_string(),"333@cc.com".to_string()],
+        remote_ip: None,
+        helo: "".to_string(),
         subject: "test111".to_string(),
         created_at: Some("10:11".to_string()),
         attachments: vec![],
@@ -19,12 +25,14 @@ async fn test_save_message() -> anyhow::Result<()> {
         formats: vec![],
         html: Some("test html content".to_string()),
         plain: Some("test html content".to_string()),
-    }).await;
+    }, &StaticResolver::default()).await;
 
     let items = EmailInbox::query_all(&app_state.db).await?;
     println!("items >> {:?}", items);
 
     assert_eq!(items.len(),1);
+    assert_eq!(items[0].cc_mail, "222@cc.com");
+    assert_eq!(items[0].bcc(), vec!["333@cc.com"]);
 
 
     Ok(())
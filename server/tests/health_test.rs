@@ -0,0 +1,38 @@
+use axum_test::TestServer;
+use serde_json::Value;
+
+use play::init_app_state;
+use play::routers;
+use shared::constants::DATA_DIR;
+
+#[tokio::test]
+async fn test_health() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-health-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+    let server = TestServer::new(routers(init_app_state(&play::config::init_config(true), true).await))?;
+
+    let response = server.get("/health").await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.json::<Value>()["status"], "ok");
+
+    // the https server keeps its status next to the certificates.
+    #[cfg(feature = "https")]
+    {
+        std::fs::create_dir_all(data_dir.join("certs"))?;
+        std::fs::write(data_dir.join("certs").join("status.json"), r#"{"mode":"acme","state":"failed","last_error":"rate limited"}"#)?;
+        let response = server.get("/health").await;
+        assert_eq!(response.status_code(), 503);
+        let body = response.json::<Value>();
+        assert_eq!((body["status"].as_str(), body["https"]["last_error"].as_str()), (Some("degraded"), Some("rate limited")));
+
+        // a failed renewal while the served certificate is good for weeks is only reported.
+        let not_after = shared::current_timestamp!() / 1000 + 30 * 24 * 3600;
+        std::fs::write(data_dir.join("certs").join("status.json"), format!(r#"{{"mode":"acme","state":"failed","last_error":"rate limited","not_after":{}}}"#, not_after))?;
+        let response = server.get("/health").await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.json::<Value>()["https"]["last_error"], "rate limited");
+    }
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
@@ -15,7 +15,8 @@ async fn test_root() -> anyhow::Result<()> {
 }
 
 
-#[ignore]
+// needs a real redis when built with the `redis` feature.
+#[cfg_attr(feature = "redis", ignore)]
 #[tokio::test]
 async fn test_redis() -> anyhow::Result<()> {
     let server = TestServer::new(routers(init_app_state(&play::config::init_config(true), true).await))?;
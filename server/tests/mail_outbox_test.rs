@@ -0,0 +1,101 @@
+#![cfg(feature = "mail_server")]
+
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use axum_test::TestServer;
+use serde_json::json;
+
+use mail_server::outbound::{Mailer, SmarthostConfig, SmarthostTls};
+use mail_server::smtp::Builder;
+use play::init_app_state;
+use play::routers;
+use play::service::mail_outbox::deliver_due;
+use play::tables::email_inbox::EmailInbox;
+use play::tables::mail_outbox::{MailOutbox, STATUS_FAILED, STATUS_QUEUED, STATUS_SENT};
+use shared::constants::DATA_DIR;
+
+/// a local smtp server standing in for the smarthost.
+fn start_sink(auth: bool) -> (Mailer, async_channel::Receiver<mail_server::models::message::Message>) {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let (server, rx) = Builder::new().with_auth(auth).bind(SocketAddr::from(([127, 0, 0, 1], port))).build();
+    std::thread::spawn(move || server.serve());
+    std::thread::sleep(Duration::from_millis(100));
+    let mailer = Mailer::new(&SmarthostConfig { host: "127.0.0.1".to_string(), port, tls: SmarthostTls::None, ..Default::default() }).unwrap();
+    (mailer, rx)
+}
+
+#[tokio::test]
+async fn test_queue_and_deliver() -> anyhow::Result<()> {
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let server = TestServer::new(routers(state.clone()))?;
+
+    let response = server.post("/mail-outbox/send").json(&json!({
+        "from": "Me <me@x.com>",
+        "to": ["a@x.com"],
+        "subject": "queued",
+        "text": "hello",
+    })).await;
+    assert_eq!(response.status_code(), 200);
+    let id = response.json::<serde_json::Value>()["id"].as_i64().unwrap();
+
+    // no recipients, rejected before it reaches the queue.
+    let response = server.post("/mail-outbox/send").json(&json!({"from": "me@x.com"})).await;
+    assert_eq!(response.status_code(), 500);
+    assert_eq!(MailOutbox::count_by_status(STATUS_QUEUED, &state.db).await?, 1);
+
+    let (mailer, rx) = start_sink(false);
+    assert_eq!(deliver_due(&state, &mailer).await?, 1);
+    let message = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await??;
+    assert_eq!(message.subject, "queued");
+    assert_eq!(message.envelope_from, "me@x.com");
+
+    let row = &MailOutbox::get_by_id(id, &state.db).await?[0];
+    assert_eq!((row.status.as_str(), row.attempts), (STATUS_SENT, 1));
+    // nothing left to do.
+    assert_eq!(deliver_due(&state, &mailer).await?, 0);
+
+    let page = server.get("/admin/mail-outbox").add_query_params(&[("status", STATUS_SENT)]).await;
+    assert_eq!(page.status_code(), 200);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rejected_mail_bounces() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-outbox-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let server = TestServer::new(routers(state.clone()))?;
+    let response = server.post("/mail-outbox/send").json(&json!({
+        "from": "Me <me@x.com>",
+        "to": ["a@x.com"],
+        "subject": "refused",
+        "text": "hello",
+    })).await;
+    let id = response.json::<serde_json::Value>()["id"].as_i64().unwrap();
+
+    // the sink wants AUTH and gets none : a permanent failure.
+    let (mailer, _rx) = start_sink(true);
+    deliver_due(&state, &mailer).await?;
+    let row = &MailOutbox::get_by_id(id, &state.db).await?[0];
+    assert_eq!(row.status, STATUS_FAILED);
+    assert!(!row.last_error.is_empty());
+
+    let inbox: Vec<EmailInbox> = EmailInbox::query_all(&state.db).await?.into_iter().filter(|m| m.from_mail == "MAILER-DAEMON").collect();
+    assert_eq!(inbox.len(), 1);
+    assert_eq!(inbox[0].from_mail, "MAILER-DAEMON");
+    assert_eq!(inbox[0].to_mail, "me@x.com");
+    assert!(inbox[0].subject.contains("refused"));
+
+    let response = server.get("/mail-outbox/retry").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(MailOutbox::get_by_id(id, &state.db).await?[0].status, STATUS_QUEUED);
+
+    let response = server.get("/mail-outbox/delete").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert!(MailOutbox::get_by_id(id, &state.db).await?.is_empty());
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
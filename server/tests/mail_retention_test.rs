@@ -0,0 +1,49 @@
+use play::config::MailboxRetention;
+use play::init_app_state;
+use play::service::mail_retention::purge;
+use play::service::mail_store::save_email;
+use play::tables::email_inbox::{EmailInbox, InboxFilter};
+use shared::constants::DATA_DIR;
+
+#[tokio::test]
+async fn test_purge_with_archive() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-retention-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+
+    let mut config = play::config::init_config(true);
+    config.retention.archive = true;
+    config.retention.mailboxes = vec![MailboxRetention { mailbox: "ci@x.com".to_string(), max_count: 1, ..Default::default() }];
+    let state = init_app_state(&config, true).await;
+
+    let mut ids = vec![];
+    for subject in ["one", "two", "three"] {
+        let raw = format!("Subject: {}\r\n\r\nbuild log\r\n", subject);
+        ids.push(save_email(&state, EmailInbox { to_mail: "ci@x.com".to_string(), subject: subject.to_string(), ..Default::default() }, raw.as_bytes(), &[]).await?);
+    }
+    assert_eq!(EmailInbox::get_by_id(ids[0], &state.db).await?[0].size, 27);
+    // a mail without its raw file is still purged, archived as json only.
+    std::fs::remove_file(data_dir.join("mails").join(ids[1].to_string()).join("raw.eml"))?;
+
+    let report = purge(&state).await?;
+    assert_eq!(report.deleted, 2);
+    let kept = EmailInbox::search(&InboxFilter { mailbox: "ci@x.com".to_string(), ..Default::default() }, 0, 10, &state.db).await?;
+    assert_eq!(kept.iter().map(|m| m.id).collect::<Vec<_>>(), vec![ids[2]]);
+    // the other mailboxes have no limits.
+    assert!(EmailInbox::count(&InboxFilter::default(), &state.db).await? > 1);
+
+    let archive = report.archive.unwrap();
+    assert!(archive.starts_with(data_dir.join("mail-archive")));
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(&archive)?)?;
+    let mut names: Vec<String> = zip.file_names().map(|n| n.to_string()).collect();
+    names.sort();
+    let mut expected = vec![format!("{}.eml", ids[0]), format!("{}.json", ids[0]), format!("{}.json", ids[1])];
+    expected.sort();
+    assert_eq!(names, expected);
+    let mut eml = String::new();
+    std::io::Read::read_to_string(&mut zip.by_name(&format!("{}.eml", ids[0]))?, &mut eml)?;
+    assert_eq!(eml, "Subject: one\r\n\r\nbuild log\r\n");
+
+    assert_eq!(purge(&state).await?, Default::default());
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use axum_test::TestServer;
+
+use play::init_app_state;
+use play::routers;
+use play::tables::mail_rule::{MailRule, MailRuleHit};
+
+#[tokio::test]
+async fn test_manage_rules() -> anyhow::Result<()> {
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    let server = TestServer::new(routers(state.clone()))?;
+
+    let response = server.post("/mail-rule/add").form(&[
+        ("name", "spam"),
+        ("priority", "1"),
+        ("match_subject", "(?i)viagra"),
+        ("action", "drop"),
+    ]).await;
+    assert_eq!(response.status_code(), 200);
+    let rules = MailRule::query_all(&state.db).await?;
+    assert_eq!(rules.len(), 1);
+    assert!(rules[0].enabled);
+
+    // a broken regex never gets saved.
+    let response = server.post("/mail-rule/add").form(&[("name", "bad"), ("priority", "1"), ("match_subject", "("), ("action", "drop")]).await;
+    assert_eq!(response.status_code(), 500);
+    let response = server.post("/mail-rule/add").form(&[("name", "bad"), ("priority", "1"), ("action", "webhook"), ("action_arg", "not a url")]).await;
+    assert_eq!(response.status_code(), 500);
+    assert_eq!(MailRule::query_all(&state.db).await?.len(), 1);
+
+    let id = rules[0].id;
+    server.get("/mail-rule/toggle").add_query_params(&[("id", id)]).await;
+    assert!(!MailRule::get_by_id(id, &state.db).await?[0].enabled);
+
+    let response = server.get("/mail-rule/list").await;
+    assert_eq!(response.status_code(), 200);
+    let response = server.get("/mail-rule/hits").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+
+    let response = server.get("/mail-rule/delete").add_query_params(&[("id", id)]).await;
+    assert_eq!(response.status_code(), 200);
+    assert!(MailRule::query_all(&state.db).await?.is_empty());
+    assert_eq!(MailRuleHit::count_by_rule(id, &state.db).await?, 0);
+    Ok(())
+}
+
+#[cfg(feature = "mail_server")]
+#[tokio::test]
+async fn test_rules_on_received_mail() -> anyhow::Result<()> {
+    use mail_server::auth::dns::StaticResolver;
+    use mail_server::models::message::{Attachment, Message};
+    use play::handle_email_message;
+    use play::tables::email_inbox::EmailInbox;
+    use shared::constants::DATA_DIR;
+
+    let data_dir = std::env::temp_dir().join(format!("play-rule-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+    let state = init_app_state(&play::config::init_config(true), true).await;
+
+    // a webhook receiver keeping what it was posted.
+    let (tx, mut posted) = tokio::sync::mpsc::unbounded_channel::<serde_json::Value>();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let hook = format!("http://{}/hook", listener.local_addr()?);
+    let receiver = axum::Router::new().route("/hook", axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| async move {
+        tx.send(body).unwrap();
+    }));
+    let server = axum::Server::from_tcp(listener)?.serve(receiver.into_make_service());
+    tokio::spawn(server);
+
+    let mut ids = vec![];
+    for (priority, match_subject, match_attachment_type, action, action_arg) in [
+        (1, "^Invoice", "", "tag", "invoice"),
+        (2, "", "application/pdf", "move", "Billing"),
+        (3, "^Invoice", "", "python", "handled {{email_id}}"),
+        (4, "^Invoice", "", "webhook", hook.as_str()),
+        (5, "(?i)casino", "", "drop", ""),
+    ] {
+        let r = MailRule::insert(&MailRule {
+            name: action.to_string(),
+            enabled: true,
+            priority,
+            match_subject: match_subject.to_string(),
+            match_attachment_type: match_attachment_type.to_string(),
+            action: action.to_string(),
+            action_arg: action_arg.to_string(),
+            ..Default::default()
+        }, &state.db).await?;
+        ids.push(play::get_last_insert_id!(r));
+    }
+
+    let message = |subject: &str| Message {
+        sender: "shop@x.com".to_string(),
+        recipients: vec!["me@x.com".to_string()],
+        envelope_from: "shop@x.com".to_string(),
+        envelope_recipients: vec!["me@x.com".to_string()],
+        subject: subject.to_string(),
+        source: format!("Subject: {}\r\n\r\nhi\r\n", subject).into_bytes(),
+        plain: Some("hi".to_string()),
+        attachments: vec![Attachment { file_type: "application/pdf".to_string(), filename: "a.pdf".to_string(), body: b"%PDF".to_vec(), ..Default::default() }],
+        ..Default::default()
+    };
+
+    handle_email_message(&state, &message("Invoice 42"), &StaticResolver::default()).await;
+    let mail = EmailInbox::query_all(&state.db).await?.into_iter().find(|m| m.subject == "Invoice 42").unwrap();
+    assert_eq!((mail.tags.as_str(), mail.mailbox.as_str()), ("invoice", "billing"));
+
+    let hits = |i: usize| MailRuleHit::query_by_rule(ids[i], 10, &state.db);
+    assert_eq!(hits(0).await?[0].email_id, mail.id);
+    // the fake template engine hands the source back.
+    assert_eq!(hits(2).await?[0].result, "handled {{email_id}}");
+    assert_eq!(hits(3).await?[0].result, "webhook 200 OK");
+    let body = posted.recv().await.unwrap();
+    assert_eq!((body["email_id"].as_i64(), body["plain"].as_str(), body["subject"].as_str()), (Some(mail.id), Some("hi"), Some("Invoice 42")));
+    assert_eq!(body["attachments"][0], serde_json::json!({"filename": "a.pdf", "content_type": "application/pdf", "cid": "", "size": 4}));
+    assert!(hits(4).await?.is_empty());
+
+    handle_email_message(&state, &message("Casino night"), &StaticResolver::default()).await;
+    assert!(!EmailInbox::query_all(&state.db).await?.iter().any(|m| m.subject == "Casino night"));
+    let dropped = hits(4).await?;
+    assert_eq!((dropped.len(), dropped[0].email_id), (1, 0));
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
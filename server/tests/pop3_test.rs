@@ -0,0 +1,62 @@
+#![cfg(feature = "pop3")]
+
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use play::config::MailAccount;
+use play::init_app_state;
+use play::service::mail_store::save_email;
+use play::tables::email_inbox::EmailInbox;
+use shared::constants::DATA_DIR;
+
+#[tokio::test]
+async fn test_pop3_over_the_inbox() -> anyhow::Result<()> {
+    let data_dir = std::env::temp_dir().join(format!("play-pop3-test-{}", std::process::id()));
+    std::env::set_var(DATA_DIR, &data_dir);
+
+    let mut config = play::config::init_config(true);
+    config.mail_server.pop3_port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    config.mail_server.accounts = vec![MailAccount { username: "pop@x.com".to_string(), password: "secret".to_string() }];
+    let state = init_app_state(&config, true).await;
+
+    let raw = "Subject: first\r\n\r\n.hidden dot\r\n";
+    let first = save_email(&state, EmailInbox { to_mail: "pop@x.com".to_string(), subject: "first".to_string(), ..Default::default() }, raw.as_bytes(), &[]).await?;
+    save_email(&state, EmailInbox { to_mail: "pop@x.com".to_string(), subject: "second".to_string(), ..Default::default() }, b"Subject: second\r\n\r\nhi\r\n", &[]).await?;
+    // a mail whose raw file went missing is left out rather than failing the login.
+    let broken = save_email(&state, EmailInbox { to_mail: "pop@x.com".to_string(), subject: "broken".to_string(), ..Default::default() }, b"Subject: broken\r\n\r\nhi\r\n", &[]).await?;
+    std::fs::remove_file(data_dir.join("mails").join(broken.to_string()).join("raw.eml"))?;
+    save_email(&state, EmailInbox { to_mail: "other@x.com".to_string(), subject: "not mine".to_string(), ..Default::default() }, b"Subject: not mine\r\n\r\nhi\r\n", &[]).await?;
+
+    play::service::pop3_setup::start(state.clone());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stream = TcpStream::connect(("127.0.0.1", config.mail_server.pop3_port)).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    assert!(lines.next_line().await?.unwrap().starts_with("+OK"));
+    writer.write_all(b"USER pop@x.com\r\nPASS secret\r\nSTAT\r\nUIDL 1\r\nRETR 1\r\nDELE 1\r\nQUIT\r\n").await?;
+    let mut replies = vec![];
+    while let Some(line) = lines.next_line().await? {
+        replies.push(line);
+    }
+    assert_eq!(replies, vec![
+        "+OK send PASS".to_string(),
+        "+OK 2 messages".to_string(),
+        format!("+OK 2 {}", raw.len() + "Subject: second\r\n\r\nhi\r\n".len()),
+        format!("+OK 1 {}", first),
+        format!("+OK {} octets", raw.len()),
+        "Subject: first".to_string(),
+        "".to_string(),
+        "..hidden dot".to_string(),
+        ".".to_string(),
+        "+OK marked".to_string(),
+        "+OK bye, 1 deleted".to_string(),
+    ]);
+    assert!(EmailInbox::get_by_id(first, &state.db).await?.is_empty());
+    assert!(!data_dir.join("mails").join(first.to_string()).exists());
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
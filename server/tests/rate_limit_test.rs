@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderName, HeaderValue};
+use axum::{Extension, Router};
+use axum_test::TestServer;
+
+use play::config::{Config, RateLimitBy, RateLimitRule};
+use play::init_app_state;
+use play::routers;
+
+fn config(trusted_proxies: &[&str]) -> Config {
+    let mut config = play::config::init_config(true);
+    config.rate_limit = vec![RateLimitRule {
+        path: "/english_card/".to_string(),
+        limit: 2,
+        window_secs: 60,
+        by: RateLimitBy::Ip,
+    }];
+    config.trusted_proxies = trusted_proxies.iter().map(|p| p.to_string()).collect();
+    config
+}
+
+/// the app as seen from a balancer at 10.0.0.1.
+fn behind_balancer(router: Router) -> Router {
+    router.layer(Extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 40000)))))
+}
+
+fn from(ip: &str) -> (HeaderName, HeaderValue) {
+    (HeaderName::from_static("x-forwarded-for"), HeaderValue::from_str(ip).unwrap())
+}
+
+// counters would survive between runs in a real redis.
+#[cfg_attr(feature = "redis", ignore)]
+#[tokio::test]
+async fn test_rate_limit_per_ip() -> anyhow::Result<()> {
+    let server = TestServer::new(behind_balancer(routers(init_app_state(&config(&["10.0.0.0/8"]), true).await)))?;
+
+    for remaining in ["1", "0"] {
+        let (k, v) = from("1.1.1.1");
+        let response = server.get("/english_card/list").add_header(k, v).await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.header("x-ratelimit-remaining"), remaining);
+    }
+
+    let (k, v) = from("1.1.1.1");
+    let response = server.get("/english_card/list").add_header(k, v).await;
+    assert_eq!(response.status_code(), 429);
+    assert!(!response.header("retry-after").is_empty());
+
+    // a made up hop in front of the real one doesn't give a fresh bucket.
+    let (k, v) = from("9.9.9.9, 1.1.1.1");
+    assert_eq!(server.get("/english_card/list").add_header(k, v).await.status_code(), 429);
+
+    // another client and routes without a rule are unaffected.
+    let (k, v) = from("2.2.2.2");
+    assert_eq!(server.get("/english_card/list").add_header(k, v).await.status_code(), 200);
+    let (k, v) = from("1.1.1.1");
+    assert_eq!(server.get("/").add_header(k, v).await.status_code(), 200);
+
+    Ok(())
+}
+
+#[cfg_attr(feature = "redis", ignore)]
+#[tokio::test]
+async fn test_forwarded_for_is_ignored_from_untrusted_peers() -> anyhow::Result<()> {
+    let server = TestServer::new(behind_balancer(routers(init_app_state(&config(&[]), true).await)))?;
+
+    for (i, expected) in [200, 200, 429].into_iter().enumerate() {
+        let (k, v) = from(&format!("1.1.1.{}", i));
+        assert_eq!(server.get("/english_card/list").add_header(k, v).await.status_code(), expected);
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use axum::http::HeaderName;
+use axum_test::TestServer;
+
+use play::init_app_state;
+use play::routers;
+
+#[tokio::test]
+async fn test_cached_export_is_invalidated_by_import() -> anyhow::Result<()> {
+    let server = TestServer::new(routers(init_app_state(&play::config::init_config(true), true).await))?;
+    let export = [("format", "curl"), ("collection", "cached")];
+
+    let response = server.get("/api-entry/export").add_query_params(&export).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.header("x-cache"), "MISS");
+    let etag = response.header("etag");
+    let first = response.text();
+
+    let response = server.get("/api-entry/export").add_query_params(&export).await;
+    assert_eq!(response.header("x-cache"), "HIT");
+    assert_eq!(response.text(), first);
+
+    let response = server.get("/api-entry/export").add_query_params(&export)
+        .add_header(HeaderName::from_static("if-none-match"), etag.clone()).await;
+    assert_eq!(response.status_code(), 304);
+
+    let response = server.post("/api-entry/import").form(&[
+        ("format", "postman"),
+        ("content", include_str!("fixtures/api_entry/requests.postman_collection.json")),
+        ("collection", "cached"),
+    ]).await;
+    assert_eq!(response.status_code(), 200);
+
+    // the import bumped the api_entry tag, so the old etag no longer matches.
+    let response = server.get("/api-entry/export").add_query_params(&export)
+        .add_header(HeaderName::from_static("if-none-match"), etag).await;
+    assert_eq!(response.status_code(), 200);
+    assert_eq!(response.header("x-cache"), "MISS");
+    assert!(response.text().contains("https://httpbin.org/get?page=1"));
+    assert!(!response.header("etag").is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use axum_test::{TestServer, TestServerConfig, Transport};
+use reqwest::Url;
+
+use play::{init_app_state, routers};
+use play::service::event_bus::AppEvent;
+
+fn progress(message: &str) -> AppEvent {
+    AppEvent::UpgradeProgress { message: message.to_string() }
+}
+
+#[tokio::test]
+async fn test_events_resume_with_last_event_id() -> anyhow::Result<()> {
+    let state = init_app_state(&play::config::init_config(true), true).await;
+    state.event_bus.emit(progress("one"));
+    state.event_bus.emit(AppEvent::TodoChanged { id: 1, action: "added".to_string(), title: "x".to_string() });
+    state.event_bus.emit(progress("two"));
+
+    // sse never ends, so go through a real port and read it chunk by chunk.
+    let server = TestServer::new_with_config(routers(state.clone()), TestServerConfig {
+        transport: Some(Transport::HttpRandomPort),
+        ..TestServerConfig::default()
+    })?;
+    let url = Url::parse(&server.server_address().unwrap().to_string())?.join("/events?types=upgrade_progress")?;
+    let mut response = reqwest::Client::new().get(url).header("Last-Event-ID", "1").send().await?;
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+
+    let mut body = String::new();
+    while !body.contains("<p>two</p>") {
+        let chunk = tokio::time::timeout(Duration::from_secs(3), response.chunk()).await??;
+        body.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+    }
+    assert!(body.contains("id:3"));
+    assert!(body.contains("event:upgrade_progress"));
+    assert!(!body.contains("<p>one</p>"));
+    assert!(!body.contains("todo"));
+
+    // live events follow the replay.
+    state.event_bus.emit(progress("three"));
+    while !body.contains("<p>three</p>") {
+        let chunk = tokio::time::timeout(Duration::from_secs(3), response.chunk()).await??;
+        body.push_str(&String::from_utf8_lossy(&chunk.unwrap()));
+    }
+    assert!(body.contains("id:4"));
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+#[cfg(feature = "https")]
+#[tokio::test]
+async fn test_vhosts() -> anyhow::Result<()> {
+    use axum::body::{Body, HttpBody};
+    use axum::http::Request;
+    use axum::Router;
+    use axum::routing::get;
+    use play::config::{HttpsCert, VirtualHost};
+    use shared::constants::DATA_DIR;
+    use tower::ServiceExt;
+
+    let data_dir = std::env::temp_dir().join(format!("play-vhost-test-{}", std::process::id()));
+    std::fs::create_dir_all(data_dir.join("site"))?;
+    std::fs::write(data_dir.join("site").join("index.html"), "<h1>site</h1>")?;
+    std::env::set_var(DATA_DIR, &data_dir);
+
+    let app = Router::new()
+        .route("/api/ping", get(|| async { "pong" }))
+        .route("/admin/index", get(|| async { "admin" }));
+    let router = play::service::vhost::router(&HttpsCert {
+        domains: vec!["api.x.com".to_string(), "www.x.com".to_string(), "x.com".to_string()],
+        vhosts: vec![
+            VirtualHost { domain: "api.x.com".to_string(), routes: vec!["/api/".to_string()], ..Default::default() },
+            VirtualHost { domain: "www.x.com".to_string(), static_root: "site".to_string(), ..Default::default() },
+        ],
+        ..Default::default()
+    }, app)?;
+
+    let get = |host: &str, path: &str| {
+        let request = Request::get(path).header("host", host).body(Body::empty()).unwrap();
+        let router = router.clone();
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            let status = response.status().as_u16();
+            let (mut body, mut bytes) = (response.into_body(), vec![]);
+            while let Some(chunk) = body.data().await {
+                bytes.extend_from_slice(&chunk.unwrap());
+            }
+            (status, String::from_utf8(bytes).unwrap())
+        }
+    };
+    assert_eq!(get("api.x.com", "/api/ping").await, (200, "pong".to_string()));
+    assert_eq!(get("api.x.com:443", "/admin/index").await.0, 404);
+    assert_eq!(get("www.x.com", "/index.html").await, (200, "<h1>site</h1>".to_string()));
+    assert_eq!(get("www.x.com", "/api/ping").await.0, 404);
+    assert_eq!(get("x.com", "/admin/index").await, (200, "admin".to_string()));
+
+    std::fs::remove_dir_all(&data_dir)?;
+    Ok(())
+}